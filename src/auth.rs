@@ -1,37 +1,46 @@
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use chrono::{DateTime, Utc};
 use jacquard::{
-    CowStr, IntoStatic,
+    AuthorizationToken, CowStr, IntoStatic,
     client::{
-        AgentSession, AtpSession, FileAuthStore, SessionStore, SessionStoreError,
+        AgentSession, AtpSession, FileAuthStore, MemorySessionStore, SessionStore,
+        SessionStoreError,
         credential_session::{CredentialSession, SessionKey},
-        token::StoredSession,
+        token::{OAuthSession as StoredOAuthSession, StoredSession},
     },
-    error::{ClientError, XrpcResult},
+    error::{AuthError, ClientError, XrpcResult},
     identity::JacquardResolver,
     prelude::{HttpClient, IdentityResolver},
     types::{did::Did, string::Handle},
+    url::Url,
     xrpc::{XrpcClient, XrpcRequest, XrpcResponse},
 };
-use jacquard_identity::PublicResolver;
+use jacquard_api::com_atproto::server::get_session::GetSession;
+use jacquard_identity::resolver::{IdentityError, IdentityErrorKind};
 use jacquard_oauth::{
     atproto::AtprotoClientMetadata,
     authstore::ClientAuthStore,
     client::{OAuthClient, OAuthSession},
-    loopback::LoopbackConfig,
+    error::{CallbackError, OAuthError},
+    loopback::{LoopbackConfig, LoopbackPort},
     session::{ClientData, ClientSessionData},
 };
 use keyring::Entry;
-use owo_colors::OwoColorize;
 use serde::{Deserialize, Serialize, de::DeserializeOwned};
 use std::{
     fmt::Display,
     hash::Hash,
     path::{Path, PathBuf},
     sync::Arc,
+    time::Duration,
 };
 
 use crate::{
     StoreMethod,
-    error::{MapErrExt, OnyxError},
+    bundle::{BundledCredentials, SessionBundle},
+    error::{MapErrExt, OnyxError, is_session_expired},
+    http::HttpOptions,
+    identity::{self, HandleCache},
 };
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -43,6 +52,29 @@ pub struct StoredPasswordSession {
     handle: String,
 }
 
+impl StoredPasswordSession {
+    pub fn new(session: AtpSession, session_id: &str) -> Self {
+        Self {
+            access_jwt: session.access_jwt.to_string(),
+            refresh_jwt: session.refresh_jwt.to_string(),
+            did: session.did.to_string(),
+            session_id: session_id.to_string(),
+            handle: session.handle.to_string(),
+        }
+    }
+}
+
+impl From<StoredPasswordSession> for AtpSession {
+    fn from(stored: StoredPasswordSession) -> Self {
+        AtpSession {
+            access_jwt: stored.access_jwt.into(),
+            refresh_jwt: stored.refresh_jwt.into(),
+            did: stored.did.into(),
+            handle: stored.handle.into(),
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct KeyringTokenStore {
     pub service: String,
@@ -52,6 +84,27 @@ impl KeyringTokenStore {
     pub fn new(service: String) -> Self {
         Self { service }
     }
+
+    /// Check the platform keyring can store credentials, by writing and
+    /// removing a probe entry
+    pub fn check_available(&self) -> Result<(), OnyxError> {
+        let probe = || -> keyring::Result<()> {
+            let entry = Entry::new(&self.service, "onyx-keyring-probe")?;
+            entry.set_password("probe")?;
+            entry.delete_credential()
+        };
+
+        match probe() {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(OnyxError::Other(
+                format!(
+                    "the system keyring is unavailable ({}), use `--store file` to store credentials in the config directory instead",
+                    e
+                )
+                .into(),
+            )),
+        }
+    }
 }
 
 impl<K: Send + Sync + Hash + Eq + Display, T: Send + Sync + Clone + Serialize + DeserializeOwned>
@@ -83,29 +136,14 @@ impl<K: Send + Sync + Hash + Eq + Display, T: Send + Sync + Clone + Serialize +
 impl SessionStore<SessionKey, AtpSession> for KeyringAuthStore {
     async fn get(&self, key: &SessionKey) -> Option<AtpSession> {
         let key_str = format!("{}_{}", key.0, key.1);
-        if let Some(stored) =
-            SessionStore::<String, StoredPasswordSession>::get(&self.0, &key_str).await
-        {
-            Some(AtpSession {
-                access_jwt: stored.access_jwt.into(),
-                refresh_jwt: stored.refresh_jwt.into(),
-                did: stored.did.into(),
-                handle: stored.handle.into(),
-            })
-        } else {
-            None
-        }
+        SessionStore::<String, StoredPasswordSession>::get(&self.0, &key_str)
+            .await
+            .map(AtpSession::from)
     }
 
     async fn set(&self, key: SessionKey, session: AtpSession) -> Result<(), SessionStoreError> {
         let key_str = format!("{}_{}", key.0, key.1);
-        let stored = StoredPasswordSession {
-            access_jwt: session.access_jwt.to_string(),
-            refresh_jwt: session.refresh_jwt.to_string(),
-            did: session.did.to_string(),
-            session_id: key.1.to_string(),
-            handle: session.handle.to_string(),
-        };
+        let stored = StoredPasswordSession::new(session, &key.1);
         self.0.set(key_str, stored).await
     }
 
@@ -226,16 +264,91 @@ pub struct AuthSession {
     pub session_id: String,
     pub store: StoreMethod,
     pub auth: AuthMethod,
+    /// PDS given at login, used instead of the one in the DID document
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pds: Option<String>,
+}
+
+/// Settings for the local server that receives the OAuth callback
+#[derive(Debug, Clone, Default)]
+pub struct CallbackOptions {
+    pub port: Option<u16>,
+    pub timeout: Option<Duration>,
+}
+
+impl CallbackOptions {
+    fn loopback(&self) -> LoopbackConfig {
+        let mut config = LoopbackConfig::default();
+
+        if let Some(port) = self.port {
+            config.port = LoopbackPort::Fixed(port);
+        }
+
+        if let Some(timeout) = self.timeout {
+            config.timeout_ms = timeout.as_millis() as u64;
+        }
+
+        config
+    }
+
+    fn map_timeout(&self, err: OAuthError) -> OnyxError {
+        match err {
+            OAuthError::Callback(CallbackError::Timeout) => OnyxError::Auth(format!(
+                "no response from the browser after {} seconds, login aborted",
+                self.loopback().timeout_ms / 1000
+            )),
+            err => err.into(),
+        }
+    }
+}
+
+impl AuthSession {
+    /// The handle to show for the account, `preferred` if the account has
+    /// it, otherwise the first one in its DID document
+    pub fn handle(&self, preferred: Option<&str>) -> Option<&str> {
+        preferred
+            .and_then(|p| self.handles.iter().find(|h| h.eq_ignore_ascii_case(p)))
+            .or(self.handles.first())
+            .map(String::as_str)
+    }
+
+    pub fn pds_url(&self) -> Result<Option<Url>, OnyxError> {
+        self.pds
+            .as_deref()
+            .map(Url::parse)
+            .transpose()
+            .map_err(|e| OnyxError::Parse(format!("invalid stored PDS URL: {}", e)))
+    }
 }
 
 pub struct AuthSessionStore {
     pub config_dir: PathBuf,
 }
 
+/// Explain a failed write to the config directory, pointing at
+/// `ONYX_CONFIG_DIR` when the directory can't be written to at all
+fn config_dir_error(config_dir: &Path, err: std::io::Error) -> OnyxError {
+    use std::io::ErrorKind;
+
+    if matches!(
+        err.kind(),
+        ErrorKind::PermissionDenied | ErrorKind::ReadOnlyFilesystem
+    ) {
+        OnyxError::Io(format!(
+            "config directory {} isn't writable ({}), set {} to a writable directory",
+            config_dir.display(),
+            err,
+            crate::CONFIG_DIR_VAR
+        ))
+    } else {
+        err.into()
+    }
+}
+
 impl AuthSessionStore {
     fn try_new(config_dir: &Path) -> Result<Self, OnyxError> {
         if !config_dir.exists() {
-            std::fs::create_dir_all(config_dir)?;
+            std::fs::create_dir_all(config_dir).map_err(|e| config_dir_error(config_dir, e))?;
         }
 
         Ok(Self {
@@ -257,7 +370,8 @@ impl AuthSessionStore {
     fn set_session(&self, session: &AuthSession) -> Result<(), OnyxError> {
         let session_str = serde_json::to_string(session)?;
         let session_path = self.config_dir.join("session.json");
-        std::fs::write(&session_path, &session_str)?;
+        std::fs::write(&session_path, &session_str)
+            .map_err(|e| config_dir_error(&self.config_dir, e))?;
 
         // set file perms on unix for security (just in case)
         #[cfg(unix)]
@@ -272,6 +386,35 @@ impl AuthSessionStore {
         Ok(())
     }
 
+    /// Sessions kept aside when logging in to another account, in the
+    /// order they were kept
+    fn get_kept_sessions(&self) -> Result<Vec<AuthSession>, OnyxError> {
+        let kept_path = self.config_dir.join("accounts.json");
+        if !kept_path.exists() {
+            return Ok(Vec::new());
+        }
+
+        let kept_str = std::fs::read_to_string(kept_path)?;
+        Ok(serde_json::from_str(&kept_str)?)
+    }
+
+    fn set_kept_sessions(&self, sessions: &[AuthSession]) -> Result<(), OnyxError> {
+        let kept_str = serde_json::to_string(sessions)?;
+        let kept_path = self.config_dir.join("accounts.json");
+        std::fs::write(&kept_path, &kept_str).map_err(|e| config_dir_error(&self.config_dir, e))?;
+
+        #[cfg(unix)]
+        {
+            use std::fs;
+            use std::os::unix::fs::PermissionsExt;
+
+            let perms = fs::Permissions::from_mode(0o0600); // -rw-------
+            fs::set_permissions(&kept_path, perms)?;
+        }
+
+        Ok(())
+    }
+
     fn delete_session(&self) -> Result<(), OnyxError> {
         let session_path = self.config_dir.join("session.json");
         if !session_path.exists() {
@@ -289,6 +432,18 @@ pub enum GenericSession {
     FileOAuth(OAuthSession<JacquardResolver, FileAuthStore>),
     KeyringPassword(CredentialSession<KeyringAuthStore, JacquardResolver>),
     FilePassword(CredentialSession<FileAuthStore, JacquardResolver>),
+    /// App password session held only for the current process
+    MemoryPassword(CredentialSession<MemorySessionStore<SessionKey, AtpSession>, JacquardResolver>),
+}
+
+/// Map a request that failed to go through a session, these come from the
+/// HTTP client underneath so they're network failures, a rejected session
+/// comes back as a response instead
+fn send_error(err: IdentityError) -> OnyxError {
+    match err.kind() {
+        IdentityErrorKind::Timeout => OnyxError::Network(err.to_string()),
+        _ => err.into(),
+    }
 }
 
 impl HttpClient for GenericSession {
@@ -299,22 +454,21 @@ impl HttpClient for GenericSession {
         request: http::Request<Vec<u8>>,
     ) -> core::result::Result<http::Response<Vec<u8>>, Self::Error> {
         match self {
-            GenericSession::KeyringOAuth(session) => session
-                .send_http(request)
-                .await
-                .map_err(|e| OnyxError::Auth(e.to_string())),
-            GenericSession::FileOAuth(session) => session
-                .send_http(request)
-                .await
-                .map_err(|e| OnyxError::Auth(e.to_string())),
-            GenericSession::KeyringPassword(session) => session
-                .send_http(request)
-                .await
-                .map_err(|e| OnyxError::Auth(e.to_string())),
-            GenericSession::FilePassword(session) => session
-                .send_http(request)
-                .await
-                .map_err(|e| OnyxError::Auth(e.to_string())),
+            GenericSession::KeyringOAuth(session) => {
+                session.send_http(request).await.map_err(send_error)
+            }
+            GenericSession::FileOAuth(session) => {
+                session.send_http(request).await.map_err(send_error)
+            }
+            GenericSession::KeyringPassword(session) => {
+                session.send_http(request).await.map_err(send_error)
+            }
+            GenericSession::FilePassword(session) => {
+                session.send_http(request).await.map_err(send_error)
+            }
+            GenericSession::MemoryPassword(session) => {
+                session.send_http(request).await.map_err(send_error)
+            }
         }
     }
 }
@@ -326,6 +480,7 @@ impl XrpcClient for GenericSession {
             GenericSession::FileOAuth(session) => session.base_uri().await,
             GenericSession::KeyringPassword(session) => session.base_uri().await,
             GenericSession::FilePassword(session) => session.base_uri().await,
+            GenericSession::MemoryPassword(session) => session.base_uri().await,
         }
     }
 
@@ -335,6 +490,7 @@ impl XrpcClient for GenericSession {
             GenericSession::FileOAuth(session) => session.opts().await,
             GenericSession::KeyringPassword(session) => session.opts().await,
             GenericSession::FilePassword(session) => session.opts().await,
+            GenericSession::MemoryPassword(session) => session.opts().await,
         }
     }
 
@@ -344,6 +500,7 @@ impl XrpcClient for GenericSession {
             GenericSession::FileOAuth(session) => session.set_opts(opts).await,
             GenericSession::KeyringPassword(session) => session.set_opts(opts).await,
             GenericSession::FilePassword(session) => session.set_opts(opts).await,
+            GenericSession::MemoryPassword(session) => session.set_opts(opts).await,
         }
     }
 
@@ -353,6 +510,7 @@ impl XrpcClient for GenericSession {
             GenericSession::FileOAuth(session) => session.set_base_uri(url).await,
             GenericSession::KeyringPassword(session) => session.set_base_uri(url).await,
             GenericSession::FilePassword(session) => session.set_base_uri(url).await,
+            GenericSession::MemoryPassword(session) => session.set_base_uri(url).await,
         }
     }
 
@@ -366,6 +524,7 @@ impl XrpcClient for GenericSession {
             GenericSession::FileOAuth(session) => session.send::<R>(request).await,
             GenericSession::KeyringPassword(session) => session.send::<R>(request).await,
             GenericSession::FilePassword(session) => session.send::<R>(request).await,
+            GenericSession::MemoryPassword(session) => session.send::<R>(request).await,
         }
     }
 
@@ -390,6 +549,9 @@ impl XrpcClient for GenericSession {
             GenericSession::FilePassword(session) => {
                 session.send_with_opts::<R>(request, opts).await
             }
+            GenericSession::MemoryPassword(session) => {
+                session.send_with_opts::<R>(request, opts).await
+            }
         }
     }
 }
@@ -401,6 +563,7 @@ impl IdentityResolver for GenericSession {
             GenericSession::FileOAuth(session) => session.options(),
             GenericSession::KeyringPassword(session) => session.options(),
             GenericSession::FilePassword(session) => session.options(),
+            GenericSession::MemoryPassword(session) => session.options(),
         }
     }
 
@@ -416,6 +579,7 @@ impl IdentityResolver for GenericSession {
             GenericSession::FileOAuth(session) => session.resolve_handle(handle).await,
             GenericSession::KeyringPassword(session) => session.resolve_handle(handle).await,
             GenericSession::FilePassword(session) => session.resolve_handle(handle).await,
+            GenericSession::MemoryPassword(session) => session.resolve_handle(handle).await,
         }
     }
 
@@ -431,6 +595,7 @@ impl IdentityResolver for GenericSession {
             GenericSession::FileOAuth(session) => session.resolve_did_doc(did).await,
             GenericSession::KeyringPassword(session) => session.resolve_did_doc(did).await,
             GenericSession::FilePassword(session) => session.resolve_did_doc(did).await,
+            GenericSession::MemoryPassword(session) => session.resolve_did_doc(did).await,
         }
     }
 }
@@ -442,6 +607,7 @@ impl AgentSession for GenericSession {
             GenericSession::FileOAuth(_) => jacquard::client::AgentKind::OAuth,
             GenericSession::KeyringPassword(_) => jacquard::client::AgentKind::AppPassword,
             GenericSession::FilePassword(_) => jacquard::client::AgentKind::AppPassword,
+            GenericSession::MemoryPassword(_) => jacquard::client::AgentKind::AppPassword,
         }
     }
 
@@ -461,6 +627,9 @@ impl AgentSession for GenericSession {
             GenericSession::FilePassword(session) => {
                 session.session_info().await.map(|key| (key.0, Some(key.1)))
             }
+            GenericSession::MemoryPassword(session) => {
+                session.session_info().await.map(|key| (key.0, Some(key.1)))
+            }
         }
     }
 
@@ -470,6 +639,7 @@ impl AgentSession for GenericSession {
             GenericSession::FileOAuth(session) => session.endpoint().await,
             GenericSession::KeyringPassword(session) => session.endpoint().await,
             GenericSession::FilePassword(session) => session.endpoint().await,
+            GenericSession::MemoryPassword(session) => session.endpoint().await,
         }
     }
 
@@ -479,6 +649,7 @@ impl AgentSession for GenericSession {
             GenericSession::FileOAuth(session) => session.set_options(opts).await,
             GenericSession::KeyringPassword(session) => session.set_options(opts).await,
             GenericSession::FilePassword(session) => session.set_options(opts).await,
+            GenericSession::MemoryPassword(session) => session.set_options(opts).await,
         }
     }
 
@@ -488,57 +659,88 @@ impl AgentSession for GenericSession {
                 .refresh()
                 .await
                 .map(|t| t.into_static())
-                .map_err(|e| ClientError::transport(e).with_context("OAuth token refresh failed")),
+                .map_err(oauth_refresh_error),
 
             GenericSession::FileOAuth(session) => session
                 .refresh()
                 .await
                 .map(|t| t.into_static())
-                .map_err(|e| ClientError::transport(e).with_context("OAuth token refresh failed")),
+                .map_err(oauth_refresh_error),
             GenericSession::KeyringPassword(session) => session
                 .refresh()
                 .await
                 .map(|t| t.into_static())
-                .map_err(|e| {
-                    ClientError::transport(e).with_context("App password token refresh failed")
-                }),
+                .map_err(|e| e.with_context("App password token refresh failed")),
             GenericSession::FilePassword(session) => session
                 .refresh()
                 .await
                 .map(|t| t.into_static())
-                .map_err(|e| {
-                    ClientError::transport(e).with_context("App password token refresh failed")
-                }),
+                .map_err(|e| e.with_context("App password token refresh failed")),
+            GenericSession::MemoryPassword(session) => session
+                .refresh()
+                .await
+                .map(|t| t.into_static())
+                .map_err(|e| e.with_context("App password token refresh failed")),
         }
     }
 }
 
+/// Report a dead OAuth session as an auth failure so callers can offer to log
+/// in again, anything else is treated as a transient transport error
+fn oauth_refresh_error(err: OAuthError) -> ClientError {
+    if is_session_expired(&err) {
+        ClientError::auth(AuthError::RefreshFailed).with_context(err.to_string())
+    } else {
+        ClientError::transport(err).with_context("OAuth token refresh failed")
+    }
+}
+
+/// Read the `exp` claim of a JWT access token, `None` for opaque tokens
+pub fn token_expiry(token: &AuthorizationToken<'_>) -> Option<DateTime<Utc>> {
+    let (AuthorizationToken::Bearer(token) | AuthorizationToken::Dpop(token)) = token;
+
+    let payload = token.split('.').nth(1)?;
+    let payload = URL_SAFE_NO_PAD.decode(payload.trim_end_matches('=')).ok()?;
+    let claims: serde_json::Value = serde_json::from_slice(&payload).ok()?;
+
+    DateTime::from_timestamp(claims.get("exp")?.as_i64()?, 0)
+}
+
 pub struct Authenticator {
     pub service: String,
     pub config_dir: PathBuf,
 
+    http: HttpOptions,
     resolver: JacquardResolver,
+    handle_cache: HandleCache,
     auth_store: AuthSessionStore,
 }
 
 impl Authenticator {
-    pub fn try_new(service: &str, config_dir: &Path) -> Result<Self, OnyxError> {
+    pub fn try_new(
+        service: &str,
+        config_dir: &Path,
+        http: HttpOptions,
+        handle_cache: HandleCache,
+    ) -> Result<Self, OnyxError> {
         Ok(Self {
             service: service.to_owned(),
             config_dir: config_dir.to_owned(),
-            resolver: PublicResolver::default(),
+            resolver: http.build_resolver()?,
+            http,
+            handle_cache,
             auth_store: AuthSessionStore::try_new(config_dir)?,
         })
     }
 
-    async fn resolve_did(&self, ident: &str) -> Result<Did<'_>, OnyxError> {
-        if let Ok(did) = ident.parse() {
-            return Ok(did);
-        }
-
-        let handle = Handle::new(ident)?;
-        let did = self.resolver.resolve_handle(&handle).await?;
-        Ok(did)
+    async fn resolve_did(&self, ident: &str) -> Result<Did<'static>, OnyxError> {
+        identity::resolve_did(
+            &self.resolver,
+            &self.handle_cache,
+            ident,
+            self.http.resolve_retries,
+        )
+        .await
     }
 
     async fn resolve_handles(&self, ident: &str) -> Result<Vec<Handle<'_>>, OnyxError> {
@@ -557,24 +759,82 @@ impl Authenticator {
         ident: &str,
         store: StoreMethod,
         password: Option<String>,
+        pds: Option<Url>,
+        callback: CallbackOptions,
     ) -> Result<(), OnyxError> {
+        // fail before touching the existing session if the new one can't be stored
+        if store == StoreMethod::Keyring {
+            KeyringTokenStore::new(self.service.clone()).check_available()?;
+        }
+
         // ensure previous creds are cleared
         let _ = self.logout().await;
 
         match password {
-            Some(pass) => self.login_app_password(ident, store, pass).await,
-            None => self.login_oauth(ident, store).await,
+            Some(pass) => self.login_app_password(ident, store, pass, pds).await,
+            None => self.login_oauth(ident, store, pds, callback).await,
         }
     }
 
+    /// Log in without prompting or opening a browser, for CI and other
+    /// unattended setups, which rules out OAuth
+    pub async fn login_non_interactive(
+        &self,
+        ident: &str,
+        store: StoreMethod,
+        password: Option<String>,
+        pds: Option<Url>,
+    ) -> Result<(), OnyxError> {
+        let Some(password) = password else {
+            return Err(OnyxError::Auth(
+                "non-interactive login needs an app password as OAuth requires a browser, set `ONYX_APP_PASSWORD` or use `--password`".to_string(),
+            ));
+        };
+
+        self.login(
+            ident,
+            store,
+            Some(password),
+            pds,
+            CallbackOptions::default(),
+        )
+        .await
+    }
+
+    /// Log in with an app password without storing anything, the session
+    /// only lives as long as the returned value
+    pub async fn login_ephemeral(
+        &self,
+        ident: &str,
+        password: &str,
+        pds: Option<Url>,
+    ) -> Result<GenericSession, OnyxError> {
+        let store = MemorySessionStore::default();
+        let session =
+            CredentialSession::new(Arc::new(store), Arc::new(self.http.build_resolver()?));
+        session
+            .login(
+                CowStr::Borrowed(ident),
+                CowStr::Borrowed(password),
+                None,
+                None,
+                None,
+                pds,
+            )
+            .await?;
+
+        Ok(GenericSession::MemoryPassword(session))
+    }
+
     async fn login_app_password(
         &self,
         ident: &str,
         store_method: StoreMethod,
         password: String,
+        pds: Option<Url>,
     ) -> Result<(), OnyxError> {
         let session_id = "session";
-        let resolver = PublicResolver::default();
+        let resolver = self.http.build_resolver()?;
 
         let handles = self
             .resolve_handles(ident)
@@ -596,7 +856,7 @@ impl Authenticator {
                     Some(CowStr::Borrowed(session_id)),
                     None,
                     None,
-                    None,
+                    pds.clone(),
                 )
                 .await?;
             let auth_session = AuthSession {
@@ -605,6 +865,7 @@ impl Authenticator {
                 session_id: session_id.to_string(),
                 store: store_method,
                 auth: AuthMethod::AppPassword,
+                pds: pds.as_ref().map(|u| u.to_string()),
             };
             self.auth_store.set_session(&auth_session)?;
         } else if store_method == StoreMethod::File {
@@ -617,7 +878,7 @@ impl Authenticator {
                     Some(CowStr::Borrowed(session_id)),
                     None,
                     None,
-                    None,
+                    pds.clone(),
                 )
                 .await?;
             let auth_session = AuthSession {
@@ -626,6 +887,7 @@ impl Authenticator {
                 session_id: session_id.to_string(),
                 store: store_method,
                 auth: AuthMethod::AppPassword,
+                pds: pds.as_ref().map(|u| u.to_string()),
             };
             self.auth_store.set_session(&auth_session)?;
         }
@@ -633,9 +895,22 @@ impl Authenticator {
         Ok(())
     }
 
-    async fn login_oauth(&self, ident: &str, store_method: StoreMethod) -> Result<(), OnyxError> {
+    async fn login_oauth(
+        &self,
+        ident: &str,
+        store_method: StoreMethod,
+        pds: Option<Url>,
+        callback: CallbackOptions,
+    ) -> Result<(), OnyxError> {
         let did = self.resolve_did(ident).await?;
 
+        // starting from the PDS URL makes the flow use that server's
+        // authorization server, rather than the one the DID document points to
+        let input = match &pds {
+            Some(pds) => pds.to_string(),
+            None => did.to_string(),
+        };
+
         let client_data = ClientData {
             keyset: None,
             config: AtprotoClientMetadata::default_localhost(),
@@ -653,10 +928,12 @@ impl Authenticator {
         // but stores aren't dyn-compatible, and I couldn't be bothered
         if store_method == StoreMethod::Keyring {
             let store = KeyringAuthStore::new(self.service.clone());
-            let oauth = OAuthClient::new(store, client_data);
+            let oauth =
+                OAuthClient::new_from_resolver(store, self.http.build_resolver()?, client_data);
             let session = oauth
-                .login_with_local_server(&did, Default::default(), LoopbackConfig::default())
-                .await?;
+                .login_with_local_server(&input, Default::default(), callback.loopback())
+                .await
+                .map_err(|e| callback.map_timeout(e))?;
 
             let session_id = session.data.try_read()?.session_id.clone();
             let auth_session = AuthSession {
@@ -665,14 +942,17 @@ impl Authenticator {
                 session_id: session_id.to_string(),
                 store: store_method,
                 auth: AuthMethod::OAuth,
+                pds: pds.as_ref().map(|u| u.to_string()),
             };
             self.auth_store.set_session(&auth_session)?;
         } else if store_method == StoreMethod::File {
             let store = FileAuthStore::new(self.get_file_store());
-            let oauth = OAuthClient::new(store, client_data);
+            let oauth =
+                OAuthClient::new_from_resolver(store, self.http.build_resolver()?, client_data);
             let session = oauth
-                .login_with_local_server(&did, Default::default(), LoopbackConfig::default())
-                .await?;
+                .login_with_local_server(&input, Default::default(), callback.loopback())
+                .await
+                .map_err(|e| callback.map_timeout(e))?;
 
             let session_id = session.data.try_read()?.session_id.clone();
             let auth_session = AuthSession {
@@ -681,6 +961,7 @@ impl Authenticator {
                 session_id: session_id.to_string(),
                 store: store_method,
                 auth: AuthMethod::OAuth,
+                pds: pds.as_ref().map(|u| u.to_string()),
             };
             self.auth_store.set_session(&auth_session)?;
         }
@@ -707,7 +988,7 @@ impl Authenticator {
         auth_session: AuthSession,
     ) -> Result<GenericSession, OnyxError> {
         let did = Did::new(&auth_session.did)?;
-        let resolver = PublicResolver::default();
+        let resolver = self.http.build_resolver()?;
 
         match auth_session.store {
             StoreMethod::Keyring => {
@@ -716,6 +997,9 @@ impl Authenticator {
                 session
                     .restore(did, CowStr::Borrowed(&auth_session.session_id))
                     .await?;
+                if let Some(pds) = auth_session.pds_url()? {
+                    session.set_endpoint(pds).await;
+                }
                 Ok(GenericSession::KeyringPassword(session))
             }
             StoreMethod::File => {
@@ -724,6 +1008,9 @@ impl Authenticator {
                 session
                     .restore(did, CowStr::Borrowed(&auth_session.session_id))
                     .await?;
+                if let Some(pds) = auth_session.pds_url()? {
+                    session.set_endpoint(pds).await;
+                }
                 Ok(GenericSession::FilePassword(session))
             }
         }
@@ -740,13 +1027,15 @@ impl Authenticator {
         match session.store {
             StoreMethod::Keyring => {
                 let store = KeyringAuthStore::new(self.service.clone());
-                let oauth = OAuthClient::new(store, client_data);
+                let oauth =
+                    OAuthClient::new_from_resolver(store, self.http.build_resolver()?, client_data);
                 let session = oauth.restore(&did, &session.session_id).await?;
                 Ok(GenericSession::KeyringOAuth(session))
             }
             StoreMethod::File => {
                 let store = FileAuthStore::new(self.get_file_store());
-                let oauth = OAuthClient::new(store, client_data);
+                let oauth =
+                    OAuthClient::new_from_resolver(store, self.http.build_resolver()?, client_data);
                 let session = oauth.restore(&did, &session.session_id).await?;
                 Ok(GenericSession::FileOAuth(session))
             }
@@ -761,8 +1050,12 @@ impl Authenticator {
             }
         };
 
-        println!("{}", format!("logging out {}", &session.did).dimmed());
+        self.delete_credentials(&session).await?;
+        self.auth_store.delete_session()
+    }
 
+    /// Delete the stored credentials of `session`, leaving the session file
+    async fn delete_credentials(&self, session: &AuthSession) -> Result<(), OnyxError> {
         let did = Did::new(&session.did)?;
 
         if session.store == StoreMethod::Keyring {
@@ -773,9 +1066,236 @@ impl Authenticator {
             store.delete_session(&did, &session.session_id).await?;
         }
 
+        Ok(())
+    }
+
+    /// Read the current session's access token from the store, without
+    /// contacting the server, `None` if no credentials are stored
+    pub async fn stored_access_token(
+        &self,
+    ) -> Result<Option<AuthorizationToken<'static>>, OnyxError> {
+        let session = self.get_session_info()?;
+        let did = Did::new(&session.did)?.into_static();
+        let session_id = session.session_id.as_str();
+
+        let oauth_token = |data: ClientSessionData| {
+            AuthorizationToken::Dpop(data.token_set.access_token.into_static())
+        };
+        let password_token =
+            |data: AtpSession| AuthorizationToken::Bearer(data.access_jwt.into_static());
+
+        Ok(match (session.store, session.auth) {
+            (StoreMethod::Keyring, AuthMethod::OAuth) => {
+                let store = KeyringAuthStore::new(self.service.clone());
+                store
+                    .get_session(&did, session_id)
+                    .await
+                    .ok()
+                    .flatten()
+                    .map(oauth_token)
+            }
+            (StoreMethod::File, AuthMethod::OAuth) => {
+                let store = FileAuthStore::new(self.get_file_store());
+                store
+                    .get_session(&did, session_id)
+                    .await
+                    .ok()
+                    .flatten()
+                    .map(oauth_token)
+            }
+            (StoreMethod::Keyring, AuthMethod::AppPassword) => {
+                let store = KeyringAuthStore::new(self.service.clone());
+                let key = SessionKey(did, CowStr::Owned(session_id.into()));
+                SessionStore::<SessionKey, AtpSession>::get(&store, &key)
+                    .await
+                    .map(password_token)
+            }
+            (StoreMethod::File, AuthMethod::AppPassword) => {
+                let store = FileAuthStore::new(self.get_file_store());
+                let key = SessionKey(did, CowStr::Owned(session_id.into()));
+                SessionStore::<SessionKey, AtpSession>::get(&store, &key)
+                    .await
+                    .map(password_token)
+            }
+        })
+    }
+
+    /// Restore the session and ask the server to confirm it with
+    /// `com.atproto.server.getSession`
+    pub async fn confirm_session(&self) -> Result<(), OnyxError> {
+        let session = self.restore().await?;
+
+        session
+            .send(GetSession)
+            .await?
+            .into_output()
+            .map_err(|e| OnyxError::Auth(e.to_string()))?;
+
+        Ok(())
+    }
+
+    /// Collect the current session and its stored credentials into a bundle
+    /// that can be moved to another machine
+    pub async fn export_session(&self) -> Result<SessionBundle, OnyxError> {
+        let session = self.get_session_info()?;
+        let did = Did::new(&session.did)?.into_static();
+        let session_id = session.session_id.as_str();
+        let missing =
+            || OnyxError::Auth("no stored credentials found for the current session".to_string());
+
+        let credentials = match (&session.store, &session.auth) {
+            (StoreMethod::Keyring, AuthMethod::OAuth) => {
+                let store = KeyringAuthStore::new(self.service.clone());
+                let data = store
+                    .get_session(&did, session_id)
+                    .await?
+                    .ok_or_else(missing)?;
+                BundledCredentials::OAuth(Box::new(StoredOAuthSession::from(data)))
+            }
+            (StoreMethod::File, AuthMethod::OAuth) => {
+                let store = FileAuthStore::new(self.get_file_store());
+                let data = store
+                    .get_session(&did, session_id)
+                    .await?
+                    .ok_or_else(missing)?;
+                BundledCredentials::OAuth(Box::new(StoredOAuthSession::from(data)))
+            }
+            (StoreMethod::Keyring, AuthMethod::AppPassword) => {
+                let store = KeyringAuthStore::new(self.service.clone());
+                let key = SessionKey(did, CowStr::Owned(session_id.into()));
+                let data = SessionStore::<SessionKey, AtpSession>::get(&store, &key)
+                    .await
+                    .ok_or_else(missing)?;
+                BundledCredentials::AppPassword(StoredPasswordSession::new(data, session_id))
+            }
+            (StoreMethod::File, AuthMethod::AppPassword) => {
+                let store = FileAuthStore::new(self.get_file_store());
+                let key = SessionKey(did, CowStr::Owned(session_id.into()));
+                let data = SessionStore::<SessionKey, AtpSession>::get(&store, &key)
+                    .await
+                    .ok_or_else(missing)?;
+                BundledCredentials::AppPassword(StoredPasswordSession::new(data, session_id))
+            }
+        };
+
+        Ok(SessionBundle {
+            session,
+            credentials,
+        })
+    }
+
+    /// Replace the current session with one from a bundle, writing its
+    /// credentials to `store`
+    pub async fn import_session(
+        &self,
+        bundle: SessionBundle,
+        store: StoreMethod,
+    ) -> Result<AuthSession, OnyxError> {
+        if store == StoreMethod::Keyring {
+            KeyringTokenStore::new(self.service.clone()).check_available()?;
+        }
+
+        // the previous session is only dropped once the new one is stored
+        let previous = self.auth_store.get_session()?;
+
+        let SessionBundle {
+            session,
+            credentials,
+        } = bundle;
+        let did = Did::new(&session.did)?.into_static();
+        let key = SessionKey(did, CowStr::Owned(session.session_id.as_str().into()));
+
+        let auth = match (&store, credentials) {
+            (StoreMethod::Keyring, BundledCredentials::OAuth(data)) => {
+                KeyringAuthStore::new(self.service.clone())
+                    .upsert_session((*data).into())
+                    .await?;
+                AuthMethod::OAuth
+            }
+            (StoreMethod::File, BundledCredentials::OAuth(data)) => {
+                FileAuthStore::new(self.get_file_store())
+                    .upsert_session((*data).into())
+                    .await?;
+                AuthMethod::OAuth
+            }
+            (StoreMethod::Keyring, BundledCredentials::AppPassword(data)) => {
+                let store = KeyringAuthStore::new(self.service.clone());
+                SessionStore::<SessionKey, AtpSession>::set(&store, key, data.into()).await?;
+                AuthMethod::AppPassword
+            }
+            (StoreMethod::File, BundledCredentials::AppPassword(data)) => {
+                let store = FileAuthStore::new(self.get_file_store());
+                SessionStore::<SessionKey, AtpSession>::set(&store, key, data.into()).await?;
+                AuthMethod::AppPassword
+            }
+        };
+
+        let session = AuthSession {
+            store,
+            auth,
+            ..session
+        };
+        self.auth_store.set_session(&session)?;
+
+        if !matches!(self.stored_access_token().await, Ok(Some(_))) {
+            match &previous {
+                Some(previous) => self.auth_store.set_session(previous)?,
+                None => self.auth_store.delete_session()?,
+            }
+            return Err(OnyxError::Auth(
+                "the imported credentials couldn't be read back from the store".to_string(),
+            ));
+        }
+
+        // the same account and store share credentials, which were just replaced
+        if let Some(previous) = previous
+            && (&previous.did, &previous.session_id, &previous.store)
+                != (&session.did, &session.session_id, &session.store)
+        {
+            let _ = self.delete_credentials(&previous).await;
+        }
+
+        Ok(session)
+    }
+
+    /// Set the current session aside, keeping its credentials, so another
+    /// account can be logged in to without logging this one out
+    pub fn keep_session(&self) -> Result<(), OnyxError> {
+        let session = self.get_session_info()?;
+
+        let mut kept = self.auth_store.get_kept_sessions()?;
+        kept.retain(|s| s.did != session.did);
+        kept.push(session);
+        self.auth_store.set_kept_sessions(&kept)?;
+
         self.auth_store.delete_session()
     }
 
+    /// Accounts set aside with `keep_session`
+    pub fn kept_sessions(&self) -> Result<Vec<AuthSession>, OnyxError> {
+        self.auth_store.get_kept_sessions()
+    }
+
+    /// Make the kept session for `ident`, a DID or one of its handles, the
+    /// current one, keeping the current session in its place
+    pub fn switch_session(&self, ident: &str) -> Result<AuthSession, OnyxError> {
+        let mut kept = self.auth_store.get_kept_sessions()?;
+        let index = kept
+            .iter()
+            .position(|s| s.did == ident || s.handles.iter().any(|h| h.eq_ignore_ascii_case(ident)))
+            .ok_or_else(|| OnyxError::Other(format!("no kept account for {}", ident).into()))?;
+        let session = kept.remove(index);
+
+        if let Some(current) = self.auth_store.get_session()? {
+            kept.push(current);
+        }
+
+        self.auth_store.set_session(&session)?;
+        self.auth_store.set_kept_sessions(&kept)?;
+
+        Ok(session)
+    }
+
     pub fn get_session_info(&self) -> Result<AuthSession, OnyxError> {
         let session = self.auth_store.get_session()?;
         if let Some(session) = session {
@@ -789,3 +1309,115 @@ impl Authenticator {
         self.config_dir.join("store.json")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session_handle() {
+        let session = AuthSession {
+            did: "did:plc:abcd".to_string(),
+            handles: vec!["one.example.com".to_string(), "two.example.com".to_string()],
+            session_id: "session".to_string(),
+            store: StoreMethod::Keyring,
+            auth: AuthMethod::AppPassword,
+            pds: None,
+        };
+
+        assert_eq!(session.handle(None), Some("one.example.com"));
+        assert_eq!(
+            session.handle(Some("Two.Example.com")),
+            Some("two.example.com")
+        );
+        assert_eq!(
+            session.handle(Some("other.example.com")),
+            Some("one.example.com")
+        );
+        assert_eq!(
+            AuthSession {
+                handles: Vec::new(),
+                ..session
+            }
+            .handle(Some("one.example.com")),
+            None
+        );
+    }
+
+    #[test]
+    fn test_keep_and_switch_session() {
+        let dir = std::env::temp_dir().join(format!("onyx-accounts-{}", std::process::id()));
+        let auth = Authenticator::try_new(
+            "onyx",
+            &dir,
+            HttpOptions::default(),
+            HandleCache::disabled(),
+        )
+        .unwrap();
+        let session = |did: &str, handle: &str| AuthSession {
+            did: did.to_string(),
+            handles: vec![handle.to_string()],
+            session_id: "session".to_string(),
+            store: StoreMethod::File,
+            auth: AuthMethod::AppPassword,
+            pds: None,
+        };
+
+        auth.auth_store
+            .set_session(&session("did:plc:one", "one.example.com"))
+            .unwrap();
+        auth.keep_session().unwrap();
+        let after_keep = auth.get_session_info().is_err();
+        auth.auth_store
+            .set_session(&session("did:plc:two", "two.example.com"))
+            .unwrap();
+
+        let switched = auth.switch_session("One.Example.com").unwrap();
+        let kept = auth.kept_sessions().unwrap();
+        let missing = auth.switch_session("three.example.com").is_err();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(after_keep);
+        assert_eq!(switched.did, "did:plc:one");
+        assert_eq!(kept.len(), 1);
+        assert_eq!(kept[0].did, "did:plc:two");
+        assert!(missing);
+    }
+
+    #[tokio::test]
+    async fn test_send_error_kind() {
+        let session = crate::mock_pds::MockPds::start().await.session().await;
+
+        // nothing listens on a port that was just released
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let request = http::Request::get(format!("http://127.0.0.1:{}/", port))
+            .body(Vec::new())
+            .unwrap();
+
+        assert!(matches!(
+            session.send_http(request).await,
+            Err(OnyxError::Network(_))
+        ));
+    }
+
+    #[test]
+    fn test_token_expiry() {
+        let payload = URL_SAFE_NO_PAD.encode(r#"{"sub":"did:plc:test","exp":1700000000}"#);
+        let token = AuthorizationToken::Bearer(CowStr::Owned(
+            format!("header.{}.signature", payload).into(),
+        ));
+
+        assert_eq!(
+            token_expiry(&token).map(|t| t.timestamp()),
+            Some(1700000000)
+        );
+        assert_eq!(
+            token_expiry(&AuthorizationToken::Dpop(CowStr::Borrowed("opaque"))),
+            None
+        );
+    }
+}
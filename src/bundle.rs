@@ -0,0 +1,173 @@
+use base64::{Engine, engine::general_purpose::STANDARD};
+use jacquard::client::token::OAuthSession as StoredOAuthSession;
+use ring::{
+    aead::{self, Aad, CHACHA20_POLY1305, LessSafeKey, Nonce, UnboundKey},
+    pbkdf2,
+    rand::{SecureRandom, SystemRandom},
+};
+use serde::{Deserialize, Serialize};
+use std::num::NonZeroU32;
+
+use crate::{
+    auth::{AuthSession, StoredPasswordSession},
+    error::OnyxError,
+};
+
+const BUNDLE_VERSION: u32 = 1;
+const KDF_ITERATIONS: u32 = 600_000;
+const SALT_LEN: usize = 16;
+
+/// Credentials pulled out of the session's token store
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum BundledCredentials {
+    AppPassword(StoredPasswordSession),
+    OAuth(Box<StoredOAuthSession>),
+}
+
+/// Everything needed to restore a session on another machine
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SessionBundle {
+    pub session: AuthSession,
+    pub credentials: BundledCredentials,
+}
+
+/// On-disk form of a bundle, the serialized `SessionBundle` is sealed with
+/// ChaCha20-Poly1305 under a key derived from the passphrase with PBKDF2
+#[derive(Debug, Serialize, Deserialize)]
+struct EncryptedBundle {
+    version: u32,
+    iterations: u32,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], iterations: u32) -> Result<LessSafeKey, OnyxError> {
+    let iterations = NonZeroU32::new(iterations)
+        .ok_or_else(|| OnyxError::Parse("invalid bundle iteration count".to_string()))?;
+
+    let mut key = [0; 32];
+    pbkdf2::derive(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        iterations,
+        salt,
+        passphrase.as_bytes(),
+        &mut key,
+    );
+
+    let key = UnboundKey::new(&CHACHA20_POLY1305, &key)
+        .map_err(|_| OnyxError::Other("failed to create bundle key".into()))?;
+    Ok(LessSafeKey::new(key))
+}
+
+fn decode(field: &str, value: &str) -> Result<Vec<u8>, OnyxError> {
+    STANDARD
+        .decode(value)
+        .map_err(|e| OnyxError::Parse(format!("invalid bundle {}: {}", field, e)))
+}
+
+impl SessionBundle {
+    /// Serialize and encrypt the bundle with `passphrase`
+    pub fn seal(&self, passphrase: &str) -> Result<String, OnyxError> {
+        self.seal_with_iterations(passphrase, KDF_ITERATIONS)
+    }
+
+    fn seal_with_iterations(&self, passphrase: &str, iterations: u32) -> Result<String, OnyxError> {
+        let rng = SystemRandom::new();
+        let random_error = |_| OnyxError::Other("failed to generate random bytes".into());
+
+        let mut salt = [0; SALT_LEN];
+        rng.fill(&mut salt).map_err(random_error)?;
+        let mut nonce = [0; aead::NONCE_LEN];
+        rng.fill(&mut nonce).map_err(random_error)?;
+
+        let key = derive_key(passphrase, &salt, iterations)?;
+        let mut data = serde_json::to_vec(self)?;
+        key.seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::empty(), &mut data)
+            .map_err(|_| OnyxError::Other("failed to encrypt bundle".into()))?;
+
+        let bundle = EncryptedBundle {
+            version: BUNDLE_VERSION,
+            iterations,
+            salt: STANDARD.encode(salt),
+            nonce: STANDARD.encode(nonce),
+            ciphertext: STANDARD.encode(data),
+        };
+
+        Ok(serde_json::to_string_pretty(&bundle)?)
+    }
+
+    /// Decrypt a bundle written by `seal`
+    pub fn open(contents: &str, passphrase: &str) -> Result<Self, OnyxError> {
+        let bundle: EncryptedBundle = serde_json::from_str(contents)?;
+        if bundle.version != BUNDLE_VERSION {
+            return Err(OnyxError::Parse(format!(
+                "unsupported bundle version {}",
+                bundle.version
+            )));
+        }
+
+        let salt = decode("salt", &bundle.salt)?;
+        let nonce = Nonce::try_assume_unique_for_key(&decode("nonce", &bundle.nonce)?)
+            .map_err(|_| OnyxError::Parse("invalid bundle nonce".to_string()))?;
+        let mut data = decode("ciphertext", &bundle.ciphertext)?;
+
+        let key = derive_key(passphrase, &salt, bundle.iterations)?;
+        let plaintext = key
+            .open_in_place(nonce, Aad::empty(), &mut data)
+            .map_err(|_| OnyxError::Other("failed to decrypt bundle, wrong passphrase?".into()))?;
+
+        Ok(serde_json::from_slice(plaintext)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{StoreMethod, auth::AuthMethod};
+    use jacquard::{
+        client::AtpSession,
+        types::string::{Did, Handle},
+    };
+
+    #[test]
+    fn test_seal_and_open() {
+        let bundle = SessionBundle {
+            session: AuthSession {
+                did: "did:plc:abcd".to_string(),
+                handles: vec!["test.example.com".to_string()],
+                session_id: "session".to_string(),
+                store: StoreMethod::File,
+                auth: AuthMethod::AppPassword,
+                pds: None,
+            },
+            credentials: BundledCredentials::AppPassword(StoredPasswordSession::new(
+                AtpSession {
+                    access_jwt: "access".into(),
+                    refresh_jwt: "refresh".into(),
+                    did: Did::new_static("did:plc:abcd").unwrap(),
+                    handle: Handle::new_static("test.example.com").unwrap(),
+                },
+                "session",
+            )),
+        };
+
+        // the real iteration count is slow in debug builds
+        let sealed = bundle.seal_with_iterations("hunter2", 1000).unwrap();
+        assert!(!sealed.contains("refresh"));
+
+        let opened = SessionBundle::open(&sealed, "hunter2").unwrap();
+        assert_eq!(opened.session.did, "did:plc:abcd");
+        match opened.credentials {
+            BundledCredentials::AppPassword(s) => {
+                assert_eq!(AtpSession::from(s).refresh_jwt, "refresh")
+            }
+            _ => panic!("expected app password credentials"),
+        }
+
+        assert!(matches!(
+            SessionBundle::open(&sealed, "wrong"),
+            Err(OnyxError::Other(_))
+        ));
+    }
+}
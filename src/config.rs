@@ -0,0 +1,55 @@
+use serde::Deserialize;
+use std::path::Path;
+
+use crate::error::OnyxError;
+
+/// User settings from `config.toml` in the config directory, every field is
+/// optional and a missing file is the same as an empty one
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// How long a status lasts when `status set` isn't given `--expiry`,
+    /// as a duration string like `10m` or `1h`
+    pub default_status_expiry: Option<String>,
+
+    /// Number of plays above which `scrobble logfile` asks before submitting
+    pub import_confirm_threshold: Option<usize>,
+
+    /// Handle shown for the logged-in account when its DID has several
+    pub preferred_handle: Option<String>,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self, OnyxError> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let contents = std::fs::read_to_string(path)?;
+        toml::from_str(&contents)
+            .map_err(|e| OnyxError::Parse(format!("invalid config {}: {}", path.display(), e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_config() {
+        let config: Config = toml::from_str("default_status_expiry = \"30m\"").unwrap();
+        assert_eq!(config.default_status_expiry, Some("30m".to_string()));
+
+        let config: Config = toml::from_str("").unwrap();
+        assert_eq!(config.default_status_expiry, None);
+
+        let config: Config = toml::from_str("import_confirm_threshold = 1000").unwrap();
+        assert_eq!(config.import_confirm_threshold, Some(1000));
+
+        let config: Config = toml::from_str("preferred_handle = \"me.example.com\"").unwrap();
+        assert_eq!(config.preferred_handle, Some("me.example.com".to_string()));
+
+        assert!(toml::from_str::<Config>("default_status_expiry = 30").is_err());
+        assert!(toml::from_str::<Config>("unknown = true").is_err());
+    }
+}
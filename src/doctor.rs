@@ -0,0 +1,317 @@
+//! `onyx doctor`, a checklist of everything onyx needs to work, for working
+//! out why it doesn't
+
+use std::path::Path;
+
+use jacquard::{prelude::IdentityResolver, types::did::Did};
+use owo_colors::{OwoColorize, Stream::Stdout, Style};
+
+use crate::{
+    StoreMethod,
+    auth::{AuthSession, Authenticator, KeyringTokenStore, token_expiry},
+    config::Config,
+    error::OnyxError,
+    http::HttpOptions,
+    identity::{self, HandleCache},
+    record::DEFAULT_DATE_FORMAT,
+};
+
+/// Handle resolved to check the resolver when nobody is logged in
+const PROBE_HANDLE: &str = "atproto.com";
+
+#[derive(Debug, PartialEq)]
+enum Outcome {
+    Pass,
+    /// Something's off, but onyx can still work
+    Warn,
+    /// Something onyx needs is broken
+    Fail,
+}
+
+struct Check {
+    name: &'static str,
+    outcome: Outcome,
+    detail: String,
+}
+
+impl Check {
+    fn pass(name: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            outcome: Outcome::Pass,
+            detail: detail.into(),
+        }
+    }
+
+    fn warn(name: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            outcome: Outcome::Warn,
+            detail: detail.into(),
+        }
+    }
+
+    fn fail(name: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            outcome: Outcome::Fail,
+            detail: detail.into(),
+        }
+    }
+
+    fn print(&self) {
+        let (outcome, style) = match self.outcome {
+            Outcome::Pass => ("ok", Style::new().green().bold()),
+            Outcome::Warn => ("warning", Style::new().yellow().bold()),
+            Outcome::Fail => ("failed", Style::new().red().bold()),
+        };
+
+        println!(
+            "{}: {}, {}",
+            self.name,
+            outcome.if_supports_color(Stdout, |t| t.style(style)),
+            self.detail
+        );
+    }
+}
+
+/// Run every check and print how each went, failing if any critical check
+/// did. Network checks are skipped when `offline`.
+pub async fn run(
+    config_dir: &Path,
+    http: &HttpOptions,
+    cache: &HandleCache,
+    offline: bool,
+) -> Result<(), OnyxError> {
+    let mut checks = vec![check_config_dir(config_dir), check_config(config_dir)];
+
+    let auth = Authenticator::try_new("onyx", config_dir, http.clone(), cache.clone());
+    let session = auth.as_ref().ok().and_then(|a| a.get_session_info().ok());
+
+    checks.push(check_keyring(session.as_ref()));
+
+    match (&auth, &session) {
+        (Err(e), _) => checks.push(Check::fail("session", e.to_string())),
+        (Ok(_), None) => checks.push(Check::warn(
+            "session",
+            "not logged in, log in with 'onyx auth login'",
+        )),
+        (Ok(auth), Some(_)) => checks.push(check_session(auth, offline).await),
+    }
+
+    if offline {
+        checks.push(Check::warn("resolver", "not checked while offline"));
+        checks.push(Check::warn("pds", "not checked while offline"));
+    } else {
+        // the handle the rest of onyx shows, a broken config was reported above
+        let preferred = Config::load(&config_dir.join("config.toml"))
+            .ok()
+            .and_then(|c| c.preferred_handle);
+        let (check, did) = check_resolver(http, session.as_ref(), preferred.as_deref()).await;
+        checks.push(check);
+        checks.push(check_pds(http, session.as_ref(), did).await);
+    }
+
+    for check in &checks {
+        check.print();
+    }
+
+    let failed = checks.iter().filter(|c| c.outcome == Outcome::Fail).count();
+    if failed > 0 {
+        return Err(OnyxError::Other(
+            format!("{} of {} checks failed", failed, checks.len()).into(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Whether the config directory exists, or can be created, and takes writes
+fn check_config_dir(config_dir: &Path) -> Check {
+    let probe = config_dir.join(".onyx-doctor-probe");
+    let res = std::fs::create_dir_all(config_dir)
+        .and_then(|_| std::fs::write(&probe, b"probe"))
+        .and_then(|_| std::fs::remove_file(&probe));
+
+    match res {
+        Ok(()) => Check::pass(
+            "config directory",
+            format!("{} is writable", config_dir.display()),
+        ),
+        Err(e) => Check::fail(
+            "config directory",
+            format!(
+                "{} isn't writable ({}), set {} to a writable directory",
+                config_dir.display(),
+                e,
+                crate::CONFIG_DIR_VAR
+            ),
+        ),
+    }
+}
+
+fn check_config(config_dir: &Path) -> Check {
+    let path = config_dir.join("config.toml");
+
+    match Config::load(&path) {
+        Ok(_) if !path.exists() => Check::pass("config file", "none, using defaults"),
+        Ok(_) => Check::pass("config file", format!("{} is valid", path.display())),
+        Err(e) => Check::fail("config file", e.to_string()),
+    }
+}
+
+/// The keyring is only critical when the session is stored in it
+fn check_keyring(session: Option<&AuthSession>) -> Check {
+    match KeyringTokenStore::new("onyx".to_string()).check_available() {
+        Ok(()) => Check::pass("keyring", "available"),
+        Err(e) if session.is_some_and(|s| s.store == StoreMethod::Keyring) => {
+            Check::fail("keyring", e.to_string())
+        }
+        Err(e) => Check::warn("keyring", e.to_string()),
+    }
+}
+
+/// Whether the stored token is still current and the server accepts the
+/// session
+async fn check_session(auth: &Authenticator, offline: bool) -> Check {
+    let expiry = match auth.stored_access_token().await {
+        Ok(Some(token)) => token_expiry(&token),
+        Ok(None) => {
+            return Check::fail(
+                "session",
+                "no stored credentials, log in again with 'onyx auth login'",
+            );
+        }
+        Err(e) => return Check::fail("session", e.to_string()),
+    };
+    let expiry = match expiry {
+        Some(e) if e > chrono::Utc::now() => format!(
+            "token expires {}",
+            e.with_timezone(&chrono::Local).format(DEFAULT_DATE_FORMAT)
+        ),
+        Some(_) => "token expired, refreshed on next use".to_string(),
+        None => "token expiry unknown".to_string(),
+    };
+
+    if offline {
+        return Check::warn(
+            "session",
+            format!("{}, not confirmed while offline", expiry),
+        );
+    }
+
+    match auth.confirm_session().await {
+        Ok(()) => Check::pass("session", format!("confirmed by the server, {}", expiry)),
+        // the PDS check covers the server being unreachable
+        Err(e @ OnyxError::Network(_)) => Check::warn(
+            "session",
+            format!("couldn't be confirmed ({}), {}", e, expiry),
+        ),
+        Err(e) => Check::fail(
+            "session",
+            format!("rejected ({}), log in again with 'onyx auth login'", e),
+        ),
+    }
+}
+
+/// Resolve the account's handle, the preferred one if it has it, or
+/// `PROBE_HANDLE`, bypassing the handle cache so the resolver is actually
+/// used, returning the DID it resolved to
+async fn check_resolver(
+    http: &HttpOptions,
+    session: Option<&AuthSession>,
+    preferred: Option<&str>,
+) -> (Check, Option<Did<'static>>) {
+    let resolver = match http.build_resolver() {
+        Ok(r) => r,
+        Err(e) => return (Check::fail("resolver", e.to_string()), None),
+    };
+    let handle = session
+        .and_then(|s| s.handle(preferred))
+        .unwrap_or(PROBE_HANDLE);
+
+    let did = identity::resolve_did(
+        &resolver,
+        &HandleCache::disabled(),
+        handle,
+        http.resolve_retries,
+    )
+    .await;
+
+    match did {
+        Ok(did) => (
+            Check::pass("resolver", format!("resolved @{} to {}", handle, did)),
+            Some(did),
+        ),
+        Err(e) => (
+            Check::fail("resolver", format!("couldn't resolve @{}: {}", handle, e)),
+            None,
+        ),
+    }
+}
+
+/// Whether the account's PDS answers its health check, the PDS comes from
+/// the session if it was given at login, otherwise the DID document
+async fn check_pds(
+    http: &HttpOptions,
+    session: Option<&AuthSession>,
+    did: Option<Did<'static>>,
+) -> Check {
+    let Some(session) = session else {
+        return Check::warn("pds", "not checked without a session");
+    };
+
+    let pds = match session.pds_url() {
+        Ok(Some(pds)) => pds,
+        Ok(None) => {
+            let resolved = match did {
+                Some(did) => Ok(did),
+                None => Did::new_owned(&session.did).map_err(OnyxError::from),
+            };
+            let pds = match (resolved, http.build_resolver()) {
+                (Ok(did), Ok(resolver)) => {
+                    resolver.pds_for_did(&did).await.map_err(OnyxError::from)
+                }
+                (Err(e), _) | (_, Err(e)) => Err(e),
+            };
+
+            match pds {
+                Ok(pds) => pds,
+                Err(e) => return Check::fail("pds", format!("couldn't find the PDS: {}", e)),
+            }
+        }
+        Err(e) => return Check::fail("pds", e.to_string()),
+    };
+
+    let health = match (http.build_client(), pds.join("xrpc/_health")) {
+        (Ok(client), Ok(url)) => client.get(url).send().await.map_err(|e| e.to_string()),
+        (Err(e), _) => Err(e.to_string()),
+        (_, Err(e)) => Err(e.to_string()),
+    };
+
+    match health {
+        Ok(res) if res.status().is_success() => Check::pass("pds", format!("{} is reachable", pds)),
+        Ok(res) => Check::fail("pds", format!("{} answered {}", pds, res.status())),
+        Err(e) => Check::fail("pds", format!("{} is unreachable: {}", pds, e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_config() {
+        let dir = std::env::temp_dir().join(format!("onyx-doctor-{}", std::process::id()));
+
+        let writable = check_config_dir(&dir);
+        let missing = check_config(&dir);
+        std::fs::write(dir.join("config.toml"), "unknown = true").unwrap();
+        let invalid = check_config(&dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(writable.outcome, Outcome::Pass);
+        assert_eq!(missing.outcome, Outcome::Pass);
+        assert_eq!(invalid.outcome, Outcome::Fail);
+    }
+}
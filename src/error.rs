@@ -1,9 +1,9 @@
 use jacquard::{
     client::{AgentError, SessionStoreError},
-    error::ClientError,
+    error::{AuthError, ClientError, ClientErrorKind},
     types::{string::AtStrError, uri::UriError},
 };
-use jacquard_identity::resolver::IdentityError;
+use jacquard_identity::resolver::{IdentityError, IdentityErrorKind};
 use jacquard_oauth::error::OAuthError;
 use thiserror::Error;
 
@@ -24,14 +24,49 @@ pub enum OnyxError {
     #[error("auth: {0}")]
     Auth(String),
 
+    /// The stored session can't be refreshed anymore, logging in again fixes it
+    #[error("auth: session expired, {0}")]
+    SessionExpired(String),
+
     #[error("io: {0}")]
     Io(String),
 
+    #[error("network: {0}")]
+    Network(String),
+
+    /// A handle couldn't be resolved to a DID in time
+    #[error("identity: {0}")]
+    Identity(String),
+
     #[error("parse: {0}")]
     Parse(String),
 
+    /// A record changed between being read and written back, re-reading it
+    /// and trying again resolves it
+    #[error("conflict: {0}")]
+    Conflict(String),
+
     #[error("{0}")]
     Other(#[from] Box<dyn std::error::Error + Send + Sync>),
+
+    /// Not a failure, the command finished with a status for scripts to
+    /// check, the process exits with the code without printing anything
+    #[error("exit status {0}")]
+    Exit(i32),
+}
+
+impl OnyxError {
+    /// Process exit code for this category of error
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            OnyxError::Auth(_) | OnyxError::SessionExpired(_) => 2,
+            OnyxError::Network(_) | OnyxError::Identity(_) => 3,
+            OnyxError::Parse(_) => 4,
+            OnyxError::Io(_) => 5,
+            OnyxError::Conflict(_) | OnyxError::Other(_) => 1,
+            OnyxError::Exit(code) => *code,
+        }
+    }
 }
 
 impl From<AtStrError> for OnyxError {
@@ -66,19 +101,42 @@ impl From<serde_json::Error> for OnyxError {
 
 impl From<IdentityError> for OnyxError {
     fn from(err: IdentityError) -> Self {
-        OnyxError::Other(err.to_string().into())
+        match err.kind() {
+            IdentityErrorKind::Timeout => OnyxError::Identity(err.to_string()),
+            IdentityErrorKind::Transport(_)
+            | IdentityErrorKind::Dns
+            | IdentityErrorKind::HttpStatus(_) => OnyxError::Network(err.to_string()),
+            _ => OnyxError::Other(err.to_string().into()),
+        }
     }
 }
 
+/// Whether an OAuth error means the stored session is gone for good, e.g. the
+/// refresh token was revoked or has expired
+pub fn is_session_expired(err: &OAuthError) -> bool {
+    matches!(err, OAuthError::Session(e) if e.is_permanent())
+}
+
 impl From<OAuthError> for OnyxError {
     fn from(err: OAuthError) -> Self {
+        if is_session_expired(&err) {
+            return OnyxError::SessionExpired(err.to_string());
+        }
+
         OnyxError::Auth(err.to_string())
     }
 }
 
 impl From<ClientError> for OnyxError {
     fn from(err: ClientError) -> Self {
-        OnyxError::Other(err.to_string().into())
+        match err.kind() {
+            ClientErrorKind::Transport => OnyxError::Network(err.to_string()),
+            ClientErrorKind::Auth(AuthError::RefreshFailed) => {
+                OnyxError::SessionExpired(err.to_string())
+            }
+            ClientErrorKind::Auth(_) => OnyxError::Auth(err.to_string()),
+            _ => OnyxError::Other(err.to_string().into()),
+        }
     }
 }
 
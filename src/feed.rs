@@ -0,0 +1,449 @@
+use jacquard::{
+    CowStr, IntoStatic,
+    prelude::{IdentityResolver, XrpcClient, XrpcExt},
+    types::{
+        collection::Collection,
+        ident::AtIdentifier,
+        nsid::Nsid,
+        value::{Data, from_data_owned},
+    },
+};
+use jacquard_api::{
+    com_atproto::repo::list_records::ListRecords, fm_teal::alpha::feed as fm_teal_feed,
+};
+use jacquard_identity::JacquardResolver;
+use owo_colors::{OwoColorize, Stream::Stdout};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::OpenOptions,
+    io::{Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    auth::GenericSession,
+    error::OnyxError,
+    http::HttpOptions,
+    identity::{self, HandleCache},
+    label::{Label, stderr_label},
+    record::Play,
+};
+
+/// Largest page `listRecords` will return
+const PAGE_SIZE: usize = 100;
+
+/// A play record along with its location in the repo
+#[derive(Debug, Clone, Serialize)]
+pub struct PlayRecord {
+    pub uri: String,
+    pub play: Play,
+}
+
+/// A record from any collection, as stored in the repo
+#[derive(Debug, Clone, Serialize)]
+pub struct RawRecord {
+    pub uri: String,
+    pub value: Data<'static>,
+}
+
+/// A line of a backup written by `FeedClient::backup_plays`
+#[derive(Debug, Serialize)]
+struct BackupEntry {
+    uri: String,
+    #[serde(flatten)]
+    play: Play,
+}
+
+/// How far a backup got, saved after each page so it can be resumed
+#[derive(Debug, Serialize, Deserialize)]
+struct ExportCheckpoint {
+    /// Repo being backed up, so another account's export never resumes it
+    did: String,
+    /// Cursor for the next page
+    cursor: String,
+    /// Length of the backup file up to the end of the last saved page
+    length: u64,
+    /// Plays written up to the end of the last saved page
+    count: usize,
+}
+
+impl ExportCheckpoint {
+    /// The checkpoint for a backup at `path`, kept beside it
+    fn path_for(path: &Path) -> PathBuf {
+        let mut checkpoint = path.as_os_str().to_owned();
+        checkpoint.push(".checkpoint");
+        PathBuf::from(checkpoint)
+    }
+
+    fn load(path: &Path) -> Result<Option<Self>, OnyxError> {
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        serde_json::from_str(&std::fs::read_to_string(path)?)
+            .map(Some)
+            .map_err(|e| {
+                OnyxError::Parse(format!(
+                    "invalid export checkpoint {}: {}",
+                    path.display(),
+                    e
+                ))
+            })
+    }
+
+    /// Replace the checkpoint in one rename, so it's never left half written
+    fn save(&self, path: &Path) -> Result<(), OnyxError> {
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(".tmp");
+
+        std::fs::write(&temp, serde_json::to_string(self)?)?;
+        std::fs::rename(&temp, path)?;
+        Ok(())
+    }
+}
+
+/// Reads a user's play feed, publicly unless given a session
+pub struct FeedClient {
+    pub ident: String,
+
+    http: HttpOptions,
+    resolver: JacquardResolver,
+    handle_cache: HandleCache,
+    session: Option<GenericSession>,
+}
+
+impl FeedClient {
+    pub fn try_new(
+        ident: &str,
+        http: HttpOptions,
+        handle_cache: HandleCache,
+    ) -> Result<Self, OnyxError> {
+        Ok(Self {
+            ident: ident.to_owned(),
+            resolver: http.build_resolver()?,
+            http,
+            handle_cache,
+            session: None,
+        })
+    }
+
+    /// Read through the logged in session, for the session's own feed
+    pub fn with_session(mut self, session: Option<GenericSession>) -> Self {
+        self.session = session;
+        self
+    }
+
+    /// Fetch play records, newest first, following the cursor across pages
+    /// until `limit` records are fetched or the feed runs out
+    pub async fn get_plays(&self, limit: Option<usize>) -> Result<Vec<PlayRecord>, OnyxError> {
+        self.get_records(fm_teal_feed::play::Play::nsid(), limit)
+            .await?
+            .into_iter()
+            .map(|record| {
+                let play: fm_teal_feed::play::Play =
+                    from_data_owned(record.value).map_err(|e| {
+                        OnyxError::Parse(format!("invalid play record {}: {}", record.uri, e))
+                    })?;
+
+                Ok(PlayRecord {
+                    uri: record.uri,
+                    play: play.into(),
+                })
+            })
+            .collect()
+    }
+
+    /// Fetch records from any collection, newest first, paged the same way
+    /// as `get_plays`
+    pub async fn get_records(
+        &self,
+        collection: Nsid<'static>,
+        limit: Option<usize>,
+    ) -> Result<Vec<RawRecord>, OnyxError> {
+        let mut records = Vec::new();
+
+        self.page_records(collection, limit, None, |page, _| {
+            records.extend(page);
+            Ok(())
+        })
+        .await?;
+
+        Ok(records)
+    }
+
+    /// Fetch records page by page, newest first, starting at `cursor`, until
+    /// `limit` records are fetched or the collection runs out
+    ///
+    /// Each page is handed to `on_page` along with the cursor for the page
+    /// after it, `None` on the last page, which can be empty.
+    pub async fn page_records(
+        &self,
+        collection: Nsid<'static>,
+        limit: Option<usize>,
+        mut cursor: Option<CowStr<'static>>,
+        mut on_page: impl FnMut(Vec<RawRecord>, Option<&str>) -> Result<(), OnyxError>,
+    ) -> Result<(), OnyxError> {
+        let did = identity::resolve_did(
+            &self.resolver,
+            &self.handle_cache,
+            &self.ident,
+            self.http.resolve_retries,
+        )
+        .await?;
+        // a session already knows its PDS, so only resolve it for public reads
+        let pds = match self.session {
+            Some(_) => None,
+            None => Some(self.resolver.pds_for_did(&did).await?),
+        };
+        let client = self.http.build_client()?;
+
+        let mut fetched = 0;
+
+        loop {
+            let remaining = limit.map_or(PAGE_SIZE, |l| l.saturating_sub(fetched));
+            if remaining == 0 {
+                break;
+            }
+
+            let request = ListRecords::new()
+                .repo(AtIdentifier::Did(did.clone()))
+                .collection(collection.clone())
+                .limit(remaining.min(PAGE_SIZE) as i64)
+                .maybe_cursor(cursor.take())
+                .build();
+
+            let response = match (&self.session, &pds) {
+                (Some(session), _) => session.send(request).await?,
+                (None, Some(pds)) => client.xrpc(pds.clone()).send(&request).await?,
+                (None, None) => unreachable!("PDS is resolved without a session"),
+            };
+            let output = response
+                .into_output()
+                .map_err(|e| OnyxError::Other(e.to_string().into()))?;
+
+            if output.records.is_empty() {
+                on_page(Vec::new(), None)?;
+                break;
+            }
+
+            let next = output.cursor.map(|c| c.into_static());
+            let mut records = output.records;
+            // a PDS may send more than was asked for, don't go past the limit
+            if limit.is_some() {
+                records.truncate(remaining);
+            }
+            fetched += records.len();
+            on_page(
+                records
+                    .into_iter()
+                    .map(|record| RawRecord {
+                        uri: record.uri.to_string(),
+                        value: record.value.into_static(),
+                    })
+                    .collect(),
+                next.as_deref(),
+            )?;
+
+            match next {
+                Some(next) => cursor = Some(next),
+                None => break,
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Write every play record to `path` as NDJSON, one play per line with
+    /// its URI, the same shape the `json` log format reads back
+    ///
+    /// After each page the cursor is saved to a checkpoint file next to
+    /// `path`, so an interrupted export picks up where it left off when run
+    /// again, and the checkpoint is removed once the feed is exhausted.
+    /// Returns how many plays the file holds.
+    pub async fn backup_plays(&self, path: &Path) -> Result<usize, OnyxError> {
+        let did = identity::resolve_did(
+            &self.resolver,
+            &self.handle_cache,
+            &self.ident,
+            self.http.resolve_retries,
+        )
+        .await?;
+
+        let checkpoint_path = ExportCheckpoint::path_for(path);
+        let checkpoint = ExportCheckpoint::load(&checkpoint_path)?;
+
+        if let Some(checkpoint) = &checkpoint
+            && checkpoint.did != did.as_str()
+        {
+            return Err(OnyxError::Conflict(format!(
+                "{} holds an unfinished export of {}, finish it or export to another file",
+                path.display(),
+                checkpoint.did
+            )));
+        }
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(false)
+            .open(path)?;
+
+        // a backup cut short since the checkpoint was saved has lost plays
+        // the cursor is past, so it can only be started over
+        let length = file.metadata()?.len();
+        let checkpoint = match checkpoint {
+            Some(checkpoint) if length < checkpoint.length => {
+                eprintln!(
+                    "{}: {} is shorter than its checkpoint, starting the export over",
+                    stderr_label(Label::Warning),
+                    path.display()
+                );
+                None
+            }
+            checkpoint => checkpoint,
+        };
+
+        // anything past the checkpoint is from a page that was cut off
+        // before it was saved, and will be fetched again
+        let mut count = 0;
+        let cursor = match checkpoint {
+            Some(checkpoint) => {
+                file.set_len(checkpoint.length)?;
+                count = checkpoint.count;
+
+                println!(
+                    "{}",
+                    format!("resuming export after {} plays", count)
+                        .if_supports_color(Stdout, |t| t.dimmed())
+                );
+                Some(CowStr::from(checkpoint.cursor))
+            }
+            None => {
+                file.set_len(0)?;
+                None
+            }
+        };
+        file.seek(SeekFrom::End(0))?;
+
+        self.page_records(
+            fm_teal_feed::play::Play::nsid(),
+            None,
+            cursor,
+            |page, next| {
+                let mut lines = String::new();
+                for record in page {
+                    let play: fm_teal_feed::play::Play =
+                        from_data_owned(record.value).map_err(|e| {
+                            OnyxError::Parse(format!("invalid play record {}: {}", record.uri, e))
+                        })?;
+                    let entry = BackupEntry {
+                        uri: record.uri,
+                        play: play.into(),
+                    };
+
+                    lines.push_str(&serde_json::to_string(&entry)?);
+                    lines.push('\n');
+                    count += 1;
+                }
+
+                file.write_all(lines.as_bytes())?;
+                file.sync_data()?;
+
+                match next {
+                    Some(cursor) => ExportCheckpoint {
+                        did: did.to_string(),
+                        cursor: cursor.to_string(),
+                        length: file.stream_position()?,
+                        count,
+                    }
+                    .save(&checkpoint_path),
+                    None => Ok(()),
+                }
+            },
+        )
+        .await?;
+
+        if checkpoint_path.exists() {
+            std::fs::remove_file(&checkpoint_path)?;
+        }
+
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        mock_pds::{DID, MockPds},
+        parser::{LogParser, json::JsonParser},
+        record::Artist,
+        scrobble::Scrobbler,
+    };
+
+    #[tokio::test]
+    async fn test_backup_plays() {
+        let pds = MockPds::start().await;
+        let scrobbler = Scrobbler::new("onyx", "test", pds.session().await);
+
+        for name in ["Track 1", "Track 2", "Track 3"] {
+            let play = Play {
+                track_name: name.to_string(),
+                artists: Some(vec![Artist {
+                    artist_name: "Artist".to_string(),
+                    artist_mb_id: None,
+                }]),
+                ..Default::default()
+            };
+            scrobbler.scrobble_track(play).await.unwrap();
+        }
+
+        let path = std::env::temp_dir().join(format!("onyx-backup-{}.ndjson", std::process::id()));
+        let feed = FeedClient::try_new(DID, HttpOptions::default(), HandleCache::disabled())
+            .unwrap()
+            .with_session(Some(pds.session().await));
+
+        assert_eq!(feed.backup_plays(&path).await.unwrap(), 3);
+        let full = std::fs::read_to_string(&path).unwrap();
+        let checkpoint = ExportCheckpoint::path_for(&path);
+        assert!(!checkpoint.exists());
+
+        // resume after the first page was saved and the next was cut off
+        let first = full.lines().next().unwrap();
+        std::fs::write(&path, format!("{}\n{{\"uri\":", first)).unwrap();
+        let saved = |did: &str, length: u64| {
+            ExportCheckpoint {
+                did: did.to_string(),
+                cursor: "3".to_string(),
+                length,
+                count: 1,
+            }
+            .save(&checkpoint)
+            .unwrap()
+        };
+        saved(DID, first.len() as u64 + 1);
+
+        assert_eq!(feed.backup_plays(&path).await.unwrap(), 3);
+        let resumed = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(resumed, full);
+        assert!(!checkpoint.exists());
+
+        // a backup shorter than its checkpoint starts over
+        std::fs::write(&path, "").unwrap();
+        saved(DID, first.len() as u64 + 1);
+        assert_eq!(feed.backup_plays(&path).await.unwrap(), 3);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), full);
+
+        // another account's checkpoint is left alone
+        saved("did:plc:other", 0);
+        let other = feed.backup_plays(&path).await;
+        std::fs::remove_file(&checkpoint).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(matches!(other, Err(OnyxError::Conflict(_))));
+
+        let plays = JsonParser::parse_reader(full.as_bytes()).unwrap();
+        let names: Vec<&str> = plays.iter().map(|p| p.track_name.as_str()).collect();
+        assert_eq!(names, ["Track 3", "Track 2", "Track 1"]);
+    }
+}
@@ -0,0 +1,185 @@
+use chrono::{DateTime, FixedOffset, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::OpenOptions,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use crate::{error::OnyxError, record::Play};
+
+/// A successfully submitted play, as recorded in the history file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub submitted: DateTime<Utc>,
+    pub uri: String,
+    pub rkey: Option<String>,
+    /// Submitted by hand, e.g. with `scrobble track`, rather than imported
+    /// from a log, older entries without it count as imported
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub manual: bool,
+    pub play: Play,
+}
+
+/// Local NDJSON record of everything scrobbled, kept as a backup independent
+/// of the PDS
+#[derive(Debug, Clone)]
+pub struct History {
+    pub path: PathBuf,
+}
+
+impl History {
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_owned(),
+        }
+    }
+
+    /// Append an entry as a single line, creating the file if needed
+    pub fn append(&self, entry: &HistoryEntry) -> Result<(), OnyxError> {
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+
+        // a single write keeps lines whole if two processes append at once
+        file.write_all(line.as_bytes())?;
+        Ok(())
+    }
+
+    /// Read every entry, skipping lines that don't parse, or nothing if the
+    /// file doesn't exist yet
+    pub fn entries(&self) -> Result<Vec<HistoryEntry>, OnyxError> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+
+        Ok(std::fs::read_to_string(&self.path)?
+            .lines()
+            .filter_map(|l| serde_json::from_str(l).ok())
+            .collect())
+    }
+
+    /// Entries for plays submitted to `did`'s repo
+    pub fn entries_for(&self, did: &str) -> Result<Vec<HistoryEntry>, OnyxError> {
+        let prefix = format!("at://{}/", did);
+
+        Ok(self
+            .entries()?
+            .into_iter()
+            .filter(|e| e.uri.starts_with(&prefix))
+            .collect())
+    }
+
+    /// Latest played time among the plays imported to `did`'s repo, the
+    /// point an incremental import can pick up from, plays submitted by hand
+    /// don't count since they say nothing about how far a log was imported
+    pub fn last_played(&self, did: &str) -> Result<Option<DateTime<FixedOffset>>, OnyxError> {
+        Ok(self
+            .entries_for(did)?
+            .into_iter()
+            .filter(|e| !e.manual)
+            .filter_map(|e| e.play.played_time)
+            .max())
+    }
+
+    /// Plays submitted to `did`'s repo with `query` in the track name,
+    /// ignoring case, and played at `played` if given
+    pub fn search(
+        &self,
+        did: &str,
+        query: &str,
+        played: Option<DateTime<FixedOffset>>,
+    ) -> Result<Vec<HistoryEntry>, OnyxError> {
+        let query = query.to_lowercase();
+
+        Ok(self
+            .entries_for(did)?
+            .into_iter()
+            .filter(|e| e.play.track_name.to_lowercase().contains(&query))
+            .filter(|e| played.is_none_or(|p| e.play.played_time == Some(p)))
+            .collect())
+    }
+
+    /// Drop the entries for records at `uris`, once they've been deleted
+    ///
+    /// The file is replaced without them in one rename, so it's never left
+    /// half written, lines that don't parse are kept as they are.
+    pub fn remove(&self, uris: &[&str]) -> Result<(), OnyxError> {
+        if !self.path.exists() {
+            return Ok(());
+        }
+
+        let kept: String = std::fs::read_to_string(&self.path)?
+            .lines()
+            .filter(|l| match serde_json::from_str::<HistoryEntry>(l) {
+                Ok(e) => !uris.contains(&e.uri.as_str()),
+                Err(_) => true,
+            })
+            .map(|l| format!("{}\n", l))
+            .collect();
+
+        let mut temp = self.path.as_os_str().to_owned();
+        temp.push(".tmp");
+
+        std::fs::write(&temp, kept)?;
+        std::fs::rename(&temp, &self.path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_append() {
+        let path = std::env::temp_dir().join(format!("onyx-history-{}.ndjson", std::process::id()));
+        let history = History::new(&path);
+
+        for (name, did, time, manual) in [
+            ("Track 1", "did:plc:abcd", "2024-01-02T00:00:00Z", false),
+            ("Track 2", "did:plc:abcd", "2024-01-01T00:00:00Z", false),
+            ("Track 3", "did:plc:efgh", "2024-01-03T00:00:00Z", false),
+            ("Track 4", "did:plc:abcd", "2024-01-04T00:00:00Z", true),
+        ] {
+            let entry = HistoryEntry {
+                submitted: Utc::now(),
+                uri: format!("at://{}/fm.teal.alpha.feed.play/{}", did, &name[6..]),
+                rkey: Some(name[6..].to_string()),
+                manual,
+                play: Play {
+                    track_name: name.to_string(),
+                    played_time: Some(DateTime::parse_from_rfc3339(time).unwrap()),
+                    ..Default::default()
+                },
+            };
+            history.append(&entry).unwrap();
+        }
+
+        let entries = history.entries().unwrap();
+        let last = history.last_played("did:plc:abcd").unwrap();
+        let none = history.last_played("did:plc:ijkl").unwrap();
+        let found = history.search("did:plc:abcd", "track", None).unwrap();
+        let played = DateTime::parse_from_rfc3339("2024-01-01T00:00:00Z").ok();
+        let found_at = history.search("did:plc:abcd", "TRACK", played).unwrap();
+
+        history.remove(&[found_at[0].uri.as_str()]).unwrap();
+        let remaining = history.entries().unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(entries.len(), 4);
+        assert_eq!(entries[1].play.track_name, "Track 2");
+        assert_eq!(last.unwrap().to_rfc3339(), "2024-01-02T00:00:00+00:00");
+        assert_eq!(none, None);
+        assert_eq!(found.len(), 3);
+        assert_eq!(found_at.len(), 1);
+        assert_eq!(found_at[0].play.track_name, "Track 2");
+        assert_eq!(remaining.len(), 3);
+        assert!(remaining[2].manual);
+        assert!(remaining.iter().all(|e| e.play.track_name != "Track 2"));
+    }
+}
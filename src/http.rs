@@ -0,0 +1,52 @@
+use std::time::Duration;
+
+use jacquard_identity::{JacquardResolver, resolver::ResolverOptions};
+
+use crate::error::OnyxError;
+
+/// Options applied to every outbound HTTP client onyx constructs.
+///
+/// The timeout covers each individual request, which includes handle/DID
+/// resolution, record creation when scrobbling, and status reads/writes.
+/// Standard proxy environment variables (`HTTP_PROXY`, `HTTPS_PROXY`,
+/// `ALL_PROXY`, `NO_PROXY`) are honored unless an explicit proxy is given.
+#[derive(Debug, Clone, Default)]
+pub struct HttpOptions {
+    pub timeout: Option<Duration>,
+    pub proxy: Option<String>,
+    pub user_agent: Option<String>,
+    /// Extra attempts at resolving a handle after a network failure
+    pub resolve_retries: u32,
+}
+
+impl HttpOptions {
+    pub fn build_client(&self) -> Result<reqwest::Client, OnyxError> {
+        let mut builder = reqwest::Client::builder();
+
+        // sessions send through this client too, so this covers `GenericSession::send_http`
+        if let Some(user_agent) = &self.user_agent {
+            builder = builder.user_agent(user_agent);
+        }
+
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(timeout);
+        }
+
+        if let Some(proxy) = &self.proxy {
+            let proxy = reqwest::Proxy::all(proxy)
+                .map_err(|e| OnyxError::Parse(format!("invalid proxy '{}': {}", proxy, e)))?;
+            builder = builder.proxy(proxy);
+        }
+
+        builder.build().map_err(|e| OnyxError::Other(Box::new(e)))
+    }
+
+    pub fn build_resolver(&self) -> Result<JacquardResolver, OnyxError> {
+        let mut opts = ResolverOptions::default();
+        if self.timeout.is_some() {
+            opts.request_timeout = self.timeout;
+        }
+
+        Ok(JacquardResolver::new_dns(self.build_client()?, opts))
+    }
+}
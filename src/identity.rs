@@ -0,0 +1,292 @@
+use chrono::{DateTime, Duration, Utc};
+use jacquard::{
+    IntoStatic,
+    prelude::IdentityResolver,
+    types::{did::Did, string::Handle},
+};
+use jacquard_identity::{
+    JacquardResolver,
+    resolver::{IdentityError, IdentityErrorKind},
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, path::PathBuf};
+
+use crate::error::OnyxError;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CacheEntry {
+    did: String,
+    resolved: DateTime<Utc>,
+}
+
+/// On-disk cache of handle to DID mappings
+#[derive(Debug, Clone)]
+pub struct HandleCache {
+    path: Option<PathBuf>,
+    ttl: Duration,
+    read: bool,
+}
+
+impl HandleCache {
+    /// Create a cache backed by `path`, if `read` is false, entries are
+    /// refreshed but never used
+    pub fn new(path: PathBuf, ttl: Duration, read: bool) -> Self {
+        Self {
+            path: Some(path),
+            ttl,
+            read,
+        }
+    }
+
+    /// A cache that never stores anything
+    pub fn disabled() -> Self {
+        Self {
+            path: None,
+            ttl: Duration::zero(),
+            read: false,
+        }
+    }
+
+    fn load(&self) -> HashMap<String, CacheEntry> {
+        self.path
+            .as_ref()
+            .and_then(|p| std::fs::read_to_string(p).ok())
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn get(&self, handle: &str) -> Option<String> {
+        if !self.read {
+            return None;
+        }
+
+        let entries = self.load();
+        let entry = entries.get(&handle.to_lowercase())?;
+
+        if Utc::now() - entry.resolved > self.ttl {
+            return None;
+        }
+
+        Some(entry.did.clone())
+    }
+
+    /// Reverse lookup of a fresh entry's handle by its DID
+    pub fn handle_for(&self, did: &str) -> Option<String> {
+        if !self.read {
+            return None;
+        }
+
+        self.load()
+            .into_iter()
+            .find(|(_, entry)| entry.did == did && Utc::now() - entry.resolved <= self.ttl)
+            .map(|(handle, _)| handle)
+    }
+
+    pub fn insert(&self, handle: &str, did: &str) -> Result<(), OnyxError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        let mut entries = self.load();
+        entries.insert(
+            handle.to_lowercase(),
+            CacheEntry {
+                did: did.to_owned(),
+                resolved: Utc::now(),
+            },
+        );
+
+        std::fs::write(path, serde_json::to_string(&entries)?)?;
+        Ok(())
+    }
+}
+
+/// Delay before the first retry of a failed resolution, doubling after each
+const RETRY_BASE_DELAY: std::time::Duration = std::time::Duration::from_millis(500);
+
+/// Longest delay between retries, however many have failed
+const RETRY_MAX_DELAY: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// Delay before retry number `attempt`, counting from zero
+fn retry_delay(attempt: u32) -> std::time::Duration {
+    // the exponent is capped so the multiplication can't overflow
+    (RETRY_BASE_DELAY * 2u32.pow(attempt.min(6))).min(RETRY_MAX_DELAY)
+}
+
+/// Resolve a handle or DID to a DID, consulting the handle cache first, and
+/// retrying up to `retries` times if resolution fails on network trouble
+pub async fn resolve_did(
+    resolver: &JacquardResolver,
+    cache: &HandleCache,
+    ident: &str,
+    retries: u32,
+) -> Result<Did<'static>, OnyxError> {
+    if let Ok(did) = ident.parse::<Did>() {
+        return Ok(did.into_static());
+    }
+
+    if let Some(did) = cache.get(ident)
+        && let Ok(did) = Did::new_owned(did)
+    {
+        return Ok(did);
+    }
+
+    let handle = Handle::new(ident)?;
+
+    let mut attempt = 0;
+    let did = loop {
+        match resolve_handle_once(resolver, &handle, ident).await {
+            Ok(did) => break did,
+            Err((e, transient)) if !transient || attempt >= retries => return Err(e),
+            Err(_) => {
+                tokio::time::sleep(retry_delay(attempt)).await;
+                attempt += 1;
+            }
+        }
+    };
+
+    // a failed cache write shouldn't fail the command
+    let _ = cache.insert(ident, did.as_str());
+
+    Ok(did)
+}
+
+/// A single attempt at resolving `handle`, failing with whether the error is
+/// worth retrying
+async fn resolve_handle_once(
+    resolver: &JacquardResolver,
+    handle: &Handle<'_>,
+    ident: &str,
+) -> Result<Did<'static>, (OnyxError, bool)> {
+    // the resolver only bounds each HTTP request, DNS lookups and fallbacks
+    // can still add up, so bound the whole resolution by the same timeout
+    let result = match resolver.options().request_timeout {
+        Some(timeout) => tokio::time::timeout(timeout, resolver.resolve_handle(handle))
+            .await
+            .map_err(|_| {
+                let e = OnyxError::Identity(format!(
+                    "timed out resolving {} after {}s",
+                    ident,
+                    timeout.as_secs()
+                ));
+                (e, true)
+            })?,
+        None => resolver.resolve_handle(handle).await,
+    };
+
+    result.map_err(|e| {
+        let transient = is_transient(&e);
+        (e.into(), transient)
+    })
+}
+
+/// Whether a resolution failure could go away by itself, network trouble
+/// can, a handle that doesn't point at a DID won't
+fn is_transient(err: &IdentityError) -> bool {
+    match err.kind() {
+        IdentityErrorKind::Transport(_) | IdentityErrorKind::Timeout | IdentityErrorKind::Dns => {
+            true
+        }
+        IdentityErrorKind::HttpStatus(status) => status.is_server_error() || status.as_u16() == 429,
+        _ => false,
+    }
+}
+
+/// Name to show for a handle or DID, `@handle` where the DID document
+/// names one that resolves back to it, falling back to the DID itself if
+/// resolution fails or the handle doesn't match
+pub async fn resolve_did_to_handle(
+    resolver: &JacquardResolver,
+    cache: &HandleCache,
+    ident: &str,
+) -> String {
+    let Ok(did) = ident.parse::<Did>() else {
+        return format!("@{}", ident);
+    };
+
+    if let Some(handle) = cache.handle_for(did.as_str()) {
+        return format!("@{}", handle);
+    }
+
+    let handle = match resolver.resolve_did_doc(&did).await {
+        Ok(response) => response
+            .parse()
+            .ok()
+            .and_then(|doc| doc.handles().into_iter().next()),
+        Err(_) => None,
+    };
+
+    let Some(handle) = handle else {
+        return ident.to_owned();
+    };
+
+    // alsoKnownAs is claimed by the DID document itself, only trust it if
+    // the handle points back at the same DID
+    match resolve_handle_once(resolver, &handle, handle.as_str()).await {
+        Ok(resolved) if resolved == did => {
+            let _ = cache.insert(handle.as_str(), did.as_str());
+            format!("@{}", handle)
+        }
+        _ => ident.to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_handle_cache() {
+        let path = std::env::temp_dir().join(format!("onyx-handles-{}.json", std::process::id()));
+
+        let cache = HandleCache::new(path.clone(), Duration::hours(24), true);
+        cache.insert("Alice.example.com", "did:plc:alice").unwrap();
+        assert_eq!(
+            cache.get("alice.example.com"),
+            Some("did:plc:alice".to_string())
+        );
+
+        let expired = HandleCache::new(path.clone(), Duration::zero() - Duration::seconds(1), true);
+        assert_eq!(expired.get("alice.example.com"), None);
+
+        let unread = HandleCache::new(path.clone(), Duration::hours(24), false);
+        assert_eq!(unread.get("alice.example.com"), None);
+
+        assert_eq!(HandleCache::disabled().get("alice.example.com"), None);
+
+        assert_eq!(
+            cache.handle_for("did:plc:alice"),
+            Some("alice.example.com".to_string())
+        );
+        assert_eq!(cache.handle_for("did:plc:bob"), None);
+        assert_eq!(expired.handle_for("did:plc:alice"), None);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        assert_eq!(retry_delay(0), RETRY_BASE_DELAY);
+        assert_eq!(retry_delay(2), RETRY_BASE_DELAY * 4);
+        assert_eq!(retry_delay(6), RETRY_MAX_DELAY);
+        assert_eq!(retry_delay(u32::MAX), RETRY_MAX_DELAY);
+    }
+
+    #[test]
+    fn test_is_transient() {
+        use reqwest::StatusCode;
+
+        assert!(is_transient(&IdentityError::timeout()));
+        assert!(is_transient(&IdentityError::http_status(
+            StatusCode::BAD_GATEWAY
+        )));
+        assert!(is_transient(&IdentityError::http_status(
+            StatusCode::TOO_MANY_REQUESTS
+        )));
+
+        assert!(!is_transient(&IdentityError::http_status(
+            StatusCode::NOT_FOUND
+        )));
+        assert!(!is_transient(&IdentityError::invalid_well_known()));
+    }
+}
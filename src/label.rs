@@ -0,0 +1,50 @@
+//! Coloured labels that start a line of output, like `success:` and
+//! `warning:`
+
+use owo_colors::{OwoColorize, Stream, Style};
+
+/// What a labelled line reports, which sets its text and colour
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Label {
+    Success,
+    Warning,
+    Error,
+    Summary,
+    Hint,
+}
+
+impl Label {
+    fn text(self) -> &'static str {
+        match self {
+            Label::Success => "success",
+            Label::Warning => "warning",
+            Label::Error => "error",
+            Label::Summary => "summary",
+            Label::Hint => "hint",
+        }
+    }
+
+    fn style(self) -> Style {
+        match self {
+            Label::Success | Label::Hint => Style::new().green().bold(),
+            Label::Warning | Label::Summary => Style::new().yellow().bold(),
+            Label::Error => Style::new().red().bold(),
+        }
+    }
+
+    fn styled(self, stream: Stream) -> String {
+        self.text()
+            .if_supports_color(stream, |t| t.style(self.style()))
+            .to_string()
+    }
+}
+
+/// `kind`'s label, coloured if stdout supports it
+pub fn label(kind: Label) -> String {
+    kind.styled(Stream::Stdout)
+}
+
+/// `kind`'s label, coloured if stderr supports it
+pub fn stderr_label(kind: Label) -> String {
+    kind.styled(Stream::Stderr)
+}
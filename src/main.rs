@@ -1,15 +1,38 @@
-use owo_colors::OwoColorize;
+use owo_colors::{OwoColorize, Stream::Stdout, Style};
 use serde::{Deserialize, Serialize};
-use std::{io::BufReader, path::PathBuf};
+use std::{
+    io::{IsTerminal, Write},
+    path::{Path, PathBuf},
+};
+use tokio::io::BufReader;
 
 #[cfg(unix)]
-use std::os::unix::net::UnixListener;
+use tokio::net::UnixListener;
+
+use jacquard::{
+    client::{Agent, AgentSession},
+    types::nsid::Nsid,
+};
+use jacquard_api::fm_teal::alpha::actor::status as fm_teal_status;
 
 use crate::{
-    auth::{AuthMethod, Authenticator, GenericSession},
+    auth::{AuthMethod, AuthSession, Authenticator, CallbackOptions, GenericSession, token_expiry},
+    config::Config,
     error::OnyxError,
-    record::{Artist, Play, PlayView, Status},
-    scrobble::Scrobbler,
+    feed::{FeedClient, PlayRecord},
+    history::History,
+    http::HttpOptions,
+    identity::HandleCache,
+    label::{Label, label},
+    musicbrainz::MusicBrainzClient,
+    parser::delimited::{ColumnMap, DelimitedParser, parse_delimiter},
+    record::{
+        Artist, DEFAULT_DATE_FORMAT, MAX_PLAY_COUNT, Play, PlayMatch, PlayView, Status,
+        StatusSnapshot, TWELVE_HOUR_DATE_FORMAT,
+    },
+    scrobble::{
+        ArtistFilter, STDIN_PATH, Scrobbler, Webhook, export_plays, merge_plays, parse_log,
+    },
     status::StatusManager,
 };
 use clap::{
@@ -19,13 +42,58 @@ use clap::{
         styling::{AnsiColor, Effects},
     },
 };
+use comfy_table::{Attribute, Cell, Color, ContentArrangement, Table, presets};
 
 mod auth;
+mod bundle;
+mod config;
+mod doctor;
 mod error;
+mod feed;
+mod history;
+mod http;
+mod identity;
+mod label;
+mod metrics;
+#[cfg(test)]
+mod mock_pds;
+mod musicbrainz;
 mod parser;
 mod record;
 mod scrobble;
 mod status;
+mod systemd;
+
+const HANDLE_CACHE_TTL_HOURS: i64 = 24;
+
+/// Exit code for `status show --exit-code` when nothing is playing, distinct
+/// from the error codes in `OnyxError::exit_code`
+const NOTHING_PLAYING_EXIT_CODE: i32 = 6;
+
+/// Exit code for `auth status` without a stored session, the same as auth
+/// errors
+const LOGGED_OUT_EXIT_CODE: i32 = 2;
+
+/// Overrides the config directory, for systems where the default isn't
+/// writable
+const CONFIG_DIR_VAR: &str = "ONYX_CONFIG_DIR";
+
+/// When both are set, commands log in with these for the current process
+/// only, instead of using the stored session. `auth login` without a handle
+/// also reads them, to log in without prompts
+const EPHEMERAL_HANDLE_VAR: &str = "ONYX_HANDLE";
+const EPHEMERAL_PASSWORD_VAR: &str = "ONYX_APP_PASSWORD";
+
+/// Store method for `auth login` when `--store` isn't given
+const LOGIN_STORE_VAR: &str = "ONYX_STORE";
+
+/// Status expiry for `status set` when neither `--expiry` nor the
+/// `default_status_expiry` config setting is given
+const DEFAULT_STATUS_EXPIRY: std::time::Duration = std::time::Duration::from_mins(10);
+
+/// Plays in a `scrobble logfile` import above which confirmation is asked
+/// for, unless the `import_confirm_threshold` config setting is given
+const DEFAULT_IMPORT_CONFIRM_THRESHOLD: usize = 500;
 
 fn args_styles() -> Styles {
     Styles::styled()
@@ -39,6 +107,45 @@ fn args_styles() -> Styles {
 
 #[derive(Parser, Debug)]
 struct Args {
+    /// Disable colored output (also respects NO_COLOR)
+    #[arg(long, global = true, action)]
+    no_color: bool,
+
+    /// Fail immediately on commands that need network access
+    #[arg(long, global = true, action)]
+    offline: bool,
+
+    /// Timeout in seconds for each network request (resolution, scrobbling, status)
+    #[arg(long, global = true)]
+    timeout: Option<u64>,
+
+    /// Times to retry resolving a handle after a network failure, with a
+    /// growing delay between attempts, up to 10
+    #[arg(long, global = true, default_value_t = 2, value_parser = clap::value_parser!(u32).range(..=10))]
+    resolve_retries: u32,
+
+    /// Proxy URL for all requests, overriding HTTP_PROXY/HTTPS_PROXY/ALL_PROXY
+    #[arg(long, global = true)]
+    proxy: Option<String>,
+
+    /// Ignore cached handle resolutions, refreshing them from the network
+    #[arg(long, global = true, action)]
+    no_cache: bool,
+
+    /// User-Agent header sent with requests, defaults to onyx/<version>
+    #[arg(long, global = true)]
+    user_agent: Option<String>,
+
+    /// How to print results, tables are for `auth whoami`, `status show` and
+    /// `scrobble list`, under `json` success reports and errors are JSON too
+    #[arg(long, global = true, value_enum, default_value_t = OutputFormat::Plain)]
+    format: OutputFormat,
+
+    /// Serve Prometheus metrics on this address while the command runs
+    #[cfg(feature = "metrics")]
+    #[arg(long, global = true)]
+    metrics_addr: Option<std::net::SocketAddr>,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -54,38 +161,143 @@ enum Commands {
 
     /// Scrobble tracks
     Scrobble {
+        #[command(flatten)]
+        webhook: WebhookArgs,
+
+        /// Don't record submitted plays in the local history file
+        #[arg(long, global = true)]
+        no_history: bool,
+
+        /// Look up missing MusicBrainz recording IDs by ISRC, and artist IDs by
+        /// name, before submitting
+        #[arg(long, global = true)]
+        lookup: bool,
+
+        /// Submit track, release and artist names as given, without trimming
+        /// surrounding whitespace
+        #[arg(long, global = true)]
+        no_trim: bool,
+
+        /// Refuse plays dated more than a few minutes in the future, instead
+        /// of submitting them with a warning
+        #[arg(long, global = true)]
+        strict: bool,
+
+        /// Record where the plays came from, e.g. `spotify` for imported
+        /// history, to tell them apart from live scrobbles later
+        #[arg(long, global = true)]
+        source: Option<String>,
+
         #[command(subcommand)]
         command: ScrobbleCommands,
     },
 
     /// View and manage listening status
     Status {
+        #[command(flatten)]
+        record: StatusRecordArgs,
+
         #[command(subcommand)]
         command: StatusCommands,
     },
+
+    /// Inspect raw records in any collection
+    Records {
+        #[command(subcommand)]
+        command: RecordsCommands,
+    },
+
+    /// Check the config directory, keyring, session, resolver and PDS, and
+    /// report what's broken
+    Doctor,
 }
 
 #[derive(Subcommand, Debug)]
 enum AuthCommands {
     /// Login with an ATProto handle or DID
     Login {
-        /// Handle or DID for login
-        handle: String,
+        /// Handle or DID for login, if left out ONYX_HANDLE is used and the
+        /// login runs without prompts, taking the app password from
+        /// ONYX_APP_PASSWORD
+        handle: Option<String>,
 
-        /// Preferred method of storing credentials
-        #[arg(short, long, default_value = "keyring")]
-        store: StoreMethod,
+        /// Preferred method of storing credentials, defaults to ONYX_STORE,
+        /// the current session's, or keyring
+        #[arg(short, long)]
+        store: Option<StoreMethod>,
 
         /// App password to use, OAuth used if left blank
-        #[arg(short, long)]
+        #[arg(short, long, group = "app_password")]
         password: Option<String>,
+
+        /// Read the app password from the first line of a file
+        #[arg(long, group = "app_password")]
+        password_file: Option<PathBuf>,
+
+        /// Replace an existing session for a different account without prompting
+        #[arg(short, long, action)]
+        force: bool,
+
+        /// PDS URL to log in through, for accounts not on the server in their DID document
+        #[arg(long)]
+        pds: Option<url::Url>,
+
+        /// Local port for the OAuth callback server, if the default (4000) is taken
+        #[arg(long, conflicts_with = "app_password")]
+        callback_port: Option<u16>,
+
+        /// Seconds to wait for the browser login to complete (default 300)
+        #[arg(long, conflicts_with = "app_password")]
+        auth_timeout: Option<u64>,
+
+        /// Only check the app password, storing nothing (see ONYX_HANDLE/ONYX_APP_PASSWORD)
+        #[arg(short, long, action, requires = "app_password", conflicts_with_all = ["store", "force"])]
+        ephemeral: bool,
     },
 
     /// Logout of your account
     Logout,
 
+    /// Switch to an account kept when logging in to another, listing the
+    /// kept accounts if none is given
+    Switch {
+        /// Handle or DID of the kept account
+        account: Option<String>,
+    },
+
+    /// Refresh the session's tokens now, instead of when they next expire
+    Refresh,
+
+    /// Export the session and its credentials to a passphrase protected file
+    Export {
+        /// File to write the encrypted bundle to
+        file: PathBuf,
+    },
+
+    /// Import a session exported with `auth export`, replacing the current one
+    Import {
+        /// Encrypted bundle to read
+        file: PathBuf,
+
+        /// Where to store the imported credentials, defaults to the exported session's store
+        #[arg(short, long)]
+        store: Option<StoreMethod>,
+    },
+
+    /// Exit with 0 if logged in, or 2 if not, checking only the stored
+    /// credentials, for scripts
+    Status {
+        /// Print nothing, only set the exit code
+        #[arg(short, long, action)]
+        quiet: bool,
+    },
+
     /// Display logged-in user information
-    Whoami,
+    Whoami {
+        /// Validate the session with the server instead of only checking local credentials
+        #[arg(short, long, action)]
+        check: bool,
+    },
 }
 
 #[derive(Debug, Clone, ValueEnum, Serialize, Deserialize, PartialEq)]
@@ -133,6 +345,18 @@ enum ScrobbleCommands {
         #[arg(long)]
         release_mb_id: Option<String>,
 
+        /// The artist credited on the release/album, if different from the track
+        #[arg(long)]
+        album_artist: Option<String>,
+
+        /// The disc number of the track within the release/album
+        #[arg(long)]
+        disc_number: Option<i64>,
+
+        /// Mark the play as loved
+        #[arg(long, action)]
+        love: bool,
+
         /// The URL associated with the track
         #[arg(short, long)]
         origin_url: Option<String>,
@@ -152,19 +376,198 @@ enum ScrobbleCommands {
         /// Distinguishing information for release variants
         #[arg(long)]
         release_discriminant: Option<String>,
+
+        /// Scrobble the track this many times back to back, up to 1000, the
+        /// last play starting at the played time
+        #[arg(
+            long,
+            value_parser = clap::value_parser!(u32).range(1..=i64::from(MAX_PLAY_COUNT)),
+            requires = "duration"
+        )]
+        repeat: Option<u32>,
+
+        /// Also set the track as your status, until it ends when `--duration`
+        /// is given
+        #[arg(long, action, conflicts_with = "repeat")]
+        set_status: bool,
+
+        /// Don't echo the submitted track, artists and played time
+        #[arg(short, long, action)]
+        quiet: bool,
     },
 
     /// Scrobble tracks from a log file
     Logfile {
-        /// Log file path
-        log: PathBuf,
+        /// Log file paths, merged and submitted in chronological order, `-`
+        /// reads a log from standard input
+        #[arg(required = true, num_args = 1..)]
+        logs: Vec<PathBuf>,
+
+        /// Log file format
+        log_format: LogFormat,
+
+        /// Delete the log files after processing
+        #[arg(short, long, action)]
+        delete: bool,
+
+        /// Print a line per track instead of a progress bar
+        #[arg(short, long, action)]
+        verbose: bool,
+
+        /// Only submit the first N plays, after merging
+        #[arg(short, long, conflicts_with = "delete")]
+        limit: Option<usize>,
+
+        /// Don't ask for confirmation before a large import
+        #[arg(short, long, action)]
+        yes: bool,
+
+        /// Submit entries the log marks as skipped too, to a separate skip
+        /// collection rather than the feed
+        #[arg(long, action)]
+        record_skips: bool,
+
+        /// After the import, show the top artists and the time span of the
+        /// submitted plays
+        #[arg(long, action)]
+        summary: bool,
+
+        /// Only submit plays after the latest one already imported to this
+        /// account from a log, according to the history file
+        #[arg(long, action)]
+        since_last: bool,
+
+        /// Leave out entries that fail to parse, listing their line numbers
+        /// at the end, instead of failing the whole log, not for Maloja and
+        /// Pano exports which are read whole
+        #[arg(long, action, conflicts_with = "delete")]
+        skip_errors: bool,
+
+        /// Only submit plays by this artist, can be given more than once,
+        /// ignoring case
+        #[arg(long = "include-artist", value_name = "ARTIST")]
+        include_artists: Vec<String>,
+
+        /// Leave out plays by this artist, can be given more than once,
+        /// ignoring case
+        #[arg(long = "exclude-artist", value_name = "ARTIST")]
+        exclude_artists: Vec<String>,
+
+        /// Shift every play's time by a signed offset such as `+2h` or
+        /// `-1d`, for logs from a device with its clock set wrong
+        #[arg(long, value_parser = parse_time_offset, allow_hyphen_values = true)]
+        time_offset: Option<chrono::Duration>,
+
+        #[command(flatten)]
+        delimited: DelimitedArgs,
+    },
+
+    /// Watch a directory and submit new plays from logs written to it
+    ///
+    /// Submitted plays are remembered in the history file, so this can't be
+    /// combined with `--no-history`.
+    ///
+    /// Under `--format json` each log submitted is reported as one line of
+    /// JSON.
+    Watch {
+        /// Directory to watch for new or changed log files
+        dir: PathBuf,
 
         /// Log file format
         log_format: LogFormat,
 
-        /// Delete the log file after processing
+        /// Delete log files once all their plays are submitted
         #[arg(short, long, action)]
         delete: bool,
+
+        /// Seconds a log must go unchanged before it's read
+        #[arg(long, default_value_t = 5)]
+        settle: u64,
+
+        #[command(flatten)]
+        delimited: DelimitedArgs,
+    },
+
+    /// Convert log files to play records as NDJSON, without submitting them
+    Export {
+        /// Log file paths, merged in chronological order, `-` reads a log from
+        /// standard input
+        #[arg(required = true, num_args = 1..)]
+        logs: Vec<PathBuf>,
+
+        /// Log file format
+        log_format: LogFormat,
+
+        /// Output file, defaults to standard output
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        #[command(flatten)]
+        delimited: DelimitedArgs,
+    },
+
+    /// Back up every play in a user's feed to a file as NDJSON, which the
+    /// `json` log format reads back in
+    ///
+    /// Progress is checkpointed after each page, so running the same command
+    /// again after an interruption resumes the export.
+    ExportRepo {
+        /// File to write the plays to
+        file: PathBuf,
+
+        /// Handle or DID to export, defaults to the logged in user
+        #[arg(long)]
+        handle: Option<String>,
+    },
+
+    /// List plays from a user's feed, newest first
+    List {
+        /// Handle or DID to query, defaults to the logged in user
+        #[arg(long)]
+        handle: Option<String>,
+
+        /// Maximum number of plays to fetch
+        #[arg(short, long, default_value_t = 20)]
+        limit: usize,
+
+        /// Show the record URI under each play, not with table output
+        #[arg(short, long, conflicts_with = "table")]
+        verbose: bool,
+
+        /// Show plays as a table sized to the terminal, the same as
+        /// `--format table`
+        #[arg(short, long)]
+        table: bool,
+    },
+
+    /// Delete your plays with a played time in a date range
+    Purge {
+        /// Delete plays at or after this time (RFC 3339 format)
+        #[arg(long, required_unless_present = "until")]
+        since: Option<chrono::DateTime<chrono::FixedOffset>>,
+
+        /// Delete plays before this time (RFC 3339 format)
+        #[arg(long)]
+        until: Option<chrono::DateTime<chrono::FixedOffset>>,
+
+        /// Confirm the deletion, without it matching plays are only counted
+        #[arg(short, long, action)]
+        yes: bool,
+    },
+
+    /// Delete plays you submitted, found by track name in the local history
+    /// file
+    Delete {
+        /// Text to look for in track names, ignoring case
+        query: String,
+
+        /// Only match plays with this played time (RFC 3339 format)
+        #[arg(long)]
+        played: Option<chrono::DateTime<chrono::FixedOffset>>,
+
+        /// Confirm the deletion, without it matching plays are only listed
+        #[arg(short, long, action)]
+        yes: bool,
     },
 
     /// Scrobble tracks interactively
@@ -175,12 +578,104 @@ enum ScrobbleCommands {
     },
 }
 
+#[derive(clap::Args, Debug)]
+struct DelimitedArgs {
+    /// Column mapping for generic-delimited logs, e.g. `artist=0,track=2,timestamp=5`,
+    /// a `play_count` column repeats each row that many times
+    #[arg(long)]
+    columns: Option<ColumnMap>,
+
+    /// Field delimiter for generic-delimited logs (tab, comma, semicolon, pipe, or a character)
+    #[arg(long, default_value = "comma", value_parser = parse_delimiter)]
+    delimiter: u8,
+
+    /// Skip the first row of generic-delimited logs as a header
+    #[arg(long, action)]
+    header: bool,
+}
+
+#[derive(clap::Args, Debug)]
+struct WebhookArgs {
+    /// URL to POST a JSON event to after each successful scrobble
+    #[arg(long, global = true)]
+    webhook: Option<url::Url>,
+
+    /// Header sent with webhook requests, e.g. `Authorization: Bearer <token>`
+    #[arg(long, global = true, value_parser = parse_header, requires = "webhook")]
+    webhook_header: Option<(String, String)>,
+
+    /// Timeout in seconds for webhook requests
+    #[arg(long, global = true, default_value_t = 10)]
+    webhook_timeout: u64,
+}
+
+#[derive(clap::Args, Debug)]
+struct StatusRecordArgs {
+    /// Collection NSID of the status record, defaults to fm.teal.alpha.actor.status
+    #[arg(long, global = true, value_parser = parse_nsid)]
+    collection: Option<Nsid<'static>>,
+
+    /// Record key of the status record, defaults to `self`
+    #[arg(long, global = true)]
+    rkey: Option<String>,
+}
+
+impl StatusRecordArgs {
+    fn manager(
+        &self,
+        ident: &str,
+        http: HttpOptions,
+        cache: HandleCache,
+    ) -> Result<StatusManager, OnyxError> {
+        Ok(StatusManager::try_new(ident, http, cache)?
+            .with_record(self.collection.clone(), self.rkey.clone()))
+    }
+}
+
+impl WebhookArgs {
+    fn webhook(self, http: &HttpOptions) -> Result<Option<Webhook>, OnyxError> {
+        self.webhook
+            .map(|url| {
+                Webhook::new(
+                    url,
+                    self.webhook_header,
+                    std::time::Duration::from_secs(self.webhook_timeout),
+                    http,
+                )
+            })
+            .transpose()
+    }
+}
+
+impl DelimitedArgs {
+    fn parser(self) -> Option<DelimitedParser> {
+        self.columns
+            .map(|c| DelimitedParser::new(c, self.delimiter, self.header))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    /// Human readable text
+    Plain,
+    /// JSON, one document per result
+    Json,
+    /// A table sized to the terminal
+    Table,
+}
+
 #[derive(Debug, Clone, ValueEnum)]
 enum LogFormat {
     /// Use AudioScrobbler log format
     AudioScrobbler,
     /// Use newline-delimited JSON format
     Json,
+    /// Use Maloja JSON export format
+    Maloja,
+    /// Use Pano Scrobbler JSON export format
+    Pano,
+    /// Use a delimited format described by `--columns`
+    GenericDelimited,
 }
 
 #[allow(clippy::large_enum_variant)]
@@ -199,12 +694,47 @@ enum StatusCommands {
         /// Display all status fields
         #[arg(short, long, action)]
         full: bool,
+
+        /// List artists one per line, with their IDs indented under them
+        /// when used with `--full`
+        #[arg(long, action)]
+        long: bool,
+
+        /// Exit with code 6 when nothing is playing
+        #[arg(long, action, conflicts_with = "watch")]
+        exit_code: bool,
+
+        /// Print `field,value` rows as CSV, quoted per RFC 4180, with a row
+        /// per artist
+        #[arg(long, action, conflicts_with_all = ["watch", "long"])]
+        csv: bool,
+
+        /// Format for displayed times, as a strftime string
+        #[arg(long, value_parser = parse_date_format, conflicts_with = "twelve_hour")]
+        date_format: Option<String>,
+
+        /// Display times with a 12-hour clock
+        #[arg(long = "12-hour", action)]
+        twelve_hour: bool,
+
+        /// Keep polling the status, highlighting fields as they change
+        #[arg(short, long, action)]
+        watch: bool,
+
+        /// Seconds between polls in watch mode
+        #[arg(short, long, default_value_t = 15, requires = "watch")]
+        interval: u64,
     },
 
-    /// Set user playing status
+    /// Set user playing status, replacing the whole current status
+    ///
+    /// Fields that aren't given are left unset, and a field given as an empty
+    /// string is cleared. With `--merge`, fields that aren't given are kept
+    /// from the current status instead.
     Set {
         /// The name of the track
-        track_name: String,
+        #[arg(required_unless_present_any = ["interactive", "merge"])]
+        track_name: Option<String>,
 
         /// The MusicBrainz ID of the track
         #[arg(long)]
@@ -246,129 +776,1084 @@ enum StatusCommands {
         #[arg(short, long)]
         played_time: Option<chrono::DateTime<chrono::FixedOffset>>,
 
+        /// Distinguishing information for track variants
+        #[arg(long)]
+        track_discriminant: Option<String>,
+
+        /// Distinguishing information for release variants
+        #[arg(long)]
+        release_discriminant: Option<String>,
+
         /// Time of status creation, defaults to current time
         #[arg(short, long)]
         time: Option<chrono::DateTime<chrono::FixedOffset>>,
 
-        /// Time of status expiry, defaults to start time + 10 minutes
+        /// Time of status expiry, defaults to start time + `default_status_expiry` from the config (10 minutes)
         #[arg(short, long)]
         expiry: Option<chrono::DateTime<chrono::FixedOffset>>,
+
+        /// Start the status now, expiring when the track ends (or after 5 minutes without --duration)
+        #[arg(short, long, action, conflicts_with = "time")]
+        now: bool,
+
+        /// Keep refreshing the status until the track ends, clearing it on Ctrl-C
+        #[arg(short, long, action)]
+        keep_alive: bool,
+
+        /// Prompt for the track, artists, album and duration, defaulting to the current status
+        #[arg(short, long, action)]
+        interactive: bool,
+
+        /// Print the status record that would be written, without setting it
+        #[arg(long, action, conflicts_with = "keep_alive")]
+        dry_run: bool,
+
+        /// Keep fields that aren't given from the current status
+        #[arg(short, long, action, conflicts_with = "interactive")]
+        merge: bool,
     },
 
     /// Clear current playing status
     Clear,
+
+    /// Compare what two users are currently playing
+    Compare {
+        /// First handle or DID
+        first: String,
+
+        /// Second handle or DID
+        second: String,
+
+        /// Display raw status without processing
+        #[arg(short, long, action)]
+        raw: bool,
+
+        /// Display all status fields
+        #[arg(short, long, action)]
+        full: bool,
+    },
+
+    /// Periodically record a user's status to a timeline file
+    Log {
+        /// Handle or DID to query
+        #[arg(long)]
+        handle: Option<String>,
+
+        /// Time between polls, e.g. `30s`, `5m` or `1h`
+        #[arg(short, long, default_value = "30s", value_parser = parse_interval)]
+        interval: std::time::Duration,
+
+        /// Timeline file, new snapshots are appended as NDJSON
+        #[arg(short, long)]
+        output: PathBuf,
+    },
 }
 
-fn get_auth() -> Result<Authenticator, OnyxError> {
-    let config_dir = dirs::config_dir().unwrap().join("onyx");
-    Authenticator::try_new("onyx", &config_dir)
+#[derive(Subcommand, Debug)]
+enum RecordsCommands {
+    /// List records in a collection as JSON, newest first
+    List {
+        /// Collection NSID to list, e.g. fm.teal.alpha.feed.play
+        #[arg(long, value_parser = parse_nsid)]
+        collection: Nsid<'static>,
+
+        /// Handle or DID whose records to list, defaults to your own
+        #[arg(long)]
+        handle: Option<String>,
+
+        /// Maximum number of records to show
+        #[arg(short, long, default_value_t = 20)]
+        limit: usize,
+    },
 }
 
-async fn get_session() -> Result<GenericSession, OnyxError> {
-    let auth = get_auth()?;
-    auth.restore().await
+fn get_config_dir() -> PathBuf {
+    match std::env::var_os(CONFIG_DIR_VAR).filter(|d| !d.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => dirs::config_dir().unwrap().join("onyx"),
+    }
 }
 
-fn get_command() -> clap::Command {
-    Args::command().styles(args_styles())
+fn get_config() -> Result<Config, OnyxError> {
+    Config::load(&get_config_dir().join("config.toml"))
 }
 
-fn generate_client_version() -> String {
-    format!("v{}", env!("CARGO_PKG_VERSION"))
+/// The handle to show for a session, `preferred_handle` from the config if
+/// the account has it, otherwise its first handle
+fn session_handle(session: &AuthSession) -> Option<&str> {
+    let preferred = get_config().ok().and_then(|c| c.preferred_handle);
+    session.handle(preferred.as_deref())
 }
 
-fn parse_artist_list(
-    artist_names: Option<String>,
-    artist_mb_ids: Option<String>,
-) -> Result<Option<Vec<Artist>>, OnyxError> {
-    Ok(match artist_names {
-        Some(names) => {
-            let mut artists = Vec::new();
+fn get_history(no_history: bool) -> Option<History> {
+    if no_history || std::fs::create_dir_all(get_config_dir()).is_err() {
+        return None;
+    }
 
-            let names: Vec<&str> = names.split(",").collect();
-            for name in names {
-                let name = name.trim();
+    Some(History::new(&get_config_dir().join("history.ndjson")))
+}
 
-                if name.is_empty() {
-                    continue;
-                }
+fn get_handle_cache(no_cache: bool) -> HandleCache {
+    let config_dir = get_config_dir();
+    if std::fs::create_dir_all(&config_dir).is_err() {
+        return HandleCache::disabled();
+    }
 
-                artists.push(Artist {
-                    artist_name: name.to_owned(),
-                    artist_mb_id: None,
-                });
-            }
+    HandleCache::new(
+        config_dir.join("handles.json"),
+        chrono::Duration::hours(HANDLE_CACHE_TTL_HOURS),
+        !no_cache,
+    )
+}
 
-            if let Some(mb_ids) = artist_mb_ids {
-                let mb_ids: Vec<&str> = mb_ids.split(",").collect();
+fn get_auth(http: &HttpOptions, cache: &HandleCache) -> Result<Authenticator, OnyxError> {
+    Authenticator::try_new("onyx", &get_config_dir(), http.clone(), cache.clone())
+}
 
-                if mb_ids.len() > artists.len() {
-                    return Err(OnyxError::Parse(
-                        "cannot be more `artist_mb_ids` than `artist_names`".into(),
-                    ));
-                }
+/// The account a command acts as, see `get_account`
+struct Account {
+    session: GenericSession,
+    did: String,
+    /// Handle to show for the account, if it has one
+    handle: Option<String>,
+}
 
-                for i in 0..mb_ids.len() {
-                    let id = mb_ids[i].trim();
+/// Log in to the account commands act as, by restoring the stored session,
+/// or without one with ONYX_HANDLE and ONYX_APP_PASSWORD if set, storing
+/// nothing
+///
+/// The stored session comes first since `auth login` reads the same
+/// variables, so a session logged in from them is used rather than a fresh
+/// ephemeral login on every command.
+async fn get_account(
+    offline: bool,
+    http: &HttpOptions,
+    cache: &HandleCache,
+) -> Result<Account, OnyxError> {
+    require_online(offline)?;
+    let auth = get_auth(http, cache)?;
+
+    let session_info = match auth.get_session_info() {
+        Ok(session_info) => session_info,
+        Err(e) => {
+            let (Ok(ident), Ok(password)) = (
+                std::env::var(EPHEMERAL_HANDLE_VAR),
+                std::env::var(EPHEMERAL_PASSWORD_VAR),
+            ) else {
+                return Err(e);
+            };
+
+            let session = auth.login_ephemeral(&ident, &password, None).await?;
+            let did = session
+                .session_info()
+                .await
+                .map(|(did, _)| did.to_string())
+                .ok_or_else(|| OnyxError::Auth(format!("no session created for {}", ident)))?;
+            let handle = (ident != did).then_some(ident);
+
+            return Ok(Account {
+                session,
+                did,
+                handle,
+            });
+        }
+    };
 
-                    if !id.is_empty() {
-                        artists[i].artist_mb_id = Some(id.to_owned());
-                    }
-                }
-            }
+    let session = auth.restore().await?;
 
-            Some(artists)
-        }
-        None => None,
+    Ok(Account {
+        session,
+        handle: session_handle(&session_info).map(str::to_owned),
+        did: session_info.did,
     })
 }
 
-async fn run_onyx() -> Result<(), OnyxError> {
-    let mut matches = get_command().get_matches();
-    let args = Args::from_arg_matches_mut(&mut matches).unwrap();
+fn require_online(offline: bool) -> Result<(), OnyxError> {
+    if offline {
+        return Err(OnyxError::Network(
+            "command requires network access, but offline mode is enabled".to_string(),
+        ));
+    }
 
-    match args.command {
-        Commands::Auth { command } => match command {
-            AuthCommands::Login {
-                handle,
-                store,
-                password,
-            } => {
-                let auth = get_auth()?;
-                auth.login(&handle, store, password).await?;
+    Ok(())
+}
 
-                let session_info = auth.get_session_info()?;
+fn get_command() -> clap::Command {
+    let command = Args::command().styles(args_styles());
 
-                println!(
-                    "{}: logged in {}{}",
-                    "success".green().bold(),
-                    (session_info
-                        .handles
-                        .first()
-                        .unwrap_or(&"(no handle)".red().to_string()))
-                    .magenta(),
-                    format!(", {}", session_info.did).dimmed()
-                );
-            }
-            AuthCommands::Logout => {
-                let auth = get_auth()?;
-                let session_info = auth.get_session_info()?;
+    // clap renders help before we get parsed args, so check for the flag early
+    if std::env::args_os().any(|a| a == "--no-color") {
+        command.color(clap::ColorChoice::Never)
+    } else {
+        command
+    }
+}
 
-                auth.logout().await?;
+fn color_disabled(no_color: bool) -> bool {
+    no_color || std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty())
+}
 
-                println!(
-                    "{}: logged out {}, {}",
-                    "success".green().bold(),
-                    (session_info
-                        .handles
-                        .first()
-                        .unwrap_or(&"(no handle)".red().to_string())),
-                    session_info.did,
-                );
+fn configure_color(no_color: bool) {
+    // owo_colors already honors NO_COLOR and non-TTY stdout via supports-color,
+    // this just forces it off when requested explicitly
+    if color_disabled(no_color) {
+        owo_colors::set_override(false);
+    }
+}
+
+fn format_played_time(play: &Play) -> String {
+    play.played_time
+        .map(|t| {
+            t.with_timezone(&chrono::Local)
+                .format(DEFAULT_DATE_FORMAT)
+                .to_string()
+        })
+        .unwrap_or_else(|| "unknown time".to_string())
+}
+
+/// Track, artists and played time of a submitted play, for echoing it back
+fn format_submitted_play(play: &Play) -> String {
+    format!(
+        "{} - {}, played {}",
+        format_play_artists(play).if_supports_color(Stdout, |t| t.magenta()),
+        play.track_name,
+        play.played_time
+            .map_or("now".to_string(), |_| format_played_time(play))
+    )
+}
+
+fn format_play_artists(play: &Play) -> String {
+    match (&play.artists, &play.artist_names) {
+        (Some(artists), _) => {
+            let names: Vec<&str> = artists.iter().map(|a| a.artist_name.as_str()).collect();
+            names.join(", ")
+        }
+        (None, Some(names)) => names.join(", "),
+        (None, None) => String::new(),
+    }
+}
+
+/// A table drawn with box characters when stdout is a terminal, wrapping to
+/// its width
+fn new_table(no_color: bool) -> Table {
+    let mut table = Table::new();
+
+    if std::io::stdout().is_terminal() {
+        table.load_preset(presets::UTF8_FULL_CONDENSED);
+    } else {
+        table.load_preset(presets::ASCII_FULL_CONDENSED);
+    }
+
+    // styling is only applied on a TTY, this also turns it off for --no-color
+    if no_color {
+        table.force_no_tty();
+    }
+
+    table.set_content_arrangement(ContentArrangement::Dynamic);
+    table
+}
+
+/// Print plays as a table, wrapped to the terminal width when stdout is one
+fn print_plays_table(records: &[PlayRecord], no_color: bool) {
+    let mut table = new_table(no_color);
+
+    table.set_header(
+        ["time", "track", "artists", "album"].map(|h| Cell::new(h).add_attribute(Attribute::Bold)),
+    );
+
+    for record in records {
+        let play = &record.play;
+        table.add_row([
+            Cell::new(format_played_time(play)),
+            Cell::new(&play.track_name),
+            Cell::new(format_play_artists(play)).fg(Color::Magenta),
+            Cell::new(play.release_name.as_deref().unwrap_or_default()),
+        ]);
+    }
+
+    println!("{table}");
+}
+
+/// Print labelled fields as a two column table
+fn print_fields_table(fields: &[(&str, String)], no_color: bool) {
+    let mut table = new_table(no_color);
+
+    for (label, value) in fields {
+        table.add_row([
+            Cell::new(label).add_attribute(Attribute::Bold),
+            Cell::new(value),
+        ]);
+    }
+
+    println!("{table}");
+}
+
+/// Print labelled fields as `field,value` CSV rows under a header, quoting
+/// values with commas, quotes or line breaks in them
+fn print_fields_csv(fields: &[(&str, String)]) -> Result<(), OnyxError> {
+    let mut writer = csv::Writer::from_writer(std::io::stdout());
+
+    let rows = std::iter::once(("field", "value"))
+        .chain(fields.iter().map(|(label, value)| (*label, value.as_str())));
+    for (label, value) in rows {
+        writer
+            .write_record([label, value])
+            .map_err(|e| OnyxError::Io(e.to_string()))?;
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Print the outcome of a command that only reports success, as `value`
+/// under `--format json`, otherwise as a success line with `message`
+fn print_success(
+    output: OutputFormat,
+    message: impl std::fmt::Display,
+    value: serde_json::Value,
+) -> Result<(), OnyxError> {
+    if output == OutputFormat::Json {
+        println!("{}", serde_json::to_string_pretty(&value)?);
+    } else {
+        println!("{}: {}", label(Label::Success), message);
+    }
+
+    Ok(())
+}
+
+/// Print a status in the chosen format, JSON is a single line per status in
+/// watch mode so the output can be read as NDJSON
+#[allow(clippy::too_many_arguments)]
+fn print_status(
+    status: &Status,
+    output: OutputFormat,
+    watch: bool,
+    no_color: bool,
+    raw: bool,
+    full: bool,
+    long: bool,
+    date_format: Option<&str>,
+) -> Result<(), OnyxError> {
+    match output {
+        OutputFormat::Plain => status.display(raw, full, long, date_format),
+        OutputFormat::Json if watch => println!("{}", serde_json::to_string(status)?),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(status)?),
+        OutputFormat::Table if status.is_empty() && !raw => println!("nothing playing right now"),
+        OutputFormat::Table => print_fields_table(
+            &status.fields(raw, full, date_format),
+            color_disabled(no_color),
+        ),
+    }
+
+    Ok(())
+}
+
+fn prompt(message: &str) -> Result<String, OnyxError> {
+    print!("{}", message);
+    std::io::stdout().flush()?;
+
+    let mut input = String::new();
+    std::io::stdin().read_line(&mut input)?;
+    Ok(input.trim().to_string())
+}
+
+/// Prompt for a value, showing `default` in brackets and keeping it when the
+/// input is empty
+fn prompt_with_default(label: &str, default: Option<&str>) -> Result<Option<String>, OnyxError> {
+    let input = match default {
+        Some(default) => prompt(&format!("{} [{}]: ", label, default))?,
+        None => prompt(&format!("{}: ", label))?,
+    };
+
+    Ok(if input.is_empty() {
+        default.map(|d| d.to_string())
+    } else {
+        Some(input)
+    })
+}
+
+/// Fill in the main status fields interactively, defaulting to values given
+/// as flags, then to the current status
+fn prompt_status_fields(
+    mut play: PlayView,
+    current: Option<&PlayView>,
+) -> Result<PlayView, OnyxError> {
+    if !std::io::stdin().is_terminal() {
+        return Err(OnyxError::Other(
+            "`--interactive` needs a terminal to prompt on".into(),
+        ));
+    }
+
+    if let Some(current) = current {
+        if play.track_name.is_empty() {
+            play.track_name = current.track_name.clone();
+        }
+        if play.artists.is_empty() {
+            play.artists = current.artists.clone();
+        }
+        play.release_name = play.release_name.or(current.release_name.clone());
+        play.duration = play.duration.or(current.duration);
+    }
+
+    play.track_name = loop {
+        let default = (!play.track_name.is_empty()).then_some(play.track_name.as_str());
+        match prompt_with_default("track", default)? {
+            Some(name) => break name,
+            None => println!("a track name is required"),
+        }
+    };
+
+    let artist_names: Vec<&str> = play
+        .artists
+        .iter()
+        .map(|a| a.artist_name.as_str())
+        .collect();
+    let artist_names = artist_names.join(", ");
+    let default = (!artist_names.is_empty()).then_some(artist_names.as_str());
+    if let Some(input) = prompt_with_default("artists", default)?
+        && input != artist_names
+    {
+        // new names, so the existing MusicBrainz IDs no longer apply
+        play.artists = parse_artist_list(Some(input), None)?.unwrap_or_default();
+    }
+
+    play.release_name = prompt_with_default("album", play.release_name.as_deref())?;
+
+    let duration = play.duration.map(|d| d.to_string());
+    play.duration = loop {
+        match prompt_with_default("duration (seconds)", duration.as_deref())? {
+            Some(input) => match input.parse() {
+                Ok(d) => break Some(d),
+                Err(_) => println!("invalid duration '{}'", input),
+            },
+            None => break None,
+        }
+    };
+
+    Ok(play)
+}
+
+/// What to do with the current session when logging in to another account
+#[derive(Debug, PartialEq)]
+enum SessionConflict {
+    /// Keep the current session aside, to switch back to with `auth switch`
+    Keep,
+    Replace,
+    Cancel,
+}
+
+/// Whether `ident`, a handle or DID, is the account `session` is for
+fn is_session_account(session: &AuthSession, ident: &str) -> bool {
+    session.did == ident
+        || session
+            .handles
+            .iter()
+            .any(|h| h.eq_ignore_ascii_case(ident))
+}
+
+fn confirm_replace_session(existing: &AuthSession) -> Result<SessionConflict, OnyxError> {
+    if !std::io::stdin().is_terminal() {
+        return Err(OnyxError::Other(
+            format!(
+                "already logged in as {}, use `--force` to replace the session",
+                existing.did
+            )
+            .into(),
+        ));
+    }
+
+    let name = session_handle(existing).unwrap_or(&existing.did);
+    println!(
+        "{}: already logged in as {}, {}",
+        label(Label::Warning),
+        name,
+        existing.did
+    );
+
+    let answer =
+        prompt("keep both accounts or replace the existing session? [k]eep/[r]eplace/[c]ancel: ")?;
+    Ok(match answer.to_lowercase().as_str() {
+        "k" | "keep" => SessionConflict::Keep,
+        "r" | "replace" => SessionConflict::Replace,
+        _ => SessionConflict::Cancel,
+    })
+}
+
+/// Read an app password from the first line of `path`, without trailing whitespace
+fn read_password_file(path: &Path) -> Result<String, OnyxError> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| OnyxError::Io(format!("failed to read {}: {}", path.display(), e)))?;
+    let password = contents.lines().next().unwrap_or_default().trim_end();
+
+    if password.is_empty() {
+        return Err(OnyxError::Auth(format!(
+            "no app password found in {}",
+            path.display()
+        )));
+    }
+
+    Ok(password.to_string())
+}
+
+fn generate_client_version() -> String {
+    format!("v{}", env!("CARGO_PKG_VERSION"))
+}
+
+fn parse_artist_list(
+    artist_names: Option<String>,
+    artist_mb_ids: Option<String>,
+) -> Result<Option<Vec<Artist>>, OnyxError> {
+    Ok(match artist_names {
+        Some(names) => {
+            let mut artists = Vec::new();
+
+            let names: Vec<&str> = names.split(",").collect();
+            for name in names {
+                let name = name.trim();
+
+                if name.is_empty() {
+                    continue;
+                }
+
+                artists.push(Artist {
+                    artist_name: name.to_owned(),
+                    artist_mb_id: None,
+                });
+            }
+
+            if let Some(mb_ids) = artist_mb_ids {
+                let mb_ids: Vec<&str> = mb_ids.split(",").collect();
+
+                if mb_ids.len() > artists.len() {
+                    return Err(OnyxError::Parse(
+                        "cannot be more `artist_mb_ids` than `artist_names`".into(),
+                    ));
+                }
+
+                for i in 0..mb_ids.len() {
+                    let id = mb_ids[i].trim();
+
+                    if !id.is_empty() {
+                        artists[i].artist_mb_id = Some(id.to_owned());
+                    }
+                }
             }
-            AuthCommands::Whoami => {
-                let auth = get_auth()?;
-                let session = auth.restore().await;
+
+            Some(artists)
+        }
+        None if artist_mb_ids.is_some() => {
+            return Err(OnyxError::Parse(
+                "`artist_mb_ids` given without `artist_names`".into(),
+            ));
+        }
+        None => None,
+    })
+}
+
+fn parse_origin_url(origin_url: Option<String>) -> Result<Option<String>, OnyxError> {
+    let Some(origin_url) = origin_url.filter(|u| !u.trim().is_empty()) else {
+        return Ok(None);
+    };
+
+    url::Url::parse(origin_url.trim())
+        .map(|u| Some(u.to_string()))
+        .map_err(|e| OnyxError::Parse(format!("invalid `origin_url` '{}': {}", origin_url, e)))
+}
+
+fn parse_nsid(s: &str) -> Result<Nsid<'static>, String> {
+    Nsid::new_owned(s).map_err(|e| e.to_string())
+}
+
+fn parse_header(s: &str) -> Result<(String, String), String> {
+    let (name, value) = s
+        .split_once(':')
+        .ok_or_else(|| format!("expected 'Name: value', got '{}'", s))?;
+
+    Ok((name.trim().to_owned(), value.trim().to_owned()))
+}
+
+fn parse_date_format(s: &str) -> Result<String, String> {
+    let valid = chrono::format::StrftimeItems::new(s)
+        .all(|item| !matches!(item, chrono::format::Item::Error));
+
+    if valid {
+        Ok(s.to_owned())
+    } else {
+        Err(format!("invalid date format '{}'", s))
+    }
+}
+
+/// Longest interval `parse_interval` accepts, a week, well past any status
+/// expiry or polling interval and short enough to add to any time
+const MAX_INTERVAL_SECS: u64 = 7 * 24 * 60 * 60;
+
+/// Parse an interval such as `30s`, `5m` or `1h`, bare numbers are seconds
+fn parse_interval(s: &str) -> Result<std::time::Duration, String> {
+    let s = s.trim();
+    let (value, unit) = match s.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => s.split_at(i),
+        None => (s, "s"),
+    };
+
+    let value: u64 = value
+        .parse()
+        .map_err(|_| format!("invalid interval '{}'", s))?;
+    let seconds = match unit {
+        "s" => Some(value),
+        "m" => value.checked_mul(60),
+        "h" => value.checked_mul(60 * 60),
+        _ => return Err(format!("unknown interval unit '{}'", unit)),
+    };
+
+    if seconds == Some(0) {
+        return Err("interval must be greater than zero".into());
+    }
+
+    let seconds = seconds
+        .filter(|&s| s <= MAX_INTERVAL_SECS)
+        .ok_or_else(|| format!("interval '{}' is longer than a week", s))?;
+
+    Ok(std::time::Duration::from_secs(seconds))
+}
+
+/// Parse a signed offset such as `+2h`, `-30m` or `1d`, bare numbers are
+/// seconds and unsigned offsets are forward
+fn parse_time_offset(s: &str) -> Result<chrono::Duration, String> {
+    let s = s.trim();
+    let (negative, rest) = match s.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, s.strip_prefix('+').unwrap_or(s)),
+    };
+    let (value, unit) = match rest.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => rest.split_at(i),
+        None => (rest, "s"),
+    };
+
+    let value: i64 = value
+        .parse()
+        .map_err(|_| format!("invalid time offset '{}'", s))?;
+    let offset = match unit {
+        "s" => chrono::Duration::try_seconds(value),
+        "m" => chrono::Duration::try_minutes(value),
+        "h" => chrono::Duration::try_hours(value),
+        "d" => chrono::Duration::try_days(value),
+        _ => return Err(format!("unknown time offset unit '{}'", unit)),
+    }
+    .ok_or_else(|| format!("time offset '{}' is too large", s))?;
+
+    Ok(if negative { -offset } else { offset })
+}
+
+/// How long a status lasts without an explicit expiry, from the config or
+/// `DEFAULT_STATUS_EXPIRY`
+fn get_status_expiry() -> Result<std::time::Duration, OnyxError> {
+    match get_config()?.default_status_expiry {
+        Some(expiry) => parse_interval(&expiry)
+            .map_err(|e| OnyxError::Parse(format!("invalid default_status_expiry: {}", e))),
+        None => Ok(DEFAULT_STATUS_EXPIRY),
+    }
+}
+
+/// Set a just scrobbled track as the status of the agent's account, lasting
+/// until the track ends if its duration is known
+async fn set_track_status(
+    agent: &Agent<GenericSession>,
+    item: PlayView,
+    http: HttpOptions,
+    cache: HandleCache,
+) -> Result<(), OnyxError> {
+    let time: chrono::DateTime<chrono::FixedOffset> = chrono::Local::now().into();
+    let expiry = match item.duration {
+        Some(duration) => item.played_time.unwrap_or(time) + chrono::Duration::seconds(duration),
+        None => time + get_status_expiry()?,
+    };
+
+    if expiry <= time {
+        println!(
+            "{}: the track has already ended, status not set",
+            label(Label::Warning)
+        );
+        return Ok(());
+    }
+
+    let (did, _) = agent
+        .info()
+        .await
+        .ok_or_else(|| OnyxError::Auth("session has no DID".to_string()))?;
+
+    let status = Status {
+        time,
+        expiry: Some(expiry),
+        item,
+    };
+    StatusManager::try_new(did.as_str(), http, cache)?
+        .set_status(agent, status, None)
+        .await?;
+
+    println!(
+        "{}: status set until {}",
+        label(Label::Success),
+        expiry
+            .with_timezone(&chrono::Local)
+            .format(DEFAULT_DATE_FORMAT)
+    );
+    Ok(())
+}
+
+fn get_lookup(lookup: bool, http: &HttpOptions) -> Result<Option<MusicBrainzClient>, OnyxError> {
+    lookup.then(|| MusicBrainzClient::try_new(http)).transpose()
+}
+
+fn get_http_options(args: &Args) -> HttpOptions {
+    HttpOptions {
+        timeout: args.timeout.map(std::time::Duration::from_secs),
+        proxy: args.proxy.clone(),
+        resolve_retries: args.resolve_retries,
+        user_agent: Some(
+            args.user_agent
+                .clone()
+                .unwrap_or_else(|| format!("onyx/{}", env!("CARGO_PKG_VERSION"))),
+        ),
+    }
+}
+
+async fn run_onyx(args: Args, http: HttpOptions, cache: HandleCache) -> Result<(), OnyxError> {
+    #[cfg(feature = "metrics")]
+    if let Some(addr) = args.metrics_addr {
+        metrics::serve(addr).await?;
+    }
+
+    let offline = args.offline;
+    let no_color = args.no_color;
+    let output = args.format;
+
+    match args.command {
+        Commands::Auth { command } => match command {
+            AuthCommands::Login {
+                handle,
+                store,
+                password,
+                password_file,
+                force,
+                pds,
+                callback_port,
+                auth_timeout,
+                ephemeral,
+            } => {
+                require_online(offline)?;
+
+                // without a handle everything comes from the environment,
+                // for CI where nobody can answer a prompt or open a browser
+                let non_interactive = handle.is_none();
+                let handle = match handle {
+                    Some(handle) => handle,
+                    None => std::env::var(EPHEMERAL_HANDLE_VAR).map_err(|_| {
+                        OnyxError::Auth(format!(
+                            "no handle given, pass one or set {}",
+                            EPHEMERAL_HANDLE_VAR
+                        ))
+                    })?,
+                };
+
+                let password = match password_file {
+                    Some(path) => Some(read_password_file(&path)?),
+                    None if non_interactive => {
+                        password.or_else(|| std::env::var(EPHEMERAL_PASSWORD_VAR).ok())
+                    }
+                    None => password,
+                };
+
+                let env_store = match std::env::var(LOGIN_STORE_VAR) {
+                    Ok(s) => Some(StoreMethod::from_str(&s, true).map_err(|_| {
+                        OnyxError::Parse(format!(
+                            "invalid {} '{}', expected keyring or file",
+                            LOGIN_STORE_VAR, s
+                        ))
+                    })?),
+                    Err(_) => None,
+                };
+
+                let auth = get_auth(&http, &cache)?;
+
+                let store = store
+                    .or(env_store)
+                    .or_else(|| auth.get_session_info().ok().map(|s| s.store))
+                    .unwrap_or(StoreMethod::Keyring);
+
+                if ephemeral {
+                    let password = password.unwrap_or_default();
+                    auth.login_ephemeral(&handle, &password, pds).await?;
+
+                    print_success(
+                        output,
+                        format!(
+                            "app password accepted for {}, nothing was stored",
+                            handle.if_supports_color(Stdout, |t| t.magenta())
+                        ),
+                        serde_json::json!({ "handle": handle, "stored": false }),
+                    )?;
+                    if output == OutputFormat::Json {
+                        return Ok(());
+                    }
+                    println!(
+                        "{}: set {} and {} to run commands while no session is stored",
+                        label(Label::Hint),
+                        EPHEMERAL_HANDLE_VAR,
+                        EPHEMERAL_PASSWORD_VAR
+                    );
+                    return Ok(());
+                }
+
+                let conflict = match auth.get_session_info() {
+                    Ok(existing) if !is_session_account(&existing, &handle) => {
+                        let conflict = if force || non_interactive {
+                            SessionConflict::Replace
+                        } else {
+                            confirm_replace_session(&existing)?
+                        };
+                        Some((existing, conflict))
+                    }
+                    _ => None,
+                };
+
+                let kept = match conflict {
+                    Some((_, SessionConflict::Cancel)) => {
+                        println!("login cancelled");
+                        return Ok(());
+                    }
+                    Some((existing, SessionConflict::Keep)) => {
+                        auth.keep_session()?;
+                        Some(existing.did)
+                    }
+                    _ => None,
+                };
+
+                let res = if non_interactive {
+                    auth.login_non_interactive(&handle, store, password, pds)
+                        .await
+                } else {
+                    let callback = CallbackOptions {
+                        port: callback_port,
+                        timeout: auth_timeout.map(std::time::Duration::from_secs),
+                    };
+                    auth.login(&handle, store, password, pds, callback).await
+                };
+
+                // a failed login leaves the kept account logged in
+                if res.is_err()
+                    && let Some(did) = kept
+                {
+                    auth.switch_session(&did)?;
+                }
+                res?;
+
+                let session_info = auth.get_session_info()?;
+
+                print_success(
+                    output,
+                    format!(
+                        "logged in {}{}",
+                        (session_handle(&session_info).unwrap_or(
+                            &"(no handle)"
+                                .if_supports_color(Stdout, |t| t.red())
+                                .to_string()
+                        ))
+                        .if_supports_color(Stdout, |t| t.magenta()),
+                        format!(", {}", session_info.did).if_supports_color(Stdout, |t| t.dimmed())
+                    ),
+                    serde_json::json!({
+                        "handle": session_handle(&session_info),
+                        "did": session_info.did,
+                        "store": session_info.store,
+                    }),
+                )?;
+            }
+            AuthCommands::Logout => {
+                let auth = get_auth(&http, &cache)?;
+                let session_info = auth.get_session_info()?;
+
+                auth.logout().await?;
+
+                print_success(
+                    output,
+                    format!(
+                        "logged out {}, {}",
+                        (session_handle(&session_info).unwrap_or(
+                            &"(no handle)"
+                                .if_supports_color(Stdout, |t| t.red())
+                                .to_string()
+                        )),
+                        session_info.did,
+                    ),
+                    serde_json::json!({
+                        "handle": session_handle(&session_info),
+                        "did": session_info.did,
+                    }),
+                )?;
+            }
+            AuthCommands::Switch { account } => {
+                let auth = get_auth(&http, &cache)?;
+
+                let Some(account) = account else {
+                    let kept = auth.kept_sessions()?;
+                    if kept.is_empty() {
+                        println!("no kept accounts");
+                    }
+                    for session in kept {
+                        println!(
+                            "{}{}",
+                            session_handle(&session)
+                                .unwrap_or("(no handle)")
+                                .if_supports_color(Stdout, |t| t.magenta()),
+                            format!(", {}", session.did).if_supports_color(Stdout, |t| t.dimmed())
+                        );
+                    }
+                    return Ok(());
+                };
+
+                let session_info = auth.switch_session(&account)?;
+
+                print_success(
+                    output,
+                    format!(
+                        "switched to {}{}",
+                        (session_handle(&session_info).unwrap_or(
+                            &"(no handle)"
+                                .if_supports_color(Stdout, |t| t.red())
+                                .to_string()
+                        ))
+                        .if_supports_color(Stdout, |t| t.magenta()),
+                        format!(", {}", session_info.did).if_supports_color(Stdout, |t| t.dimmed())
+                    ),
+                    serde_json::json!({
+                        "handle": session_handle(&session_info),
+                        "did": session_info.did,
+                        "store": session_info.store,
+                    }),
+                )?;
+            }
+            AuthCommands::Refresh => {
+                let session = get_account(offline, &http, &cache).await?.session;
+                let token = session.refresh().await?;
+
+                let expiry = match token_expiry(&token) {
+                    Some(expiry) => expiry
+                        .with_timezone(&chrono::Local)
+                        .format(DEFAULT_DATE_FORMAT)
+                        .to_string(),
+                    None => "unknown".to_string(),
+                };
+
+                print_success(
+                    output,
+                    format!("session refreshed, access token expires {}", expiry),
+                    serde_json::json!({ "expires": token_expiry(&token) }),
+                )?;
+            }
+            AuthCommands::Export { file } => {
+                let auth = get_auth(&http, &cache)?;
+                let bundle = auth.export_session().await?;
+
+                let passphrase = prompt("passphrase: ")?;
+                if passphrase.is_empty() {
+                    return Err(OnyxError::Auth("passphrase can't be empty".to_string()));
+                }
+                if prompt("confirm passphrase: ")? != passphrase {
+                    return Err(OnyxError::Auth("passphrases don't match".to_string()));
+                }
+
+                std::fs::write(&file, bundle.seal(&passphrase)?)?;
+
+                // the bundle holds tokens, keep it private like the session file
+                #[cfg(unix)]
+                {
+                    use std::os::unix::fs::PermissionsExt;
+                    std::fs::set_permissions(&file, std::fs::Permissions::from_mode(0o0600))?;
+                }
+
+                print_success(
+                    output,
+                    format!(
+                        "exported session for {} to {}",
+                        bundle.session.did,
+                        file.display()
+                    ),
+                    serde_json::json!({ "did": bundle.session.did, "file": file }),
+                )?;
+            }
+            AuthCommands::Import { file, store } => {
+                let auth = get_auth(&http, &cache)?;
+                let contents = std::fs::read_to_string(&file)?;
+
+                let passphrase = prompt("passphrase: ")?;
+                let bundle = bundle::SessionBundle::open(&contents, &passphrase)?;
+
+                let conflict = match auth.get_session_info() {
+                    Ok(existing) if existing.did != bundle.session.did => {
+                        Some((existing.did.clone(), confirm_replace_session(&existing)?))
+                    }
+                    _ => None,
+                };
+
+                let kept = match conflict {
+                    Some((_, SessionConflict::Cancel)) => {
+                        println!("import cancelled");
+                        return Ok(());
+                    }
+                    Some((did, SessionConflict::Keep)) => {
+                        auth.keep_session()?;
+                        Some(did)
+                    }
+                    _ => None,
+                };
+
+                let store = store.unwrap_or_else(|| bundle.session.store.clone());
+                let session_info = match auth.import_session(bundle, store).await {
+                    Ok(session_info) => session_info,
+                    Err(e) => {
+                        if let Some(did) = kept {
+                            auth.switch_session(&did)?;
+                        }
+                        return Err(e);
+                    }
+                };
+
+                print_success(
+                    output,
+                    format!(
+                        "imported session for {}{}",
+                        (session_handle(&session_info).unwrap_or(
+                            &"(no handle)"
+                                .if_supports_color(Stdout, |t| t.red())
+                                .to_string()
+                        ))
+                        .if_supports_color(Stdout, |t| t.magenta()),
+                        format!(", {}", session_info.did).if_supports_color(Stdout, |t| t.dimmed())
+                    ),
+                    serde_json::json!({
+                        "handle": session_handle(&session_info),
+                        "did": session_info.did,
+                        "store": session_info.store,
+                    }),
+                )?;
+            }
+            AuthCommands::Status { quiet } => {
+                // a stored token is enough, an expired one is refreshed on
+                // next use so it still counts as logged in
+                let auth = get_auth(&http, &cache)?;
+                let logged_in = auth.get_session_info().is_ok()
+                    && auth.stored_access_token().await.is_ok_and(|t| t.is_some());
+
+                if !quiet {
+                    println!("{}", if logged_in { "logged-in" } else { "logged-out" });
+                }
+
+                if !logged_in {
+                    return Err(OnyxError::Exit(LOGGED_OUT_EXIT_CODE));
+                }
+            }
+            AuthCommands::Whoami { check } => {
+                let auth = get_auth(&http, &cache)?;
                 let session_info = auth.get_session_info()?;
 
                 let method_str = if session_info.auth == AuthMethod::OAuth {
@@ -377,16 +1862,147 @@ async fn run_onyx() -> Result<(), OnyxError> {
                     "app password"
                 };
 
-                if session.is_ok() {
-                    println!("status: {} via {}", "logged in".green().bold(), method_str);
+                if check {
+                    require_online(offline)?;
+                }
+
+                let token = auth.stored_access_token().await?;
+                // decoded locally, so this works offline and a network failure
+                // can't be mistaken for an expired token
+                let expiry = token.as_ref().and_then(token_expiry);
+                let server = if check {
+                    Some(auth.confirm_session().await)
+                } else {
+                    None
+                };
+
+                if output != OutputFormat::Plain {
+                    let (server_str, server_error) = match &server {
+                        Some(Ok(())) => (Some("confirmed"), None),
+                        Some(Err(e @ OnyxError::Network(_))) => (Some("unreachable"), Some(e)),
+                        Some(Err(e)) => (Some("rejected"), Some(e)),
+                        None => (None, None),
+                    };
+
+                    if output == OutputFormat::Json {
+                        let info = serde_json::json!({
+                            "logged_in": token.is_some(),
+                            "method": method_str,
+                            "token_expiry": expiry.map(|e| e.to_rfc3339()),
+                            "server": server_str,
+                            "server_error": server_error.map(|e| e.to_string()),
+                            "handles": session_info.handles,
+                            "did": session_info.did,
+                            "pds": session_info.pds,
+                        });
+                        println!("{}", serde_json::to_string_pretty(&info)?);
+                        return Ok(());
+                    }
+
+                    let logged_in = if token.is_some() {
+                        "logged in"
+                    } else {
+                        "logged out"
+                    };
+                    let mut fields = vec![
+                        ("status", format!("{} via {}", logged_in, method_str)),
+                        (
+                            "token expiry",
+                            expiry.map_or("unknown".to_string(), |e| {
+                                e.with_timezone(&chrono::Local)
+                                    .format(DEFAULT_DATE_FORMAT)
+                                    .to_string()
+                            }),
+                        ),
+                        (
+                            "server",
+                            match server_error {
+                                Some(e) => format!("{}, {}", server_str.unwrap_or_default(), e),
+                                None => server_str.unwrap_or("not checked").to_string(),
+                            },
+                        ),
+                        ("handles", session_info.handles.join(" ")),
+                        ("did", session_info.did),
+                    ];
+                    if let Some(pds) = session_info.pds {
+                        fields.push(("pds", pds));
+                    }
+
+                    print_fields_table(&fields, color_disabled(no_color));
+                    return Ok(());
+                }
+
+                if token.is_some() {
+                    println!(
+                        "status: {} via {}",
+                        "logged in"
+                            .if_supports_color(Stdout, |t| t.style(Style::new().green().bold())),
+                        method_str
+                    );
                 } else {
-                    println!("status: {} via {}", "logged out".red().bold(), method_str);
+                    println!(
+                        "status: {} via {}",
+                        "logged out"
+                            .if_supports_color(Stdout, |t| t.style(Style::new().red().bold())),
+                        method_str
+                    );
+                }
+
+                if token.is_some() {
+                    match expiry {
+                        Some(expiry) => {
+                            let local = expiry
+                                .with_timezone(&chrono::Local)
+                                .format(DEFAULT_DATE_FORMAT);
+
+                            if expiry > chrono::Utc::now() {
+                                println!(
+                                    "token: {}, expires {}",
+                                    "valid".if_supports_color(Stdout, |t| t
+                                        .style(Style::new().green().bold())),
+                                    local
+                                );
+                            } else {
+                                println!(
+                                    "token: {} at {}, refreshed on next use",
+                                    "expired".if_supports_color(Stdout, |t| t
+                                        .style(Style::new().yellow().bold())),
+                                    local
+                                );
+                            }
+                        }
+                        None => println!("token: unknown expiry"),
+                    }
+                }
+
+                match server {
+                    Some(Ok(())) => println!(
+                        "server: {}",
+                        "confirmed"
+                            .if_supports_color(Stdout, |t| t.style(Style::new().green().bold()))
+                    ),
+                    Some(Err(OnyxError::Network(e))) => println!(
+                        "server: {}, {}",
+                        "unreachable"
+                            .if_supports_color(Stdout, |t| t.style(Style::new().yellow().bold())),
+                        e
+                    ),
+                    Some(Err(e)) => println!(
+                        "server: {}, {}",
+                        "rejected"
+                            .if_supports_color(Stdout, |t| t.style(Style::new().red().bold())),
+                        e
+                    ),
+                    None => println!(
+                        "server: {}",
+                        "not checked".if_supports_color(Stdout, |t| t.dimmed())
+                    ),
                 }
 
                 print!("handles: ");
 
                 if session_info.handles.is_empty() {
-                    println!("{}", "(no handle)".red());
+                    println!("{}", "(no handle)".if_supports_color(Stdout, |t| t.red()));
                 } else {
                     for handle in &session_info.handles {
                         print!("{} ", handle);
@@ -395,9 +2011,21 @@ async fn run_onyx() -> Result<(), OnyxError> {
                 }
 
                 println!("did: {}", session_info.did);
+
+                if let Some(pds) = &session_info.pds {
+                    println!("pds: {}", pds);
+                }
             }
         },
-        Commands::Scrobble { command } => match command {
+        Commands::Scrobble {
+            webhook,
+            no_history,
+            lookup,
+            no_trim,
+            strict,
+            source,
+            command,
+        } => match command {
             ScrobbleCommands::Track {
                 track_name,
                 track_mb_id,
@@ -407,13 +2035,20 @@ async fn run_onyx() -> Result<(), OnyxError> {
                 artist_mb_ids,
                 release_name,
                 release_mb_id,
+                album_artist,
+                disc_number,
+                love,
                 origin_url,
                 isrc,
                 played_time,
                 track_discriminant,
                 release_discriminant,
+                repeat,
+                set_status,
+                quiet,
             } => {
                 let artists = parse_artist_list(artist_names, artist_mb_ids)?;
+                let origin_url = parse_origin_url(origin_url)?;
 
                 let track = Play {
                     track_name,
@@ -423,6 +2058,11 @@ async fn run_onyx() -> Result<(), OnyxError> {
                     artists,
                     release_name,
                     release_mb_id,
+                    album_artist,
+                    disc_number,
+                    loved: love.then_some(true),
+                    source: None,
+                    skipped: None,
                     origin_url,
                     isrc,
                     played_time,
@@ -433,36 +2073,421 @@ async fn run_onyx() -> Result<(), OnyxError> {
                     artist_names: None,
                     artist_mb_ids: None,
                 };
+                track.validate()?;
+                track.validate_ids()?;
 
                 let version = generate_client_version();
-                let session = get_session().await?;
-                let scrobbler = Scrobbler::new("onyx", &version, session);
-                scrobbler.scrobble_track(track).await?;
+                let session = get_account(offline, &http, &cache).await?.session;
+                let scrobbler = Scrobbler::new("onyx", &version, session)
+                    .with_webhook(webhook.webhook(&http)?)
+                    .with_history(get_history(no_history))
+                    .with_lookup(get_lookup(lookup, &http)?)
+                    .with_trim(!no_trim)
+                    .with_strict(strict)
+                    .with_source(source.clone())
+                    .with_quiet(output == OutputFormat::Json)
+                    .with_manual(true);
+
+                let Some(repeat) = repeat else {
+                    let play = scrobbler.scrobble_track(track).await?;
+
+                    print_success(
+                        output,
+                        "track submitted",
+                        serde_json::json!({ "submitted": [&play] }),
+                    )?;
+                    if !quiet && output != OutputFormat::Json {
+                        println!("  {}", format_submitted_play(&play));
+                    }
+
+                    if set_status {
+                        set_track_status(scrobbler.agent(), PlayView::from(&play), http, cache)
+                            .await?;
+                    }
+                    return Ok(());
+                };
+
+                let plays = scrobbler.scrobble_repeated(track, repeat).await?;
 
-                println!("{}: track submitted", "success".green().bold());
+                if output == OutputFormat::Json {
+                    println!(
+                        "{}",
+                        serde_json::to_string_pretty(&serde_json::json!({ "submitted": plays }))?
+                    );
+                } else if !quiet && let (Some(first), Some(last)) = (plays.first(), plays.last()) {
+                    println!(
+                        "  {} - {}, played {} to {}",
+                        format_play_artists(first).if_supports_color(Stdout, |t| t.magenta()),
+                        first.track_name,
+                        format_played_time(first),
+                        format_played_time(last)
+                    );
+                }
             }
             ScrobbleCommands::Logfile {
-                log,
+                logs,
                 log_format,
                 delete,
+                verbose,
+                limit,
+                yes,
+                record_skips,
+                summary,
+                since_last,
+                skip_errors,
+                include_artists,
+                exclude_artists,
+                time_offset,
+                delimited,
             } => {
+                let delimited = delimited.parser();
+                let confirm_over = if yes {
+                    None
+                } else {
+                    Some(
+                        get_config()?
+                            .import_confirm_threshold
+                            .unwrap_or(DEFAULT_IMPORT_CONFIRM_THRESHOLD),
+                    )
+                };
+
                 let version = generate_client_version();
-                let session = get_session().await?;
-                let scrobbler = Scrobbler::new("onyx", &version, session);
-                scrobbler.scrobble_logfile(log.clone(), log_format).await?;
+                let session = get_account(offline, &http, &cache).await?.session;
+                let scrobbler = Scrobbler::new("onyx", &version, session)
+                    .with_webhook(webhook.webhook(&http)?)
+                    .with_history(get_history(no_history))
+                    .with_lookup(get_lookup(lookup, &http)?)
+                    .with_trim(!no_trim)
+                    .with_strict(strict)
+                    .with_source(source.clone())
+                    .with_skips(record_skips)
+                    .with_summary(summary)
+                    .with_skip_errors(skip_errors)
+                    .with_time_offset(time_offset)
+                    .with_artist_filter(ArtistFilter {
+                        include: include_artists,
+                        exclude: exclude_artists,
+                    })
+                    .with_quiet(output == OutputFormat::Json);
+
+                let since = if since_last {
+                    scrobbler.last_scrobble_time().await?
+                } else {
+                    None
+                };
+                if since_last && since.is_none() {
+                    println!(
+                        "{}: no earlier imports in the history file, submitting every play",
+                        label(Label::Warning)
+                    );
+                }
+                let scrobbler = scrobbler.with_since(since);
+                let plays = scrobbler
+                    .scrobble_logfile(&logs, log_format, delimited, verbose, limit, confirm_over)
+                    .await?;
 
+                let mut deleted = Vec::new();
                 if delete {
-                    std::fs::remove_file(&log)?;
+                    for log in logs.iter().filter(|l| *l != Path::new(STDIN_PATH)) {
+                        std::fs::remove_file(log)?;
+                        if output != OutputFormat::Json {
+                            println!(
+                                "{}",
+                                format!("deleted log: {}", log.to_str().unwrap())
+                                    .if_supports_color(Stdout, |t| t.dimmed())
+                            );
+                        }
+                        deleted.push(log);
+                    }
+                }
+
+                // the plain summary was already printed as the plays went
+                if output == OutputFormat::Json {
+                    let result = serde_json::json!({ "submitted": plays, "deleted_logs": deleted });
+                    println!("{}", serde_json::to_string_pretty(&result)?);
+                }
+            }
+            ScrobbleCommands::Watch {
+                dir,
+                log_format,
+                delete,
+                settle,
+                delimited,
+            } => {
+                let delimited = delimited.parser();
+
+                // the history is all a restarted watch has to go on, without
+                // it every log in the directory would be submitted again
+                let Some(history) = get_history(no_history) else {
+                    return Err(OnyxError::Other(
+                        "watch needs the history file to remember submitted plays".into(),
+                    ));
+                };
+
+                let version = generate_client_version();
+                let session = get_account(offline, &http, &cache).await?.session;
+                let scrobbler = Scrobbler::new("onyx", &version, session)
+                    .with_webhook(webhook.webhook(&http)?)
+                    .with_history(Some(history))
+                    .with_lookup(get_lookup(lookup, &http)?)
+                    .with_trim(!no_trim)
+                    .with_strict(strict)
+                    .with_source(source.clone())
+                    .with_quiet(output == OutputFormat::Json);
+
+                scrobbler
+                    .watch_logs(
+                        &dir,
+                        log_format,
+                        delimited,
+                        delete,
+                        std::time::Duration::from_secs(settle),
+                    )
+                    .await?;
+            }
+            ScrobbleCommands::Export {
+                logs,
+                log_format,
+                output: out_path,
+                delimited,
+            } => {
+                let delimited = delimited.parser();
+
+                let mut plays = Vec::new();
+                for log in &logs {
+                    plays
+                        .push(parse_log(log, &log_format, delimited.as_ref(), false)?.into_plays());
+                }
+                let mut plays = merge_plays(plays);
+                if !no_trim {
+                    plays.iter_mut().for_each(Play::trim_names);
+                }
+                for play in plays.iter_mut().filter(|p| p.source.is_none()) {
+                    play.source = source.clone();
+                }
+
+                let version = generate_client_version();
+
+                match out_path {
+                    Some(path) => {
+                        let file = std::io::BufWriter::new(std::fs::File::create(&path)?);
+                        let count = export_plays(plays, "onyx", &version, file)?;
+                        print_success(
+                            output,
+                            format!("{} tracks exported to {}", count, path.display()),
+                            serde_json::json!({ "exported": count, "file": path }),
+                        )?;
+                    }
+                    None => {
+                        export_plays(plays, "onyx", &version, std::io::stdout().lock())?;
+                    }
+                }
+            }
+            ScrobbleCommands::ExportRepo { file, handle } => {
+                let (ident, session) = match handle {
+                    Some(s) => (s, None),
+                    None => {
+                        let account = get_account(offline, &http, &cache).await?;
+                        (account.did, Some(account.session))
+                    }
+                };
+
+                require_online(offline)?;
+
+                let feed = FeedClient::try_new(&ident, http, cache)?.with_session(session);
+                let count = feed.backup_plays(&file).await?;
+
+                print_success(
+                    output,
+                    format!("{} plays exported to {}", count, file.display()),
+                    serde_json::json!({ "exported": count, "file": file }),
+                )?;
+            }
+            ScrobbleCommands::List {
+                handle,
+                limit,
+                verbose,
+                table,
+            } => {
+                // clap only catches `--table`, `--format table` is checked here
+                let output = if table { OutputFormat::Table } else { output };
+                if verbose && output == OutputFormat::Table {
+                    return Err(OnyxError::Other(
+                        "`--verbose` can't be used with table output".into(),
+                    ));
+                }
+
+                // your own feed is read through your session, other users'
+                // publicly
+                let (ident, session) = match handle {
+                    Some(s) => (s, None),
+                    None => {
+                        let account = get_account(offline, &http, &cache).await?;
+                        (account.did, Some(account.session))
+                    }
+                };
+
+                require_online(offline)?;
+
+                // name whose plays these are, only worth showing for other users
+                let owner = match session {
+                    Some(_) => None,
+                    None => Some(
+                        identity::resolve_did_to_handle(&http.build_resolver()?, &cache, &ident)
+                            .await,
+                    ),
+                };
+
+                let feed = FeedClient::try_new(&ident, http, cache)?.with_session(session);
+                let records = feed.get_plays(Some(limit)).await?;
+
+                if output == OutputFormat::Json {
+                    println!("{}", serde_json::to_string_pretty(&records)?);
+                    return Ok(());
+                }
+
+                if records.is_empty() {
+                    println!("no plays found");
+                }
+
+                if output == OutputFormat::Table {
+                    print_plays_table(&records, color_disabled(no_color));
+                    return Ok(());
+                }
+
+                if let Some(owner) = owner
+                    && !records.is_empty()
+                {
+                    println!("plays by {}", owner.if_supports_color(Stdout, |t| t.bold()));
+                }
+
+                for record in records {
                     println!(
-                        "{}",
-                        format!("deleted log: {}", log.to_str().unwrap()).dimmed()
+                        "{}  {} - {}",
+                        format_played_time(&record.play).if_supports_color(Stdout, |t| t.dimmed()),
+                        format_play_artists(&record.play)
+                            .if_supports_color(Stdout, |t| t.magenta()),
+                        record.play.track_name
                     );
+
+                    if verbose {
+                        println!("  {}", record.uri.if_supports_color(Stdout, |t| t.dimmed()));
+                    }
                 }
             }
-            ScrobbleCommands::Interactive { socket } => {
+            ScrobbleCommands::Purge { since, until, yes } => {
+                let Account { session, did, .. } = get_account(offline, &http, &cache).await?;
+
+                let feed = FeedClient::try_new(&did, http.clone(), cache.clone())?;
+                let records: Vec<PlayRecord> = feed
+                    .get_plays(None)
+                    .await?
+                    .into_iter()
+                    .filter(|r| {
+                        r.play.played_time.is_some_and(|t| {
+                            since.is_none_or(|s| t >= s) && until.is_none_or(|u| t < u)
+                        })
+                    })
+                    .collect();
+
+                if records.is_empty() {
+                    return print_success(
+                        output,
+                        "no plays found in range",
+                        serde_json::json!({ "deleted": 0 }),
+                    );
+                }
+
+                if !yes {
+                    if output == OutputFormat::Json {
+                        let result = serde_json::json!({ "deleted": 0, "matched": records.len() });
+                        println!("{}", serde_json::to_string_pretty(&result)?);
+                        return Ok(());
+                    }
+
+                    println!(
+                        "{}: {} plays would be deleted, re-run with {} to delete them",
+                        label(Label::Warning),
+                        records.len(),
+                        "--yes".if_supports_color(Stdout, |t| t.style(Style::new().cyan().bold()))
+                    );
+                    return Ok(());
+                }
+
                 let version = generate_client_version();
-                let session = get_session().await?;
                 let scrobbler = Scrobbler::new("onyx", &version, session);
+                let deleted = scrobbler.delete_plays(&did, &records).await?;
+
+                print_success(
+                    output,
+                    format!("{} plays deleted", deleted),
+                    serde_json::json!({ "deleted": deleted, "matched": records.len() }),
+                )?;
+            }
+            ScrobbleCommands::Delete { query, played, yes } => {
+                let history = get_history(false)
+                    .ok_or_else(|| OnyxError::Io("the history file can't be opened".to_string()))?;
+                let Account { session, did, .. } = get_account(offline, &http, &cache).await?;
+
+                let entries = history.search(&did, &query, played)?;
+                if entries.is_empty() {
+                    println!("no plays in the history file match '{}'", query);
+                    return Ok(());
+                }
+
+                for entry in &entries {
+                    let played = entry.play.played_time.map_or("unknown".to_string(), |t| {
+                        t.format(DEFAULT_DATE_FORMAT).to_string()
+                    });
+                    println!(
+                        "  - {}, played {} {}",
+                        entry.play.track_name,
+                        played,
+                        format!("({})", entry.rkey.as_deref().unwrap_or(&entry.uri))
+                            .if_supports_color(Stdout, |t| t.dimmed())
+                    );
+                }
+
+                if !yes {
+                    println!(
+                        "{}: {} plays would be deleted, re-run with {} to delete them",
+                        label(Label::Warning),
+                        entries.len(),
+                        "--yes".if_supports_color(Stdout, |t| t.style(Style::new().cyan().bold()))
+                    );
+                    return Ok(());
+                }
+
+                let records: Vec<PlayRecord> = entries
+                    .into_iter()
+                    .map(|e| PlayRecord {
+                        uri: e.uri,
+                        play: e.play,
+                    })
+                    .collect();
+
+                let version = generate_client_version();
+                let scrobbler =
+                    Scrobbler::new("onyx", &version, session).with_history(Some(history));
+                let deleted = scrobbler.delete_plays(&did, &records).await?;
+
+                print_success(
+                    output,
+                    format!("{} plays deleted", deleted),
+                    serde_json::json!({ "deleted": deleted }),
+                )?;
+            }
+            ScrobbleCommands::Interactive { socket } => {
+                let version = generate_client_version();
+                let session = get_account(offline, &http, &cache).await?.session;
+                let scrobbler = Scrobbler::new("onyx", &version, session)
+                    .with_webhook(webhook.webhook(&http)?)
+                    .with_history(get_history(no_history))
+                    .with_lookup(get_lookup(lookup, &http)?)
+                    .with_trim(!no_trim)
+                    .with_strict(strict)
+                    .with_source(source.clone())
+                    .with_manual(true);
 
                 #[cfg(unix)]
                 async fn run_socket(
@@ -475,13 +2500,26 @@ async fn run_onyx() -> Result<(), OnyxError> {
                     }
 
                     let listener = UnixListener::bind(socket)?;
+                    systemd::ready();
 
                     // run the receiver forever in case client dies
                     loop {
-                        println!("{}", "waiting for socket connection...".dimmed());
-                        let (stream, _) = listener.accept()?;
+                        println!(
+                            "{}",
+                            "waiting for socket connection..."
+                                .if_supports_color(Stdout, |t| t.dimmed())
+                        );
+                        let (stream, _) = loop {
+                            tokio::select! {
+                                accepted = listener.accept() => break accepted?,
+                                () = systemd::watchdog_due() => systemd::watchdog(),
+                            }
+                        };
                         let reader = BufReader::new(stream);
-                        println!("{}", "waiting for tracks...".dimmed());
+                        println!(
+                            "{}",
+                            "waiting for tracks...".if_supports_color(Stdout, |t| t.dimmed())
+                        );
                         scrobbler.scrobble_lines(reader).await?;
                         println!();
                     }
@@ -492,26 +2530,93 @@ async fn run_onyx() -> Result<(), OnyxError> {
                 {
                     run_socket(socket, scrobbler).await?;
                 } else {
-                    let reader = BufReader::new(std::io::stdin());
-                    println!("{}", "waiting for tracks...".dimmed());
+                    let reader = BufReader::new(tokio::io::stdin());
+                    println!(
+                        "{}",
+                        "waiting for tracks...".if_supports_color(Stdout, |t| t.dimmed())
+                    );
                     scrobbler.scrobble_lines(reader).await?;
                 }
             }
         },
-        Commands::Status { command } => match command {
-            StatusCommands::Show { handle, raw, full } => {
+        Commands::Status { record, command } => match command {
+            StatusCommands::Show {
+                handle,
+                raw,
+                full,
+                long,
+                exit_code,
+                csv,
+                date_format,
+                twelve_hour,
+                watch,
+                interval,
+            } => {
+                let date_format =
+                    date_format.or(twelve_hour.then(|| TWELVE_HOUR_DATE_FORMAT.into()));
+                let date_format = date_format.as_deref();
+
                 let ident = match handle {
                     Some(s) => s,
-                    None => {
-                        let auth = get_auth()?;
-                        let session_info = auth.get_session_info()?;
-                        session_info.did
-                    }
+                    None => get_account(offline, &http, &cache).await?.did,
                 };
 
-                let status_man = StatusManager::new(&ident);
+                require_online(offline)?;
+
+                let status_man = record.manager(&ident, http, cache)?;
                 let status = status_man.get_status().await?;
-                status.display(raw, full);
+                if csv {
+                    print_fields_csv(&status.split_fields(raw, full, date_format))?;
+                } else {
+                    print_status(
+                        &status,
+                        output,
+                        watch,
+                        no_color,
+                        raw,
+                        full,
+                        long,
+                        date_format,
+                    )?;
+                }
+
+                if exit_code && status.is_empty() {
+                    return Err(OnyxError::Exit(NOTHING_PLAYING_EXIT_CODE));
+                }
+
+                if watch {
+                    let mut previous = status;
+
+                    loop {
+                        tokio::select! {
+                            _ = tokio::time::sleep(std::time::Duration::from_secs(interval)) => {}
+                            _ = tokio::signal::ctrl_c() => break,
+                        }
+
+                        let status = status_man.get_status().await?;
+                        if status.fields(raw, full, date_format)
+                            != previous.fields(raw, full, date_format)
+                        {
+                            if output == OutputFormat::Plain {
+                                println!();
+                                status.display_changes(&previous, raw, full, long, date_format);
+                            } else {
+                                print_status(
+                                    &status,
+                                    output,
+                                    watch,
+                                    no_color,
+                                    raw,
+                                    full,
+                                    long,
+                                    date_format,
+                                )?;
+                            }
+                        }
+
+                        previous = status;
+                    }
+                }
             }
             StatusCommands::Set {
                 track_name,
@@ -525,98 +2630,457 @@ async fn run_onyx() -> Result<(), OnyxError> {
                 origin_url,
                 isrc,
                 played_time,
+                track_discriminant,
+                release_discriminant,
                 time,
                 expiry,
+                now,
+                keep_alive,
+                interactive,
+                dry_run,
+                merge,
             } => {
-                let artists = parse_artist_list(artist_names, artist_mb_ids)?.unwrap_or(Vec::new());
+                let artists = parse_artist_list(artist_names, artist_mb_ids)?;
+                let artists_given = artists.is_some();
 
-                let play = PlayView {
-                    track_name,
+                let mut play = PlayView {
+                    track_name: track_name.unwrap_or_default(),
                     track_mb_id,
                     recording_mb_id,
                     duration,
-                    artists,
+                    artists: artists.unwrap_or_default(),
                     release_name,
                     release_mb_id,
                     origin_url,
                     isrc,
                     played_time,
+                    track_discriminant,
+                    release_discriminant,
                     music_service_base_domain: None,
                     submission_client_agent: None,
                 };
 
+                // a dry run with nothing to read stays offline, otherwise log
+                // in once for the reads and the write, each login is a
+                // createSession with an app password
+                let account = if dry_run && !merge && !interactive {
+                    None
+                } else {
+                    Some(get_account(offline, &http, &cache).await?)
+                };
+
+                // CID of the status the new one is based on, so the write
+                // fails rather than clobbering a status set in the meantime
+                let mut swap = None;
+                let mut current = None;
+
+                let account = match account {
+                    Some(Account {
+                        session,
+                        did,
+                        handle,
+                    }) if merge || interactive => {
+                        require_online(offline)?;
+
+                        // your own status is read through your session
+                        let status_man = record
+                            .manager(&did, http.clone(), cache.clone())?
+                            .with_session(Some(session));
+                        match status_man.get_status_record().await {
+                            Ok((status, cid)) => {
+                                swap = cid;
+                                current = Some(status).filter(|s| !s.is_empty());
+                            }
+                            Err(e @ (OnyxError::Network(_) | OnyxError::Identity(_))) if merge => {
+                                return Err(e);
+                            }
+                            // no status record yet, so nothing to keep
+                            Err(_) => {}
+                        }
+
+                        let session = status_man.into_session().expect("the session was set");
+                        Some(Account {
+                            session,
+                            did,
+                            handle,
+                        })
+                    }
+                    account => account,
+                };
+
+                if merge {
+                    if let Some(current) = current.clone() {
+                        if !artists_given {
+                            play.artists = current.item.artists.clone();
+                        }
+
+                        play.merge_unset(current.item);
+                    }
+
+                    if play.track_name.is_empty() {
+                        return Err(OnyxError::Parse(
+                            "no current status to merge with, a track name is needed".to_string(),
+                        ));
+                    }
+                }
+
+                play.origin_url = parse_origin_url(play.origin_url)?;
+                play.clear_empty();
+
+                if interactive {
+                    play = prompt_status_fields(play, current.map(|s| s.item).as_ref())?;
+                }
+                play.validate_ids()?;
+
+                let duration = play.duration;
                 let time = time.unwrap_or(chrono::Local::now().into());
+                let status_expiry = get_status_expiry()?;
+
+                let default_expiry = if now {
+                    time + duration.map_or(chrono::Duration::minutes(5), chrono::Duration::seconds)
+                } else {
+                    time + status_expiry
+                };
 
                 let status = Status {
                     time,
-                    expiry: Some(expiry.unwrap_or(time + std::time::Duration::from_mins(10))),
+                    expiry: Some(expiry.unwrap_or(default_expiry)),
                     item: play,
                 };
 
-                let auth = get_auth()?;
-                let session_info = auth.get_session_info()?;
-                let session = auth.restore().await?;
+                if dry_run {
+                    let record: fm_teal_status::Status = status.into();
+                    println!("{}", serde_json::to_string_pretty(&record)?);
+                    return Ok(());
+                }
+
+                let Some(account) = account else {
+                    unreachable!("logged in unless this is a dry run");
+                };
 
-                let status_man = StatusManager::new(&session_info.did);
-                status_man.set_status(session, status).await?;
+                let end = duration.map(|d| {
+                    status.item.played_time.unwrap_or(time) + chrono::Duration::seconds(d)
+                });
 
-                println!(
-                    "{}: set status for {}, {}",
-                    "success".green().bold(),
-                    (session_info
-                        .handles
-                        .first()
-                        .unwrap_or(&"(no handle)".red().to_string())),
-                    session_info.did
+                let agent = Agent::from(account.session);
+                let status_man = record.manager(&account.did, http, cache)?;
+                let cid = status_man.set_status(&agent, status.clone(), swap).await?;
+
+                print_success(
+                    output,
+                    format!(
+                        "set status for {}, {}",
+                        (account.handle.as_deref().unwrap_or(
+                            &"(no handle)"
+                                .if_supports_color(Stdout, |t| t.red())
+                                .to_string()
+                        )),
+                        account.did
+                    ),
+                    serde_json::json!({
+                        "handle": account.handle,
+                        "did": account.did,
+                        "status": status,
+                    }),
+                )?;
+
+                if keep_alive {
+                    let plain = output != OutputFormat::Json;
+                    if plain {
+                        println!(
+                            "{}",
+                            "keeping status alive, press Ctrl-C to stop..."
+                                .if_supports_color(Stdout, |t| t.dimmed())
+                        );
+                    }
+                    status_man.keep_alive(&agent, status, end, cid).await?;
+                    if plain {
+                        println!(
+                            "{}",
+                            "status refresh stopped".if_supports_color(Stdout, |t| t.dimmed())
+                        );
+                    }
+                }
+            }
+            StatusCommands::Compare {
+                first,
+                second,
+                raw,
+                full,
+            } => {
+                require_online(offline)?;
+
+                let first_man = record.manager(&first, http.clone(), cache.clone())?;
+                let second_man = record.manager(&second, http.clone(), cache.clone())?;
+                let (first_status, second_status) =
+                    tokio::try_join!(first_man.get_status(), second_man.get_status())?;
+
+                let resolver = http.build_resolver()?;
+                let (first_name, second_name) = tokio::join!(
+                    identity::resolve_did_to_handle(&resolver, &cache, &first),
+                    identity::resolve_did_to_handle(&resolver, &cache, &second)
                 );
+
+                for (name, status) in [(&first_name, &first_status), (&second_name, &second_status)]
+                {
+                    println!("{}", name.if_supports_color(Stdout, |t| t.bold()));
+                    status.display(raw, full, false, None);
+                    println!();
+                }
+
+                let idle: Vec<&str> =
+                    [(&first_name, &first_status), (&second_name, &second_status)]
+                        .iter()
+                        .filter(|(_, status)| status.is_empty())
+                        .map(|(ident, _)| ident.as_str())
+                        .collect();
+
+                let result = if !idle.is_empty() {
+                    Err(format!("nothing playing for {}", idle.join(" and ")))
+                } else {
+                    match first_status.item.compare(&second_status.item) {
+                        PlayMatch::Track => Ok("same track"),
+                        PlayMatch::Release => Ok("same release"),
+                        PlayMatch::Artist => Ok("same artist"),
+                        PlayMatch::None => Err("different artists".to_string()),
+                    }
+                };
+
+                match result {
+                    Ok(reason) => println!(
+                        "{}: {}",
+                        "match".if_supports_color(Stdout, |t| t.style(Style::new().green().bold())),
+                        reason
+                    ),
+                    Err(reason) => println!(
+                        "{}: {}",
+                        "no match"
+                            .if_supports_color(Stdout, |t| t.style(Style::new().red().bold())),
+                        reason
+                    ),
+                }
             }
-            StatusCommands::Clear => {
-                let auth = get_auth()?;
-                let session_info = auth.get_session_info()?;
-                let session = auth.restore().await?;
+            StatusCommands::Log {
+                handle,
+                interval,
+                output: log_path,
+            } => {
+                let ident = match handle {
+                    Some(s) => s,
+                    None => get_account(offline, &http, &cache).await?.did,
+                };
 
-                let status_man = StatusManager::new(&session_info.did);
-                status_man.clear_status(session).await?;
+                require_online(offline)?;
+
+                let name =
+                    identity::resolve_did_to_handle(&http.build_resolver()?, &cache, &ident).await;
+                let status_man = record.manager(&ident, http, cache)?;
+                let mut file = std::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&log_path)?;
 
                 println!(
-                    "{}: cleared status for {}, {}",
-                    "success".green().bold(),
-                    (session_info
-                        .handles
-                        .first()
-                        .unwrap_or(&"(no handle)".red().to_string())),
-                    session_info.did,
+                    "logging status of {} to {}, press Ctrl-C to stop",
+                    name,
+                    log_path.to_str().unwrap()
                 );
+
+                systemd::ready();
+
+                let mut count = 0;
+                loop {
+                    match status_man.get_status().await {
+                        Ok(status) => {
+                            let snapshot = StatusSnapshot {
+                                captured: chrono::Utc::now(),
+                                status,
+                            };
+                            writeln!(file, "{}", serde_json::to_string(&snapshot)?)?;
+                            count += 1;
+                        }
+                        // keep polling through transient failures
+                        Err(e @ (OnyxError::Network(_) | OnyxError::Identity(_))) => {
+                            print_error(&e)
+                        }
+                        Err(e) => return Err(e),
+                    }
+
+                    tokio::select! {
+                        _ = systemd::sleep(interval) => {}
+                        _ = tokio::signal::ctrl_c() => break,
+                    }
+                }
+
+                systemd::stopping();
+
+                print_success(
+                    output,
+                    format!("{} snapshots written to {}", count, log_path.display()),
+                    serde_json::json!({ "snapshots": count, "file": log_path }),
+                )?;
+            }
+            StatusCommands::Clear => {
+                let account = get_account(offline, &http, &cache).await?;
+
+                let status_man = record.manager(&account.did, http, cache)?;
+                status_man
+                    .clear_status(&Agent::from(account.session), None)
+                    .await?;
+
+                print_success(
+                    output,
+                    format!(
+                        "cleared status for {}, {}",
+                        (account.handle.as_deref().unwrap_or(
+                            &"(no handle)"
+                                .if_supports_color(Stdout, |t| t.red())
+                                .to_string()
+                        )),
+                        account.did,
+                    ),
+                    serde_json::json!({ "handle": account.handle, "did": account.did }),
+                )?;
+            }
+        },
+        Commands::Records { command } => match command {
+            RecordsCommands::List {
+                collection,
+                handle,
+                limit,
+            } => {
+                let (ident, session) = match handle {
+                    Some(s) => (s, None),
+                    None => {
+                        let account = get_account(offline, &http, &cache).await?;
+                        (account.did, Some(account.session))
+                    }
+                };
+
+                require_online(offline)?;
+
+                let feed = FeedClient::try_new(&ident, http, cache)?.with_session(session);
+                let records = feed.get_records(collection, Some(limit)).await?;
+
+                println!("{}", serde_json::to_string_pretty(&records)?);
             }
         },
+        Commands::Doctor => doctor::run(&get_config_dir(), &http, &cache, offline).await?,
     }
 
     Ok(())
 }
 
 fn print_error(e: &OnyxError) {
-    println!("{}: {}", "error".red().bold(), e);
+    println!("{}: {}", label(Label::Error), e);
+}
+
+/// Offer to log in again after the stored session expired, using the handle,
+/// store and auth method of the dead session
+async fn reauthenticate(http: &HttpOptions, cache: &HandleCache) -> Result<bool, OnyxError> {
+    let auth = get_auth(http, cache)?;
+    let session = auth.get_session_info()?;
+    let handle = session_handle(&session).unwrap_or(&session.did).to_string();
+
+    let answer = prompt(&format!(
+        "session for {} has expired, log in again? [y/N]: ",
+        handle
+    ))?;
+    if !matches!(answer.to_lowercase().as_str(), "y" | "yes") {
+        return Ok(false);
+    }
+
+    let pds = session.pds_url()?;
+    let password = match session.auth {
+        AuthMethod::AppPassword => Some(prompt("app password: ")?),
+        AuthMethod::OAuth => None,
+    };
+
+    auth.login(
+        &handle,
+        session.store,
+        password,
+        pds,
+        CallbackOptions::default(),
+    )
+    .await?;
+
+    Ok(true)
 }
 
-fn handle_error(e: OnyxError) {
+async fn handle_error(e: &OnyxError, offline: bool, http: &HttpOptions, cache: &HandleCache) {
+    print_error(e);
+
     match e {
-        OnyxError::Auth(_) => {
-            print_error(&e);
+        OnyxError::SessionExpired(_)
+            if !offline && std::io::stdin().is_terminal() && std::io::stdout().is_terminal() =>
+        {
+            match reauthenticate(http, cache).await {
+                Ok(true) => println!(
+                    "{}: logged in again, re-run the command to continue",
+                    label(Label::Success)
+                ),
+                Ok(false) => {}
+                Err(e) => print_error(&e),
+            }
+        }
+        OnyxError::Auth(_) | OnyxError::SessionExpired(_) => {
             println!(
                 "{}: try logging in with '{}'",
-                "hint".green().bold(),
-                "onyx auth login".cyan().bold()
+                label(Label::Hint),
+                "onyx auth login"
+                    .if_supports_color(Stdout, |t| t.style(Style::new().cyan().bold()))
+            );
+        }
+        OnyxError::Network(_) => {
+            println!(
+                "{}: check your network connection and try again",
+                label(Label::Hint),
+            );
+        }
+        OnyxError::Identity(_) => {
+            println!(
+                "{}: check the handle is correct, or allow longer with '{}'",
+                label(Label::Hint),
+                "--timeout".if_supports_color(Stdout, |t| t.style(Style::new().cyan().bold()))
+            );
+        }
+        OnyxError::Conflict(_) => {
+            println!(
+                "{}: re-run the command to apply it to the latest version",
+                label(Label::Hint),
             );
         }
-        _ => print_error(&e),
+        _ => {}
     }
 }
 
 #[tokio::main]
 async fn main() {
-    if let Err(e) = run_onyx().await {
-        handle_error(e);
-        std::process::exit(1);
+    let mut matches = get_command().get_matches();
+    let args = Args::from_arg_matches_mut(&mut matches).unwrap();
+
+    configure_color(args.no_color);
+
+    let offline = args.offline;
+    let cache = get_handle_cache(args.no_cache);
+    let http = get_http_options(&args);
+
+    let output = args.format;
+
+    if let Err(e) = run_onyx(args, http.clone(), cache.clone()).await {
+        if let OnyxError::Exit(code) = e {
+            std::process::exit(code);
+        }
+
+        if output == OutputFormat::Json {
+            let error = serde_json::json!({"error": e.to_string(), "exit_code": e.exit_code()});
+            println!("{}", error);
+            std::process::exit(e.exit_code());
+        }
+
+        handle_error(&e, offline, &http, &cache).await;
+        std::process::exit(e.exit_code());
     }
 }
 
@@ -624,6 +3088,25 @@ async fn main() {
 mod tests {
     use crate::*;
 
+    #[test]
+    fn test_command() {
+        Args::command().debug_assert();
+
+        // a global flag sharing its name with a subcommand's only panics once
+        // that subcommand is parsed
+        let commands: [&[&str]; 2] = [
+            &[
+                "onyx", "scrobble", "export", "a.log", "json", "-o", "a.ndjson",
+            ],
+            &[
+                "onyx", "--format", "json", "status", "log", "-o", "a.ndjson",
+            ],
+        ];
+        for command in commands {
+            Args::try_parse_from(command).unwrap();
+        }
+    }
+
     #[test]
     fn test_parse_artists() {
         let artist_names = "Test 1 , Test 2 , Test 3, Test 4, ";
@@ -651,5 +3134,93 @@ mod tests {
                 panic!("parse_artist_list: {e}");
             }
         }
+
+        assert!(matches!(
+            parse_artist_list(Some("Test 1".to_string()), Some("ABCD, 1234".to_string())),
+            Err(OnyxError::Parse(_))
+        ));
+        assert!(matches!(
+            parse_artist_list(None, Some("ABCD".to_string())),
+            Err(OnyxError::Parse(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_origin_url() {
+        assert_eq!(parse_origin_url(None).unwrap(), None);
+        assert_eq!(parse_origin_url(Some("  ".into())).unwrap(), None);
+        assert_eq!(
+            parse_origin_url(Some("https://example.com/track".into())).unwrap(),
+            Some("https://example.com/track".to_string())
+        );
+        assert!(matches!(
+            parse_origin_url(Some("example.com/track".into())),
+            Err(OnyxError::Parse(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_interval() {
+        assert_eq!(parse_interval("45"), Ok(std::time::Duration::from_secs(45)));
+        assert_eq!(
+            parse_interval("30s"),
+            Ok(std::time::Duration::from_secs(30))
+        );
+        assert_eq!(
+            parse_interval("5m"),
+            Ok(std::time::Duration::from_secs(300))
+        );
+        assert_eq!(
+            parse_interval("1h"),
+            Ok(std::time::Duration::from_secs(3600))
+        );
+        assert!(parse_interval("0s").is_err());
+        assert!(parse_interval("10d").is_err());
+        assert!(parse_interval("169h").is_err());
+        assert!(parse_interval("18446744073709551615h").is_err());
+        assert!(parse_interval("s").is_err());
+    }
+
+    #[test]
+    fn test_parse_time_offset() {
+        assert_eq!(parse_time_offset("+2h"), Ok(chrono::Duration::hours(2)));
+        assert_eq!(
+            parse_time_offset("-30m"),
+            Ok(chrono::Duration::minutes(-30))
+        );
+        assert_eq!(parse_time_offset("1d"), Ok(chrono::Duration::days(1)));
+        assert_eq!(parse_time_offset("90"), Ok(chrono::Duration::seconds(90)));
+        assert!(parse_time_offset("+2w").is_err());
+        assert!(parse_time_offset("-h").is_err());
+        assert!(parse_time_offset("--1h").is_err());
+    }
+
+    #[test]
+    fn test_parse_date_format() {
+        assert!(parse_date_format("%d/%m/%Y %I:%M %p").is_ok());
+        assert!(parse_date_format("%Q").is_err());
+    }
+
+    #[test]
+    fn test_read_password_file() {
+        let path = std::env::temp_dir().join(format!("onyx-password-{}", std::process::id()));
+
+        std::fs::write(&path, "abcd-efgh-ijkl-mnop  \r\nsecond line\n").unwrap();
+        assert_eq!(read_password_file(&path).unwrap(), "abcd-efgh-ijkl-mnop");
+
+        std::fs::write(&path, "\n").unwrap();
+        assert!(read_password_file(&path).is_err());
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(read_password_file(&path), Err(OnyxError::Io(_))));
+    }
+
+    #[test]
+    fn test_parse_header() {
+        assert_eq!(
+            parse_header("Authorization: Bearer abc:def"),
+            Ok(("Authorization".to_string(), "Bearer abc:def".to_string()))
+        );
+        assert!(parse_header("Authorization").is_err());
     }
 }
@@ -0,0 +1,160 @@
+#[cfg(any(feature = "metrics", test))]
+use std::fmt::Write;
+use std::sync::{
+    Mutex,
+    atomic::{AtomicU64, Ordering},
+};
+
+use crate::record::Artist;
+
+static SCROBBLES_SUBMITTED: AtomicU64 = AtomicU64::new(0);
+static SCROBBLES_FAILED: AtomicU64 = AtomicU64::new(0);
+static STATUS_UPDATES: AtomicU64 = AtomicU64::new(0);
+
+/// Track name and artists of the last scrobble or status update
+static CURRENT_TRACK: Mutex<Option<(String, String)>> = Mutex::new(None);
+
+fn set_current_track(track_name: &str, artists: &[Artist]) {
+    let current = (!track_name.is_empty()).then(|| {
+        let artists: Vec<&str> = artists.iter().map(|a| a.artist_name.as_str()).collect();
+        (track_name.to_owned(), artists.join(", "))
+    });
+
+    if let Ok(mut track) = CURRENT_TRACK.lock() {
+        *track = current;
+    }
+}
+
+pub fn scrobble_submitted(track_name: &str, artists: &[Artist]) {
+    SCROBBLES_SUBMITTED.fetch_add(1, Ordering::Relaxed);
+    set_current_track(track_name, artists);
+}
+
+pub fn scrobble_failed() {
+    SCROBBLES_FAILED.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Record a status update, an empty track name clears the current track
+pub fn status_updated(track_name: &str, artists: &[Artist]) {
+    STATUS_UPDATES.fetch_add(1, Ordering::Relaxed);
+    set_current_track(track_name, artists);
+}
+
+#[cfg(any(feature = "metrics", test))]
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Render all metrics in the Prometheus text exposition format
+#[cfg(any(feature = "metrics", test))]
+pub fn render() -> String {
+    let mut out = String::new();
+
+    let counters = [
+        (
+            "onyx_scrobbles_submitted_total",
+            "Tracks successfully scrobbled",
+            &SCROBBLES_SUBMITTED,
+        ),
+        (
+            "onyx_scrobbles_failed_total",
+            "Tracks that failed to scrobble",
+            &SCROBBLES_FAILED,
+        ),
+        (
+            "onyx_status_updates_total",
+            "Playing status updates pushed",
+            &STATUS_UPDATES,
+        ),
+    ];
+
+    for (name, help, counter) in counters {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} counter", name);
+        let _ = writeln!(out, "{} {}", name, counter.load(Ordering::Relaxed));
+    }
+
+    let _ = writeln!(
+        out,
+        "# HELP onyx_current_track Track last scrobbled or set as status"
+    );
+    let _ = writeln!(out, "# TYPE onyx_current_track gauge");
+
+    if let Ok(track) = CURRENT_TRACK.lock()
+        && let Some((track_name, artists)) = track.as_ref()
+    {
+        let _ = writeln!(
+            out,
+            "onyx_current_track{{track=\"{}\",artists=\"{}\"}} 1",
+            escape_label(track_name),
+            escape_label(artists)
+        );
+    }
+
+    out
+}
+
+/// Serve `render` over HTTP on `addr` for the lifetime of the process
+#[cfg(feature = "metrics")]
+pub async fn serve(addr: std::net::SocketAddr) -> Result<(), crate::error::OnyxError> {
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    let listener = TcpListener::bind(addr).await?;
+
+    tokio::spawn(async move {
+        loop {
+            let Ok((mut stream, _)) = listener.accept().await else {
+                continue;
+            };
+
+            tokio::spawn(async move {
+                // every path serves the metrics, so the request itself is ignored
+                let mut request = [0; 1024];
+                let _ = stream.read(&mut request).await;
+
+                let body = render();
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+            });
+        }
+    });
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        scrobble_submitted(
+            "Track \"1\"",
+            &[Artist {
+                artist_name: "Artist 1".to_string(),
+                artist_mb_id: None,
+            }],
+        );
+        scrobble_failed();
+
+        let out = render();
+
+        assert!(out.contains("# TYPE onyx_scrobbles_submitted_total counter"));
+        assert!(
+            out.contains("onyx_current_track{track=\"Track \\\"1\\\"\",artists=\"Artist 1\"} 1")
+        );
+
+        status_updated("", &[]);
+        assert!(!render().contains("onyx_current_track{"));
+    }
+}
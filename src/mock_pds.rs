@@ -0,0 +1,291 @@
+//! A minimal in-process PDS for tests, serving just enough XRPC over plain
+//! HTTP to log in and create, put, get, list and delete records, checking
+//! `swapRecord` on puts
+
+use jacquard::{
+    CowStr,
+    client::{MemorySessionStore, credential_session::CredentialSession},
+    url::Url,
+};
+use serde_json::{Value, json};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+use crate::{auth::GenericSession, http::HttpOptions};
+
+pub const DID: &str = "did:plc:abcd";
+pub const HANDLE: &str = "test.example.com";
+
+// CIDs aren't computed from the record, each write gets this with a
+// counter appended, which is enough for swapRecord checks
+const CID_PREFIX: &str = "bafyreie5737gdxlw5i64vzichcalba3z2v5n6icifvx5xytvske7mr3hpm";
+
+/// An XRPC call the mock PDS received
+#[derive(Debug, Clone)]
+pub struct MockRequest {
+    pub nsid: String,
+    pub query: HashMap<String, String>,
+    pub body: Value,
+}
+
+#[derive(Debug, Default)]
+struct MockState {
+    requests: Vec<MockRequest>,
+    /// Records and their CIDs by collection and record key
+    records: HashMap<(String, String), (Value, String)>,
+    next_rkey: u64,
+    writes: u64,
+}
+
+impl MockState {
+    fn next_cid(&mut self) -> String {
+        self.writes += 1;
+        format!("{}{}", CID_PREFIX, self.writes)
+    }
+}
+
+pub struct MockPds {
+    pub url: Url,
+    state: Arc<Mutex<MockState>>,
+}
+
+impl MockPds {
+    /// Start serving on a free local port for the rest of the test
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("http://{}", listener.local_addr().unwrap())).unwrap();
+        let state = Arc::new(Mutex::new(MockState::default()));
+
+        let server_state = state.clone();
+        tokio::spawn(async move {
+            loop {
+                let Ok((stream, _)) = listener.accept().await else {
+                    continue;
+                };
+
+                tokio::spawn(handle_connection(stream, server_state.clone()));
+            }
+        });
+
+        Self { url, state }
+    }
+
+    /// A session logged in to the mock PDS as `DID`
+    pub async fn session(&self) -> GenericSession {
+        let session = CredentialSession::new(
+            Arc::new(MemorySessionStore::default()),
+            Arc::new(HttpOptions::default().build_resolver().unwrap()),
+        );
+        session
+            .login(
+                CowStr::Borrowed(HANDLE),
+                CowStr::Borrowed("password"),
+                None,
+                None,
+                None,
+                Some(self.url.clone()),
+            )
+            .await
+            .unwrap();
+
+        GenericSession::MemoryPassword(session)
+    }
+
+    /// Calls received so far for `nsid`, oldest first
+    pub fn requests(&self, nsid: &str) -> Vec<MockRequest> {
+        self.state
+            .lock()
+            .unwrap()
+            .requests
+            .iter()
+            .filter(|r| r.nsid == nsid)
+            .cloned()
+            .collect()
+    }
+}
+
+async fn handle_connection(mut stream: TcpStream, state: Arc<Mutex<MockState>>) {
+    let mut data = Vec::new();
+    let mut buf = [0; 4096];
+
+    // read the head, then as much body as the head says there is
+    let head_end = loop {
+        let Ok(n) = stream.read(&mut buf).await else {
+            return;
+        };
+        if n == 0 {
+            return;
+        }
+        data.extend_from_slice(&buf[..n]);
+
+        if let Some(i) = data.windows(4).position(|w| w == b"\r\n\r\n") {
+            break i + 4;
+        }
+    };
+
+    let head = String::from_utf8_lossy(&data[..head_end]).to_string();
+    let content_length = head
+        .lines()
+        .find_map(|l| {
+            let (name, value) = l.split_once(':')?;
+            name.eq_ignore_ascii_case("content-length")
+                .then(|| value.trim().parse::<usize>().ok())?
+        })
+        .unwrap_or(0);
+
+    while data.len() < head_end + content_length {
+        let Ok(n) = stream.read(&mut buf).await else {
+            return;
+        };
+        if n == 0 {
+            break;
+        }
+        data.extend_from_slice(&buf[..n]);
+    }
+
+    let target = head.split_whitespace().nth(1).unwrap_or_default();
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let request = MockRequest {
+        nsid: path.trim_start_matches("/xrpc/").to_string(),
+        query: url::form_urlencoded::parse(query.as_bytes())
+            .into_owned()
+            .collect(),
+        body: serde_json::from_slice(&data[head_end..]).unwrap_or(Value::Null),
+    };
+
+    let (status, body) = respond(&mut state.lock().unwrap(), request);
+    let body = body.to_string();
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    let _ = stream.write_all(response.as_bytes()).await;
+}
+
+fn respond(state: &mut MockState, request: MockRequest) -> (&'static str, Value) {
+    state.requests.push(request.clone());
+
+    let field = |name: &str| request.body[name].as_str().unwrap_or_default().to_string();
+    let uri = |collection: &str, rkey: &str| format!("at://{}/{}/{}", DID, collection, rkey);
+
+    match request.nsid.as_str() {
+        "com.atproto.server.createSession" => (
+            "200 OK",
+            json!({
+                "accessJwt": "access",
+                "refreshJwt": "refresh",
+                "handle": HANDLE,
+                "did": DID,
+            }),
+        ),
+        "com.atproto.repo.createRecord" => {
+            state.next_rkey += 1;
+            let (collection, rkey) = (field("collection"), state.next_rkey.to_string());
+            let record = request.body["record"].clone();
+            let cid = state.next_cid();
+            state
+                .records
+                .insert((collection.clone(), rkey.clone()), (record, cid.clone()));
+
+            (
+                "200 OK",
+                json!({"uri": uri(&collection, &rkey), "cid": cid}),
+            )
+        }
+        "com.atproto.repo.putRecord" => {
+            let (collection, rkey) = (field("collection"), field("rkey"));
+            let key = (collection.clone(), rkey.clone());
+
+            if let Some(swap) = request.body["swapRecord"].as_str()
+                && state.records.get(&key).map(|(_, cid)| cid.as_str()) != Some(swap)
+            {
+                return (
+                    "400 Bad Request",
+                    json!({"error": "InvalidSwap", "message": "Record was at a different CID"}),
+                );
+            }
+
+            let record = request.body["record"].clone();
+            let cid = state.next_cid();
+            state.records.insert(key, (record, cid.clone()));
+
+            (
+                "200 OK",
+                json!({"uri": uri(&collection, &rkey), "cid": cid}),
+            )
+        }
+        "com.atproto.repo.getRecord" => {
+            let collection = request.query.get("collection").cloned().unwrap_or_default();
+            let rkey = request.query.get("rkey").cloned().unwrap_or_default();
+
+            match state.records.get(&(collection.clone(), rkey.clone())) {
+                Some((value, cid)) => (
+                    "200 OK",
+                    json!({"uri": uri(&collection, &rkey), "cid": cid, "value": value}),
+                ),
+                None => (
+                    "400 Bad Request",
+                    json!({"error": "RecordNotFound", "message": "Could not locate record"}),
+                ),
+            }
+        }
+        "com.atproto.repo.listRecords" => {
+            let collection = request.query.get("collection").cloned().unwrap_or_default();
+            let limit = request
+                .query
+                .get("limit")
+                .and_then(|l| l.parse().ok())
+                .unwrap_or(50);
+            // record keys are counters, listed newest first, and the cursor
+            // is the last key on the page
+            let cursor = request.query.get("cursor").and_then(|c| c.parse().ok());
+
+            let mut keys: Vec<u64> = state
+                .records
+                .keys()
+                .filter(|(c, _)| *c == collection)
+                .filter_map(|(_, rkey)| rkey.parse().ok())
+                .filter(|rkey| cursor.is_none_or(|c| *rkey < c))
+                .collect();
+            keys.sort_unstable_by(|a, b| b.cmp(a));
+            keys.truncate(limit);
+
+            let records: Vec<Value> = keys
+                .iter()
+                .map(|rkey| {
+                    let (value, cid) = &state.records[&(collection.clone(), rkey.to_string())];
+                    json!({"uri": uri(&collection, &rkey.to_string()), "cid": cid, "value": value})
+                })
+                .collect();
+            let cursor = (keys.len() == limit).then(|| keys.last().map(u64::to_string));
+
+            (
+                "200 OK",
+                json!({"records": records, "cursor": cursor.flatten()}),
+            )
+        }
+        "com.atproto.repo.applyWrites" => {
+            for write in request.body["writes"].as_array().into_iter().flatten() {
+                let collection = write["collection"].as_str().unwrap_or_default();
+                let rkey = write["rkey"].as_str().unwrap_or_default();
+                state
+                    .records
+                    .remove(&(collection.to_string(), rkey.to_string()));
+            }
+
+            ("200 OK", json!({}))
+        }
+        _ => (
+            "501 Not Implemented",
+            json!({"error": "MethodNotImplemented", "message": "not mocked"}),
+        ),
+    }
+}
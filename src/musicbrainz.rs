@@ -0,0 +1,202 @@
+use serde::Deserialize;
+use std::{collections::HashMap, time::Duration};
+use tokio::{sync::Mutex, time::Instant};
+
+use crate::{error::OnyxError, http::HttpOptions, record::Play};
+
+const MUSICBRAINZ_API: &str = "https://musicbrainz.org/ws/2";
+
+/// MusicBrainz allows an average of one request per second per client
+const REQUEST_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Deserialize)]
+struct IsrcResponse {
+    #[serde(default)]
+    recordings: Vec<Recording>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Recording {
+    id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ArtistSearchResponse {
+    #[serde(default)]
+    artists: Vec<ArtistResult>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ArtistResult {
+    id: String,
+    name: String,
+}
+
+/// Fills in missing MusicBrainz IDs on plays from the MusicBrainz web service
+pub struct MusicBrainzClient {
+    client: reqwest::Client,
+    last_request: Mutex<Option<Instant>>,
+    /// Artist IDs by lowercased name, so an import looks each artist up once
+    artists: Mutex<HashMap<String, Option<String>>>,
+}
+
+impl MusicBrainzClient {
+    pub fn try_new(http: &HttpOptions) -> Result<Self, OnyxError> {
+        Ok(Self {
+            client: http.build_client()?,
+            last_request: Mutex::new(None),
+            artists: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Send a GET request to `path` under the API, waiting out the rate
+    /// limit first, `None` if nothing was found
+    async fn get<T>(&self, path: &str, query: &[(&str, &str)]) -> Result<Option<T>, OnyxError>
+    where
+        T: for<'de> Deserialize<'de>,
+    {
+        {
+            let mut last_request = self.last_request.lock().await;
+            if let Some(last) = *last_request {
+                tokio::time::sleep_until(last + REQUEST_INTERVAL).await;
+            }
+            *last_request = Some(Instant::now());
+        }
+
+        let response = self
+            .client
+            .get(format!("{}/{}", MUSICBRAINZ_API, path))
+            .query(&[("fmt", "json")])
+            .query(query)
+            .send()
+            .await
+            .map_err(|e| OnyxError::Network(e.to_string()))?;
+
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        let body = response
+            .error_for_status()
+            .map_err(|e| OnyxError::Network(e.to_string()))?
+            .bytes()
+            .await
+            .map_err(|e| OnyxError::Network(e.to_string()))?;
+
+        Ok(Some(serde_json::from_slice(&body)?))
+    }
+
+    /// Look up the recording with `isrc`, `None` unless exactly one matches
+    pub async fn recording_by_isrc(&self, isrc: &str) -> Result<Option<String>, OnyxError> {
+        Ok(self
+            .get(&format!("isrc/{}", isrc), &[])
+            .await?
+            .and_then(Self::single_recording))
+    }
+
+    /// Look up the artist called `name`, `None` unless exactly one artist
+    /// has that name, since a guess at a common name is likely wrong
+    pub async fn artist_by_name(&self, name: &str) -> Result<Option<String>, OnyxError> {
+        let key = name.to_lowercase();
+        if let Some(id) = self.artists.lock().await.get(&key) {
+            return Ok(id.clone());
+        }
+
+        // quotes make it a phrase query, so only they need escaping
+        let query = format!(
+            "artist:\"{}\"",
+            name.replace('\\', "\\\\").replace('"', "\\\"")
+        );
+        let id = self
+            .get("artist", &[("query", &query), ("limit", "10")])
+            .await?
+            .and_then(|r| Self::single_artist(r, name));
+
+        self.artists.lock().await.insert(key, id.clone());
+        Ok(id)
+    }
+
+    fn single_recording(response: IsrcResponse) -> Option<String> {
+        match <[Recording; 1]>::try_from(response.recordings) {
+            Ok([recording]) => Some(recording.id),
+            Err(_) => None,
+        }
+    }
+
+    fn single_artist(response: ArtistSearchResponse, name: &str) -> Option<String> {
+        let mut matches = response
+            .artists
+            .into_iter()
+            .filter(|a| a.name.to_lowercase() == name.to_lowercase());
+
+        match (matches.next(), matches.next()) {
+            (Some(artist), None) => Some(artist.id),
+            _ => None,
+        }
+    }
+
+    /// Fill in the recording ID of a play that has an ISRC but no recording
+    /// ID, and artist IDs that are missing. Track IDs depend on the release,
+    /// so the ISRC alone can't give one.
+    pub async fn enrich(&self, play: &mut Play) -> Result<(), OnyxError> {
+        if let Some(isrc) = play
+            .isrc
+            .as_deref()
+            .filter(|_| play.recording_mb_id.is_none())
+        {
+            play.recording_mb_id = self.recording_by_isrc(isrc).await?;
+        }
+
+        for artist in play.artists.iter_mut().flatten() {
+            if artist.artist_mb_id.is_none() {
+                artist.artist_mb_id = self.artist_by_name(&artist.artist_name).await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_single_recording() {
+        let response: IsrcResponse = serde_json::from_str(
+            r#"{"isrc": "GBAYE0601498", "recordings": [{"id": "b1a9c0e9-d987-4042-ae91-78d6a3267d69", "title": "Track"}]}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            MusicBrainzClient::single_recording(response),
+            Some("b1a9c0e9-d987-4042-ae91-78d6a3267d69".to_string())
+        );
+
+        let response: IsrcResponse =
+            serde_json::from_str(r#"{"recordings": [{"id": "a"}, {"id": "b"}]}"#).unwrap();
+        assert_eq!(MusicBrainzClient::single_recording(response), None);
+    }
+
+    #[test]
+    fn test_single_artist() {
+        let response: ArtistSearchResponse = serde_json::from_str(
+            r#"{"artists": [{"id": "a", "name": "Radiohead", "score": 100}, {"id": "b", "name": "Radiohead Tribute", "score": 80}]}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            MusicBrainzClient::single_artist(response, "radiohead"),
+            Some("a".to_string())
+        );
+
+        // two artists share the name, so neither is picked
+        let response: ArtistSearchResponse = serde_json::from_str(
+            r#"{"artists": [{"id": "a", "name": "Nirvana"}, {"id": "b", "name": "Nirvana"}]}"#,
+        )
+        .unwrap();
+        assert_eq!(MusicBrainzClient::single_artist(response, "Nirvana"), None);
+
+        let response: ArtistSearchResponse =
+            serde_json::from_str(r#"{"artists": [{"id": "a", "name": "Other"}]}"#).unwrap();
+        assert_eq!(MusicBrainzClient::single_artist(response, "Nirvana"), None);
+    }
+}
@@ -1,20 +1,23 @@
-use chrono::{DateTime, FixedOffset, Local, TimeZone, Utc};
-use std::{
-    fs::File,
-    io::{BufRead, BufReader},
-    path::PathBuf,
-};
+use chrono::{DateTime, FixedOffset, Local, TimeZone};
+use std::io::BufRead;
 
 use crate::{
-    parser::{LogParser, ParserError},
+    label::{Label, stderr_label},
+    parser::{LineError, LogParser, LogPlay, ParserError},
     record::{Artist, Play},
 };
 
+/// Log versions understood by the parser, 1.1 adds a MusicBrainz track ID column
+const SUPPORTED_VERSIONS: [&str; 2] = ["1.0", "1.1"];
+
 #[derive(Debug)]
 pub struct AudioScrobblerParser {
-    timezone: Option<String>,
-    client_id: Option<String>,
     entries: Vec<Scrobble>,
+    /// Incomplete final line that was skipped
+    truncated: Option<String>,
+    /// Entries left out because they failed to parse, only collected when
+    /// skipping errors
+    errors: Vec<LineError>,
 }
 
 #[derive(Debug)]
@@ -26,6 +29,11 @@ struct Scrobble {
     rating: ScrobbleRating,
     timestamp: i64,
     mb_track_id: Option<String>,
+    /// Headers in effect where the entry appears
+    timezone: Option<String>,
+    client_id: Option<String>,
+    /// Line in the log the entry was on
+    line: usize,
 }
 
 #[derive(Debug, PartialEq)]
@@ -79,18 +87,69 @@ impl AudioScrobblerParser {
         }
     }
 
-    fn parse_timezone(s: String) -> Option<String> {
-        if s == "UNKNOWN" { None } else { Some(s) }
+    fn trim_line(line: &str) -> &str {
+        line.trim_end_matches(['\n', '\r'])
+    }
+
+    fn is_blank(line: &str) -> bool {
+        line.trim().is_empty()
     }
 
-    fn parse_entry(line: &str, version: &String) -> Result<Scrobble, ParserError> {
+    /// Convert an entry timestamp into a fixed offset time.
+    ///
+    /// Without a `#TZ/` header, or with `#TZ/UTC`, the timestamp is a Unix
+    /// timestamp, an absolute instant, and is kept in UTC. Any other zone
+    /// (`#TZ/UNKNOWN` in practice) means the log holds the device's local
+    /// wall clock time, so it's read as a naive time and placed in `local`.
+    /// Ambiguous times (DST fall back) take the earlier offset, and times
+    /// skipped by a DST change fall back to UTC, so the result only depends
+    /// on `local`.
+    fn played_time<Tz: TimeZone>(
+        timestamp: i64,
+        timezone: Option<&str>,
+        local: &Tz,
+    ) -> Result<DateTime<FixedOffset>, ParserError> {
+        let utc = DateTime::from_timestamp(timestamp, 0)
+            .ok_or_else(|| ParserError::Syntax(format!("invalid timestamp '{}'", timestamp)))?;
+
+        if matches!(timezone, None | Some("UTC")) {
+            return Ok(utc.fixed_offset());
+        }
+
+        Ok(local
+            .from_local_datetime(&utc.naive_utc())
+            .earliest()
+            .map(|dt| dt.fixed_offset())
+            .unwrap_or_else(|| utc.fixed_offset()))
+    }
+
+    fn check_version(version: &str) -> Result<(), ParserError> {
+        if !SUPPORTED_VERSIONS.contains(&version) {
+            return Err(ParserError::Other(format!(
+                "Unsupported log version '{}', expected one of {}",
+                version,
+                SUPPORTED_VERSIONS.join(", ")
+            )));
+        }
+
+        Ok(())
+    }
+
+    fn parse_entry(line: &str, version: &str) -> Result<Scrobble, ParserError> {
         let fields: Vec<&str> = line.split('\t').collect();
 
-        let mb_track_id = if version == "1.1" {
-            Self::parse_optional_string(fields[7])
-        } else {
-            None
-        };
+        // the MB track ID column may be dropped entirely when empty
+        let expected = if version == "1.1" { 7..=8 } else { 7..=7 };
+        if !expected.contains(&fields.len()) {
+            return Err(ParserError::Syntax(format!(
+                "expected {} fields for a version {} entry, got {}",
+                expected.end(),
+                version,
+                fields.len()
+            )));
+        }
+
+        let mb_track_id = fields.get(7).and_then(|f| Self::parse_optional_string(f));
 
         Ok(Scrobble {
             artist_name: fields[0].to_string(),
@@ -104,94 +163,154 @@ impl AudioScrobblerParser {
                 .parse()
                 .map_err(|e: std::num::ParseIntError| ParserError::Syntax(e.to_string()))?,
             mb_track_id,
+            timezone: None,
+            client_id: None,
+            line: 0,
         })
     }
 
-    pub fn parse<R>(mut reader: R) -> Result<Self, ParserError>
+    /// Parse a log, with `skip_errors` leaving out entries that fail to
+    /// parse and keeping them in `errors` instead
+    pub fn parse<R>(mut reader: R, skip_errors: bool) -> Result<Self, ParserError>
     where
         R: BufRead,
     {
-        let mut version: Option<String> = None;
-        let mut timezone: Option<String> = None;
-        let mut client_id: Option<String> = None;
+        /// Headers in effect at a point in the log
+        #[derive(Clone, Default)]
+        struct Headers {
+            version: Option<String>,
+            timezone: Option<String>,
+            client_id: Option<String>,
+        }
+
+        let mut headers = Headers::default();
         let mut entries = Vec::new();
 
+        // Entry lines are kept with whether they ended in a newline and the
+        // headers they appeared under. Concatenated logs can start a new
+        // header block anywhere, which replaces the previous block's headers,
+        // so `#` lines are read as headers wherever they are and unknown
+        // ones are skipped as comments
+        let mut lines = Vec::new();
         let mut line = String::new();
+        let mut number = 0;
 
-        // Parse headers first, since version is needed for entries
         loop {
             line.clear();
+            number += 1;
 
             let bytes = reader.read_line(&mut line)?;
             if bytes == 0 {
                 break;
             }
 
-            let line = line.trim_end_matches('\n');
-            if !line.starts_with('#') {
-                break;
+            let trimmed = Self::trim_line(&line);
+            if Self::is_blank(trimmed) {
+                continue;
             }
 
-            match Self::parse_header(line) {
-                LogHeaderEntry::Version(v) => version = Some(v),
-                LogHeaderEntry::TimeZone(tz) => timezone = Self::parse_timezone(tz),
-                LogHeaderEntry::ClientId(id) => client_id = Some(id),
-                _ => {}
+            if trimmed.starts_with('#') {
+                match Self::parse_header(trimmed) {
+                    LogHeaderEntry::Version(v) => {
+                        Self::check_version(&v)?;
+                        headers = Headers {
+                            version: Some(v),
+                            ..Default::default()
+                        };
+                    }
+                    LogHeaderEntry::TimeZone(tz) => headers.timezone = Some(tz),
+                    LogHeaderEntry::ClientId(id) => headers.client_id = Some(id),
+                    _ => {}
+                }
+                continue;
             }
-        }
 
-        let version =
-            version.ok_or_else(|| ParserError::Other("Log version not specified".to_string()))?;
+            if headers.version.is_none() {
+                return Err(ParserError::Other("Log version not specified".to_string()));
+            }
 
-        // Parse entries
-        if !line.is_empty() && !line.starts_with('#') {
-            let line = line.trim_end_matches('\n');
-            entries.push(Self::parse_entry(line, &version)?);
+            lines.push((
+                trimmed.to_string(),
+                number,
+                line.ends_with('\n'),
+                headers.clone(),
+            ));
         }
 
-        loop {
-            line.clear();
-
-            let bytes = reader.read_line(&mut line)?;
-            if bytes == 0 {
-                break;
-            }
+        if headers.version.is_none() {
+            return Err(ParserError::Other("Log version not specified".to_string()));
+        }
 
-            let line = line.trim_end_matches('\n');
-            if line.is_empty() {
-                continue;
+        // Rockbox can leave a partial last line when the device loses power
+        // mid-write, so an unparseable final line with no newline is dropped
+        // rather than failing the whole log
+        let mut truncated = None;
+        let mut errors = Vec::new();
+        let last = lines.len().saturating_sub(1);
+
+        for (i, (line, number, complete, headers)) in lines.into_iter().enumerate() {
+            let version = headers.version.as_deref().unwrap_or_default();
+
+            match Self::parse_entry(&line, version) {
+                Ok(entry) => entries.push(Scrobble {
+                    timezone: headers.timezone,
+                    client_id: headers.client_id,
+                    line: number,
+                    ..entry
+                }),
+                Err(_) if i == last && !complete => truncated = Some(line),
+                Err(error) if skip_errors => errors.push(LineError {
+                    line: number,
+                    error,
+                }),
+                Err(e) => return Err(e),
             }
-
-            entries.push(Self::parse_entry(line, &version)?);
         }
 
         Ok(Self {
-            timezone,
-            client_id,
             entries,
+            truncated,
+            errors,
         })
     }
 }
 
-impl LogParser for AudioScrobblerParser {
-    fn parse(log: PathBuf) -> Result<Vec<Play>, ParserError> {
-        let file = File::open(log)?;
-        let reader = BufReader::new(file);
-        let log = Self::parse(reader)?;
+impl AudioScrobblerParser {
+    /// Parse a log into plays, along with the entries rated as skipped,
+    /// which are marked as such
+    ///
+    /// With `errors` given, entries that fail to parse are added to it and
+    /// left out, otherwise the first one fails the whole log.
+    pub fn parse_with_skips<R: BufRead>(
+        reader: R,
+        errors: Option<&mut Vec<LineError>>,
+    ) -> Result<(Vec<LogPlay>, Vec<LogPlay>), ParserError> {
+        let mut log = Self::parse(reader, errors.is_some())?;
+
+        // on stderr so it stays out of logs converted to stdout
+        if let Some(line) = &log.truncated {
+            eprintln!(
+                "{}: skipping incomplete last line '{}'",
+                stderr_label(Label::Warning),
+                line.replace('\t', " ")
+            );
+        }
 
         let mut tracks = Vec::new();
+        let mut skipped = Vec::new();
 
         for entry in log.entries {
-            if entry.rating == ScrobbleRating::Skipped {
-                continue;
-            }
-
-            let dt: DateTime<FixedOffset> = if let Some(tz) = &log.timezone
-                && tz == "UTC"
-            {
-                Utc.timestamp_opt(entry.timestamp, 0).unwrap().into()
-            } else {
-                Local.timestamp_opt(entry.timestamp, 0).unwrap().into()
+            let is_skipped = entry.rating == ScrobbleRating::Skipped;
+            let dt = match Self::played_time(entry.timestamp, entry.timezone.as_deref(), &Local) {
+                Ok(dt) => dt,
+                Err(error) if errors.is_some() => {
+                    log.errors.push(LineError {
+                        line: entry.line,
+                        error,
+                    });
+                    continue;
+                }
+                Err(e) => return Err(e),
             };
 
             let mut artists = Vec::new();
@@ -207,7 +326,7 @@ impl LogParser for AudioScrobblerParser {
                 track_name: entry.track_name,
                 duration: Some(entry.duration),
                 played_time: Some(dt),
-                submission_client_agent: log.client_id.clone(),
+                submission_client_agent: entry.client_id,
                 artists: Some(artists),
                 release_name: entry.album_name,
                 track_mb_id: entry.mb_track_id,
@@ -218,14 +337,47 @@ impl LogParser for AudioScrobblerParser {
                 origin_url: None,
                 recording_mb_id: None,
                 release_mb_id: None,
+                album_artist: None,
+                disc_number: None,
+                loved: None,
+                source: None,
+                skipped: is_skipped.then_some(true),
                 track_discriminant: None,
                 release_discriminant: None,
             };
 
-            tracks.push(track);
+            let track = LogPlay {
+                line: Some(entry.line),
+                play: track,
+            };
+
+            if is_skipped {
+                skipped.push(track);
+            } else {
+                tracks.push(track);
+            }
         }
 
-        Ok(tracks)
+        if let Some(errors) = errors {
+            errors.append(&mut log.errors);
+        }
+
+        Ok((tracks, skipped))
+    }
+}
+
+impl LogParser for AudioScrobblerParser {
+    fn parse_reader<R: BufRead>(reader: R) -> Result<Vec<Play>, ParserError> {
+        Self::parse_with_skips(reader, None)
+            .map(|(tracks, _)| tracks.into_iter().map(|t| t.play).collect())
+    }
+
+    fn parse_reader_skipping<R: BufRead>(
+        reader: R,
+        errors: &mut Vec<LineError>,
+    ) -> Result<Vec<Play>, ParserError> {
+        Self::parse_with_skips(reader, Some(errors))
+            .map(|(tracks, _)| tracks.into_iter().map(|t| t.play).collect())
     }
 }
 
@@ -281,12 +433,11 @@ mod tests {
     fn test_parse_entry() {
         let str_log = "#AUDIOSCROBBLER/1.1\nArtist 1\t\tTrack 1\t5\t456\tL\t123456789\tid_0";
         let cur = std::io::Cursor::new(str_log);
-        let log = AudioScrobblerParser::parse(cur).unwrap();
-
-        assert_eq!(log.timezone, None);
-        assert_eq!(log.client_id, None);
+        let log = AudioScrobblerParser::parse(cur, false).unwrap();
 
         assert_eq!(log.entries.len(), 1);
+        assert_eq!(log.entries[0].timezone, None);
+        assert_eq!(log.entries[0].client_id, None);
         assert_eq!(log.entries[0].artist_name, "Artist 1");
         assert_eq!(log.entries[0].album_name, None);
         assert_eq!(log.entries[0].track_name, "Track 1");
@@ -295,4 +446,213 @@ mod tests {
         assert_eq!(log.entries[0].timestamp, 123456789);
         assert_eq!(log.entries[0].mb_track_id, Some("id_0".to_string()));
     }
+
+    #[test]
+    fn test_parse_entry_1_0() {
+        let str_log = "#AUDIOSCROBBLER/1.0\nArtist 1\tAlbum 1\tTrack 1\t5\t456\tL\t123456789\n";
+        let cur = std::io::Cursor::new(str_log);
+        let log = AudioScrobblerParser::parse(cur, false).unwrap();
+
+        assert_eq!(log.entries.len(), 1);
+        assert_eq!(log.entries[0].album_name, Some("Album 1".to_string()));
+        assert_eq!(log.entries[0].timestamp, 123456789);
+        assert_eq!(log.entries[0].mb_track_id, None);
+    }
+
+    #[test]
+    fn test_parse_entry_field_count() {
+        let str_log = "#AUDIOSCROBBLER/1.0\nArtist 1\t\tTrack 1\t5\t456\tL\t123456789\tid_0\n";
+        let cur = std::io::Cursor::new(str_log);
+        assert!(matches!(
+            AudioScrobblerParser::parse(cur, false),
+            Err(ParserError::Syntax(_))
+        ));
+
+        let str_log = "#AUDIOSCROBBLER/1.1\nArtist 1\t\tTrack 1\t5\n";
+        let cur = std::io::Cursor::new(str_log);
+        assert!(matches!(
+            AudioScrobblerParser::parse(cur, false),
+            Err(ParserError::Syntax(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_unknown_version() {
+        let str_log = "#AUDIOSCROBBLER/1.2\nArtist 1\t\tTrack 1\t5\t456\tL\t123456789\tid_0\n";
+        let cur = std::io::Cursor::new(str_log);
+        assert!(matches!(
+            AudioScrobblerParser::parse(cur, false),
+            Err(ParserError::Other(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_blank_lines() {
+        let str_log = "#AUDIOSCROBBLER/1.1\n\n#TZ/UTC\n  \nArtist 1\t\tTrack 1\t5\t456\tL\t123456789\t\n\nArtist 2\t\tTrack 2\t5\t300\tS\t123457000\t";
+        let cur = std::io::Cursor::new(str_log);
+        let log = AudioScrobblerParser::parse(cur, false).unwrap();
+
+        assert_eq!(log.entries.len(), 2);
+        assert_eq!(log.entries[1].timezone, Some("UTC".to_string()));
+        assert_eq!(log.entries[0].track_name, "Track 1");
+        assert_eq!(log.entries[0].mb_track_id, None);
+        assert_eq!(log.entries[1].track_name, "Track 2");
+        assert_eq!(log.entries[1].rating, ScrobbleRating::Skipped);
+    }
+
+    #[test]
+    fn test_parse_crlf_and_headers_only() {
+        let str_log =
+            "#AUDIOSCROBBLER/1.0\r\n#CLIENT/Test\r\nArtist 1\t\tTrack 1\t5\t456\tL\t123456789\r\n";
+        let cur = std::io::Cursor::new(str_log);
+        let log = AudioScrobblerParser::parse(cur, false).unwrap();
+
+        assert_eq!(log.entries.len(), 1);
+        assert_eq!(log.entries[0].client_id, Some("Test".to_string()));
+        assert_eq!(log.entries[0].timestamp, 123456789);
+
+        let cur = std::io::Cursor::new("#AUDIOSCROBBLER/1.1\n#TZ/UNKNOWN");
+        let log = AudioScrobblerParser::parse(cur, false).unwrap();
+
+        assert!(log.entries.is_empty());
+
+        let cur = std::io::Cursor::new("#TZ/UNKNOWN\n");
+        assert!(matches!(
+            AudioScrobblerParser::parse(cur, false),
+            Err(ParserError::Other(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_concatenated_logs() {
+        let str_log = "#AUDIOSCROBBLER/1.0\n#TZ/UNKNOWN\n#CLIENT/Old Client\nArtist 1\tAlbum 1\tTrack 1\t1\t456\tL\t123456789\n#AUDIOSCROBBLER/1.1\n#TZ/UTC\n#CLIENT/New Client\n# merged from another month\nArtist 2\t\tTrack 2\t2\t300\tL\t123457000\tid_1\n#AUDIOSCROBBLER/1.1\nArtist 3\t\tTrack 3\t3\t300\tS\t123458000\t\n";
+        let cur = std::io::Cursor::new(str_log);
+        let log = AudioScrobblerParser::parse(cur, false).unwrap();
+
+        assert_eq!(log.entries.len(), 3);
+        assert_eq!(log.entries[0].album_name, Some("Album 1".to_string()));
+        assert_eq!(log.entries[0].timezone, Some("UNKNOWN".to_string()));
+        assert_eq!(log.entries[0].client_id, Some("Old Client".to_string()));
+
+        // the second block is version 1.1, so has the MB track ID column
+        assert_eq!(log.entries[1].mb_track_id, Some("id_1".to_string()));
+        assert_eq!(log.entries[1].timezone, Some("UTC".to_string()));
+        assert_eq!(log.entries[1].client_id, Some("New Client".to_string()));
+
+        // a new block doesn't inherit headers from the one before
+        assert_eq!(log.entries[2].timezone, None);
+        assert_eq!(log.entries[2].client_id, None);
+        assert_eq!(log.entries[2].rating, ScrobbleRating::Skipped);
+
+        let str_log = "#AUDIOSCROBBLER/1.1\nArtist 1\t\tTrack 1\t1\t456\tL\t123456789\t\n#AUDIOSCROBBLER/2.0\n";
+        let cur = std::io::Cursor::new(str_log);
+        assert!(matches!(
+            AudioScrobblerParser::parse(cur, false),
+            Err(ParserError::Other(_))
+        ));
+    }
+
+    #[test]
+    fn test_played_time() {
+        let plus_two = FixedOffset::east_opt(2 * 3600).unwrap();
+
+        let utc = AudioScrobblerParser::played_time(123456789, Some("UTC"), &plus_two).unwrap();
+        assert_eq!(utc.timestamp(), 123456789);
+        assert_eq!(utc.offset().local_minus_utc(), 0);
+
+        // without a header the timestamp is still an absolute instant
+        let absent = AudioScrobblerParser::played_time(123456789, None, &plus_two).unwrap();
+        assert_eq!(absent, utc);
+        assert_eq!(absent.offset().local_minus_utc(), 0);
+
+        // unknown timezone means the timestamp is local wall clock time
+        let local =
+            AudioScrobblerParser::played_time(123456789, Some("UNKNOWN"), &plus_two).unwrap();
+        assert_eq!(local.timestamp(), 123456789 - 2 * 3600);
+        assert_eq!(local.offset().local_minus_utc(), 2 * 3600);
+        assert_eq!(local.naive_local(), utc.naive_utc());
+
+        assert!(AudioScrobblerParser::played_time(i64::MAX, Some("UNKNOWN"), &plus_two).is_err());
+    }
+
+    #[test]
+    fn test_parse_counts_skipped() {
+        let log = "#AUDIOSCROBBLER/1.1\n#TZ/UTC\nArtist 1\t\tTrack 1\t1\t456\tL\t123456789\t\nArtist 2\t\tTrack 2\t2\t300\tS\t123457000\t\nArtist 3\t\tTrack 3\t3\t300\tS\t123458000\t\n";
+
+        let (plays, skipped) =
+            AudioScrobblerParser::parse_with_skips(log.as_bytes(), None).unwrap();
+
+        assert_eq!(plays.len(), 1);
+        assert_eq!(plays[0].play.track_name, "Track 1");
+        assert_eq!(plays[0].play.skipped, None);
+        assert_eq!(plays[0].line, Some(3));
+        assert_eq!(skipped.len(), 2);
+        assert_eq!(skipped[0].play.track_name, "Track 2");
+        assert_eq!(skipped[0].play.skipped, Some(true));
+        assert_eq!(skipped[0].line, Some(4));
+    }
+
+    #[test]
+    fn test_parse_rockbox_log() {
+        let cur = std::io::Cursor::new(include_str!("fixtures/rockbox.scrobbler.log"));
+        let log = AudioScrobblerParser::parse(cur, false).unwrap();
+
+        assert_eq!(log.entries.len(), 3);
+        assert_eq!(log.entries[0].timezone, Some("UNKNOWN".to_string()));
+        assert_eq!(
+            log.entries[0].client_id,
+            Some("Rockbox ipodvideo $Revision$".to_string())
+        );
+        assert_eq!(log.entries[0].track_name, "Paranoid Android");
+        assert_eq!(log.entries[0].duration, 383);
+        assert_eq!(log.entries[0].rating, ScrobbleRating::Listened);
+        assert_eq!(log.entries[0].mb_track_id, None);
+        assert_eq!(log.entries[1].rating, ScrobbleRating::Skipped);
+        // Rockbox leaves the track number empty when the file has none
+        assert_eq!(log.entries[2].duration, 294);
+        assert_eq!(
+            log.entries[2].mb_track_id,
+            Some("5a1c7d3e-1b2f-4c9a-8e3d-6f0b2a4c8d1e".to_string())
+        );
+
+        assert_eq!(
+            log.truncated,
+            Some("Boards of Canada\tMusic Has the Right to Children\tRoygbiv\t5\t15".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid_last_line() {
+        // a complete but invalid last line is still an error
+        let cur = std::io::Cursor::new("#AUDIOSCROBBLER/1.1\nArtist\t\tTrack\t1\t10\tX\t123\t\n");
+        assert!(AudioScrobblerParser::parse(cur, false).is_err());
+
+        let cur = std::io::Cursor::new(
+            "#AUDIOSCROBBLER/1.1\nArtist\t\tTrack\t1\t10\tX\t123\t\nArtist\t\tTrack\t1\t10\tL\t123\t",
+        );
+        assert!(AudioScrobblerParser::parse(cur, false).is_err());
+    }
+
+    #[test]
+    fn test_parse_skipping_errors() {
+        let log = "#AUDIOSCROBBLER/1.1\n#TZ/UTC\n\
+                   Artist\t\tOne\t1\t10\tL\t1700000000\t\n\
+                   Artist\t\tTwo\t2\t10\tL\tyesterday\t\n\
+                   \n\
+                   Artist\t\tThree\t3\t10\tL\t1700000100\t\n";
+
+        assert!(AudioScrobblerParser::parse_with_skips(log.as_bytes(), None).is_err());
+
+        let mut errors = Vec::new();
+        let (plays, _) =
+            AudioScrobblerParser::parse_with_skips(log.as_bytes(), Some(&mut errors)).unwrap();
+
+        let tracks: Vec<(&str, Option<usize>)> = plays
+            .iter()
+            .map(|p| (p.play.track_name.as_str(), p.line))
+            .collect();
+        assert_eq!(tracks, [("One", Some(3)), ("Three", Some(6))]);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].line, 4);
+    }
 }
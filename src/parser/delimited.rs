@@ -0,0 +1,334 @@
+use chrono::{DateTime, FixedOffset, TimeZone, Utc};
+use std::{io::Read, str::FromStr};
+
+use crate::{
+    parser::{LineError, LogPlay, ParserError},
+    record::{Artist, Play},
+};
+
+/// Mapping of `Play` fields to zero-based column indices
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ColumnMap {
+    pub track: usize,
+    pub artist: Option<usize>,
+    pub artist_mb_id: Option<usize>,
+    pub album: Option<usize>,
+    pub album_artist: Option<usize>,
+    pub disc_number: Option<usize>,
+    pub timestamp: Option<usize>,
+    pub duration: Option<usize>,
+    pub track_mb_id: Option<usize>,
+    pub recording_mb_id: Option<usize>,
+    pub release_mb_id: Option<usize>,
+    pub isrc: Option<usize>,
+    pub origin_url: Option<usize>,
+    /// Times the play is repeated, for exports that collapse back to back
+    /// plays into one row
+    pub play_count: Option<usize>,
+}
+
+impl FromStr for ColumnMap {
+    type Err = String;
+
+    /// Parse a mapping like `artist=0,track=2,timestamp=5`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut map = ColumnMap::default();
+        let mut track = None;
+
+        for entry in s.split(',') {
+            let entry = entry.trim();
+            if entry.is_empty() {
+                continue;
+            }
+
+            let (name, index) = entry
+                .split_once('=')
+                .ok_or_else(|| format!("expected 'field=index', got '{}'", entry))?;
+            let index: usize = index
+                .trim()
+                .parse()
+                .map_err(|_| format!("invalid column index for '{}'", name.trim()))?;
+
+            match name.trim() {
+                "track" => track = Some(index),
+                "artist" => map.artist = Some(index),
+                "artist_mb_id" => map.artist_mb_id = Some(index),
+                "album" | "release" => map.album = Some(index),
+                "album_artist" => map.album_artist = Some(index),
+                "disc_number" | "disc" => map.disc_number = Some(index),
+                "timestamp" | "time" => map.timestamp = Some(index),
+                "duration" => map.duration = Some(index),
+                "track_mb_id" => map.track_mb_id = Some(index),
+                "recording_mb_id" => map.recording_mb_id = Some(index),
+                "release_mb_id" => map.release_mb_id = Some(index),
+                "isrc" => map.isrc = Some(index),
+                "origin_url" | "url" => map.origin_url = Some(index),
+                "play_count" | "count" => map.play_count = Some(index),
+                other => return Err(format!("unknown column '{}'", other)),
+            }
+        }
+
+        map.track = track.ok_or_else(|| "a 'track' column is required".to_string())?;
+        Ok(map)
+    }
+}
+
+/// Parse a delimiter name (`tab`, `comma`, `semicolon`, `pipe`) or a single character
+pub fn parse_delimiter(s: &str) -> Result<u8, String> {
+    match s {
+        "tab" | "\\t" => Ok(b'\t'),
+        "comma" => Ok(b','),
+        "semicolon" => Ok(b';'),
+        "pipe" => Ok(b'|'),
+        _ if s.len() == 1 && s.is_ascii() => Ok(s.as_bytes()[0]),
+        _ => Err(format!("unsupported delimiter '{}'", s)),
+    }
+}
+
+/// Parser for arbitrary delimited logs described by a `ColumnMap`
+#[derive(Debug, Clone)]
+pub struct DelimitedParser {
+    pub columns: ColumnMap,
+    pub delimiter: u8,
+    pub has_header: bool,
+}
+
+impl DelimitedParser {
+    pub fn new(columns: ColumnMap, delimiter: u8, has_header: bool) -> Self {
+        Self {
+            columns,
+            delimiter,
+            has_header,
+        }
+    }
+
+    /// Parse a Unix timestamp, optionally with fractional seconds, as UTC,
+    /// or an RFC 3339 time keeping its own offset and sub-second precision
+    fn parse_timestamp(s: &str, row: usize) -> Result<DateTime<FixedOffset>, ParserError> {
+        let invalid = || ParserError::Syntax(format!("row {}: invalid timestamp '{}'", row, s));
+
+        let (secs, frac) = s.split_once('.').unwrap_or((s, ""));
+        if let Ok(secs) = secs.parse::<i64>() {
+            if !frac.bytes().all(|b| b.is_ascii_digit()) {
+                return Err(invalid());
+            }
+
+            // pad or truncate the fraction to nanoseconds
+            let nanos = format!("{:0<9.9}", frac)
+                .parse::<u32>()
+                .map_err(|_| invalid())?;
+
+            return Utc
+                .timestamp_opt(secs, nanos)
+                .single()
+                .map(|dt| dt.into())
+                .ok_or_else(invalid);
+        }
+
+        DateTime::parse_from_rfc3339(s).map_err(|e| {
+            ParserError::Syntax(format!("row {}: invalid timestamp '{}': {}", row, s, e))
+        })
+    }
+
+    /// Parse a log into plays, along with the line each row started on
+    ///
+    /// With `errors` given, rows that fail to parse are added to it and left
+    /// out, otherwise the first one fails the whole log.
+    pub fn parse<R>(
+        &self,
+        reader: R,
+        mut errors: Option<&mut Vec<LineError>>,
+    ) -> Result<Vec<LogPlay>, ParserError>
+    where
+        R: Read,
+    {
+        let mut reader = csv::ReaderBuilder::new()
+            .delimiter(self.delimiter)
+            .has_headers(self.has_header)
+            .flexible(true)
+            .comment(Some(b'#'))
+            .from_reader(reader);
+
+        let mut plays = Vec::new();
+
+        for (i, record) in reader.records().enumerate() {
+            let line = match &record {
+                Ok(record) => record.position().map(|p| p.line()),
+                Err(e) => e.position().map(|p| p.line()),
+            }
+            .map_or(i + 1, |l| l as usize);
+
+            let play = record
+                .map_err(|e| ParserError::Syntax(e.to_string()))
+                .and_then(|record| self.parse_record(&record, i));
+
+            match play {
+                Ok(rows) => plays.extend(rows.into_iter().map(|play| LogPlay {
+                    line: Some(line),
+                    play,
+                })),
+                Err(error) => match errors.as_deref_mut() {
+                    Some(errors) => errors.push(LineError { line, error }),
+                    None => return Err(error),
+                },
+            }
+        }
+
+        Ok(plays)
+    }
+
+    /// Parse the `i`th row of a log into its plays, more than one if the row
+    /// has a play count, spread out as in `Play::repeat`
+    fn parse_record(&self, record: &csv::StringRecord, i: usize) -> Result<Vec<Play>, ParserError> {
+        let field = |index: Option<usize>| {
+            index
+                .and_then(|i| record.get(i))
+                .map(|s| s.trim())
+                .filter(|s| !s.is_empty())
+                .map(|s| s.to_owned())
+        };
+
+        let track_name = field(Some(self.columns.track))
+            .ok_or_else(|| ParserError::Syntax(format!("row {} is missing a track name", i + 1)))?;
+
+        let artists = field(self.columns.artist).map(|name| {
+            vec![Artist {
+                artist_name: name,
+                artist_mb_id: field(self.columns.artist_mb_id),
+            }]
+        });
+
+        let played_time = field(self.columns.timestamp)
+            .map(|s| Self::parse_timestamp(&s, i + 1))
+            .transpose()?;
+
+        let number = |index: Option<usize>| {
+            field(index)
+                .map(|s| {
+                    s.parse().map_err(|e: std::num::ParseIntError| {
+                        ParserError::Syntax(format!("row {}: {}", i + 1, e))
+                    })
+                })
+                .transpose()
+        };
+
+        let duration = number(self.columns.duration)?;
+        let disc_number = number(self.columns.disc_number)?;
+        let play_count = number(self.columns.play_count)?.unwrap_or(1);
+        let play_count = u32::try_from(play_count).map_err(|_| {
+            ParserError::Syntax(format!("row {}: invalid play count {}", i + 1, play_count))
+        })?;
+
+        let play = Play {
+            track_name,
+            artists,
+            played_time,
+            duration,
+            release_name: field(self.columns.album),
+            album_artist: field(self.columns.album_artist),
+            disc_number,
+            track_mb_id: field(self.columns.track_mb_id),
+            recording_mb_id: field(self.columns.recording_mb_id),
+            release_mb_id: field(self.columns.release_mb_id),
+            isrc: field(self.columns.isrc),
+            origin_url: field(self.columns.origin_url),
+            ..Default::default()
+        };
+
+        // a single play keeps its played time, even if it has none
+        Ok(match play_count {
+            1 => vec![play],
+            n => play
+                .repeat(n)
+                .map_err(|e| ParserError::Syntax(format!("row {}: {}", i + 1, e)))?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_column_map() {
+        let map: ColumnMap = "artist=0, track=2,timestamp=5".parse().unwrap();
+
+        assert_eq!(map.track, 2);
+        assert_eq!(map.artist, Some(0));
+        assert_eq!(map.timestamp, Some(5));
+        assert_eq!(map.album, None);
+
+        assert!("artist=0".parse::<ColumnMap>().is_err());
+        assert!("track=x".parse::<ColumnMap>().is_err());
+        assert!("track=0,colour=1".parse::<ColumnMap>().is_err());
+    }
+
+    #[test]
+    fn test_parse_rows() {
+        let columns: ColumnMap = "artist=0,track=2,album=1,timestamp=3".parse().unwrap();
+        let parser = DelimitedParser::new(columns, b'\t', false);
+
+        let log = "Artist 1\tAlbum 1\tTrack 1\t123456789\nArtist 2\t\tTrack 2\t2024-01-01T10:00:00.250+02:00\nArtist 3\t\tTrack 3\t123456789.5\n";
+        let plays = parser.parse(log.as_bytes(), None).unwrap();
+        let plays: Vec<Play> = plays.into_iter().map(|p| p.play).collect();
+
+        assert_eq!(plays.len(), 3);
+        assert_eq!(plays[0].track_name, "Track 1");
+        assert_eq!(plays[0].release_name, Some("Album 1".to_string()));
+        assert_eq!(
+            plays[0].artists.as_ref().unwrap()[0].artist_name,
+            "Artist 1"
+        );
+        assert_eq!(plays[0].played_time.unwrap().timestamp(), 123456789);
+        assert_eq!(plays[1].release_name, None);
+        assert_eq!(
+            plays[1].played_time.unwrap().offset().local_minus_utc(),
+            2 * 3600
+        );
+        assert_eq!(plays[1].played_time.unwrap().timestamp_subsec_millis(), 250);
+        assert_eq!(plays[2].played_time.unwrap().timestamp(), 123456789);
+        assert_eq!(plays[2].played_time.unwrap().timestamp_subsec_millis(), 500);
+
+        let columns: ColumnMap = "artist=0,track=1,timestamp=2".parse().unwrap();
+        let parser = DelimitedParser::new(columns, b'\t', false);
+        let err = parser.parse("A\tT\t12.3x\n".as_bytes(), None).unwrap_err();
+        assert!(err.to_string().contains("row 1: invalid timestamp"));
+    }
+
+    #[test]
+    fn test_parse_play_count() {
+        let columns: ColumnMap = "artist=0,track=1,timestamp=2,duration=3,count=4"
+            .parse()
+            .unwrap();
+        let parser = DelimitedParser::new(columns, b'\t', false);
+
+        let log = "A\tOne\t1000\t100\t3\nA\tTwo\t2000\t100\t\nA\tNone\t3000\t100\t0\n";
+        let plays = parser.parse(log.as_bytes(), None).unwrap();
+
+        let times: Vec<(&str, i64, Option<usize>)> = plays
+            .iter()
+            .map(|p| {
+                (
+                    p.play.track_name.as_str(),
+                    p.play.played_time.unwrap().timestamp(),
+                    p.line,
+                )
+            })
+            .collect();
+        assert_eq!(
+            times,
+            [
+                ("One", 800, Some(1)),
+                ("One", 900, Some(1)),
+                ("One", 1000, Some(1)),
+                ("Two", 2000, Some(2))
+            ]
+        );
+
+        assert!(
+            parser
+                .parse("A\tT\t1000\t100\t-1\n".as_bytes(), None)
+                .is_err()
+        );
+    }
+}
@@ -11,3 +11,11 @@ pub enum ParserError {
     #[error("{0}")]
     Other(String),
 }
+
+/// A log entry left out because it couldn't be parsed
+#[derive(Debug)]
+pub struct LineError {
+    /// One-based line in the log the entry was on
+    pub line: usize,
+    pub error: ParserError,
+}
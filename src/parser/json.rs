@@ -1,24 +1,43 @@
-use std::{
-    fs::File,
-    io::{BufRead, BufReader},
-};
+use serde::Deserialize;
+use std::io::BufRead;
 
 use crate::{
-    parser::{LogParser, ParserError},
+    parser::{LineError, LogParser, LogPlay, ParserError},
     record::Play,
 };
 
 #[derive(Debug)]
 pub struct JsonParser();
 
+/// A line of the log, a play with an optional count for exports that
+/// collapse back to back plays into one line
+#[derive(Debug, Deserialize)]
+struct JsonPlay {
+    #[serde(flatten)]
+    play: Play,
+    play_count: Option<u32>,
+}
+
 impl JsonParser {
     pub fn parse<R>(reader: R) -> Result<Vec<Play>, ParserError>
+    where
+        R: BufRead,
+    {
+        Self::parse_lines(reader, None).map(|plays| plays.into_iter().map(|p| p.play).collect())
+    }
+
+    /// Parse each line as a play, lines that fail are added to `errors` if
+    /// given, otherwise the first one fails the whole log
+    pub fn parse_lines<R>(
+        reader: R,
+        mut errors: Option<&mut Vec<LineError>>,
+    ) -> Result<Vec<LogPlay>, ParserError>
     where
         R: BufRead,
     {
         let mut plays = Vec::new();
 
-        for play in reader.lines() {
+        for (i, play) in reader.lines().enumerate() {
             let play = play?;
 
             if play.trim().is_empty() {
@@ -26,9 +45,29 @@ impl JsonParser {
                 continue;
             }
 
-            let play: Play =
-                serde_json::from_str(&play).map_err(|e| ParserError::Syntax(e.to_string()))?;
-            plays.push(play);
+            let parsed = match serde_json::from_str::<JsonPlay>(&play) {
+                // a single play keeps its played time, even if it has none
+                Ok(JsonPlay {
+                    play,
+                    play_count: None | Some(1),
+                }) => Ok(vec![play]),
+                Ok(JsonPlay {
+                    play,
+                    play_count: Some(n),
+                }) => play.repeat(n),
+                Err(e) => Err(ParserError::Syntax(e.to_string())),
+            };
+
+            match parsed {
+                Ok(parsed) => plays.extend(parsed.into_iter().map(|play| LogPlay {
+                    line: Some(i + 1),
+                    play,
+                })),
+                Err(error) => match errors.as_deref_mut() {
+                    Some(errors) => errors.push(LineError { line: i + 1, error }),
+                    None => return Err(error),
+                },
+            }
         }
 
         Ok(plays)
@@ -36,10 +75,39 @@ impl JsonParser {
 }
 
 impl LogParser for JsonParser {
-    fn parse(log: std::path::PathBuf) -> Result<Vec<Play>, ParserError> {
-        let file = File::open(log)?;
-        let reader = BufReader::new(file);
-        let plays = Self::parse(reader)?;
-        Ok(plays)
+    fn parse_reader<R: BufRead>(reader: R) -> Result<Vec<Play>, ParserError> {
+        Self::parse(reader)
+    }
+
+    fn parse_reader_skipping<R: BufRead>(
+        reader: R,
+        errors: &mut Vec<LineError>,
+    ) -> Result<Vec<Play>, ParserError> {
+        Self::parse_lines(reader, Some(errors))
+            .map(|plays| plays.into_iter().map(|p| p.play).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_play_count() {
+        let log = r#"{"track_name":"One","played_time":"2024-01-01T12:00:00Z","duration":60,"play_count":2}
+{"track_name":"Two"}
+"#;
+        let plays = JsonParser::parse(log.as_bytes()).unwrap();
+
+        assert_eq!(plays.len(), 3);
+        assert_eq!(
+            plays[0].played_time.unwrap().to_rfc3339(),
+            "2024-01-01T11:59:00+00:00"
+        );
+        assert_eq!(plays[2].track_name, "Two");
+        assert_eq!(plays[2].played_time, None);
+
+        let log = r#"{"track_name":"One","play_count":4000000000}"#;
+        assert!(JsonParser::parse(log.as_bytes()).is_err());
     }
 }
@@ -1,8 +1,35 @@
-use std::path::PathBuf;
+use std::io::BufRead;
 
-use crate::{parser::ParserError, record::Play};
+use crate::{
+    parser::{LineError, ParserError},
+    record::Play,
+};
+
+/// A play read from a log, with where it was in the log
+#[derive(Debug)]
+pub struct LogPlay {
+    /// One-based line the entry was on, `None` for formats read as a single
+    /// document
+    pub line: Option<usize>,
+    pub play: Play,
+}
 
 pub trait LogParser {
-    /// Parse the given log file into a list of tracks
-    fn parse(log: PathBuf) -> Result<Vec<Play>, ParserError>;
+    /// Parse a log, read from a file or standard input, into a list of tracks
+    fn parse_reader<R: BufRead>(reader: R) -> Result<Vec<Play>, ParserError>;
+
+    /// Like `parse_reader`, but entries that fail to parse are left out and
+    /// added to `errors` rather than failing the whole log
+    ///
+    /// Formats read as a single document can't skip individual entries, so
+    /// by default this fails rather than quietly parsing strictly.
+    fn parse_reader_skipping<R: BufRead>(
+        _reader: R,
+        _errors: &mut Vec<LineError>,
+    ) -> Result<Vec<Play>, ParserError> {
+        Err(ParserError::Other(
+            "this format is read as a single document, entries that fail to parse can't be skipped"
+                .to_string(),
+        ))
+    }
 }
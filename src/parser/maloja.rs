@@ -0,0 +1,143 @@
+use chrono::{DateTime, FixedOffset, TimeZone, Utc};
+use serde::Deserialize;
+use std::io::{BufRead, Read};
+
+use crate::{
+    parser::{LogParser, ParserError},
+    record::{Artist, Play},
+};
+
+#[derive(Debug)]
+pub struct MalojaParser();
+
+#[derive(Debug, Deserialize)]
+struct MalojaExport {
+    scrobbles: Vec<MalojaScrobble>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MalojaScrobble {
+    time: i64,
+    track: MalojaTrack,
+    duration: Option<i64>,
+    /// Not part of Maloja's own exports, for tools that collapse back to
+    /// back plays of a track into one scrobble
+    play_count: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MalojaTrack {
+    title: String,
+    #[serde(default)]
+    artists: Vec<String>,
+    album: Option<MalojaAlbum>,
+    length: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MalojaAlbum {
+    albumtitle: Option<String>,
+}
+
+impl MalojaParser {
+    pub fn parse<R>(reader: R) -> Result<Vec<Play>, ParserError>
+    where
+        R: Read,
+    {
+        let export: MalojaExport =
+            serde_json::from_reader(reader).map_err(|e| ParserError::Syntax(e.to_string()))?;
+
+        let mut plays = Vec::new();
+
+        for scrobble in export.scrobbles {
+            let played_time: DateTime<FixedOffset> = Utc
+                .timestamp_opt(scrobble.time, 0)
+                .single()
+                .ok_or_else(|| {
+                    ParserError::Syntax(format!("invalid scrobble time '{}'", scrobble.time))
+                })?
+                .into();
+
+            let artists = scrobble
+                .track
+                .artists
+                .into_iter()
+                .map(|name| Artist {
+                    artist_name: name,
+                    artist_mb_id: None,
+                })
+                .collect();
+
+            let play = Play {
+                track_name: scrobble.track.title,
+                artists: Some(artists),
+                release_name: scrobble.track.album.and_then(|a| a.albumtitle),
+                duration: scrobble.track.length.or(scrobble.duration),
+                played_time: Some(played_time),
+                ..Default::default()
+            };
+
+            match scrobble.play_count {
+                None | Some(1) => plays.push(play),
+                Some(n) => plays.extend(play.repeat(n)?),
+            }
+        }
+
+        Ok(plays)
+    }
+}
+
+impl LogParser for MalojaParser {
+    fn parse_reader<R: BufRead>(reader: R) -> Result<Vec<Play>, ParserError> {
+        Self::parse(reader)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_export() {
+        let export = r#"{
+            "maloja": {"export_time": 1700000000},
+            "scrobbles": [
+                {
+                    "time": 1700000000,
+                    "track": {
+                        "artists": ["Artist 1", "Artist 2"],
+                        "title": "Track 1",
+                        "album": {"artists": ["Artist 1"], "albumtitle": "Album 1"},
+                        "length": 240
+                    },
+                    "duration": 200,
+                    "origin": "client:test"
+                },
+                {
+                    "time": 1700000300,
+                    "track": {"artists": ["Artist 3"], "title": "Track 2", "album": null, "length": null},
+                    "duration": 180
+                },
+                {
+                    "time": 1700000600,
+                    "track": {"artists": ["Artist 3"], "title": "Track 3", "length": 200},
+                    "play_count": 2
+                }
+            ]
+        }"#;
+
+        let plays = MalojaParser::parse(export.as_bytes()).unwrap();
+
+        assert_eq!(plays.len(), 4);
+        assert_eq!(plays[0].track_name, "Track 1");
+        assert_eq!(plays[0].artists.as_ref().unwrap().len(), 2);
+        assert_eq!(plays[0].release_name, Some("Album 1".to_string()));
+        assert_eq!(plays[0].duration, Some(240));
+        assert_eq!(plays[0].played_time.unwrap().timestamp(), 1700000000);
+        assert_eq!(plays[1].release_name, None);
+        assert_eq!(plays[1].duration, Some(180));
+        assert_eq!(plays[2].track_name, "Track 3");
+        assert_eq!(plays[2].played_time.unwrap().timestamp(), 1700000400);
+        assert_eq!(plays[3].played_time.unwrap().timestamp(), 1700000600);
+    }
+}
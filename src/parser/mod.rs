@@ -1,8 +1,11 @@
 pub mod audio_scrobbler;
+pub mod delimited;
 pub mod json;
+pub mod maloja;
+pub mod pano;
 
 mod error;
 mod log_parser;
 
-pub use error::ParserError;
-pub use log_parser::LogParser;
+pub use error::{LineError, ParserError};
+pub use log_parser::{LogParser, LogPlay};
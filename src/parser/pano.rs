@@ -0,0 +1,205 @@
+use chrono::{DateTime, FixedOffset, TimeZone, Utc};
+use serde::Deserialize;
+use std::{
+    collections::HashSet,
+    io::{BufRead, Read},
+};
+
+use crate::{
+    parser::{LogParser, ParserError},
+    record::{Artist, Play},
+};
+
+#[derive(Debug)]
+pub struct PanoParser();
+
+#[derive(Debug, Deserialize)]
+struct PanoExport {
+    scrobbles: Vec<PanoScrobble>,
+}
+
+// Pano stores times and durations in milliseconds
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PanoScrobble {
+    #[serde(alias = "trackName")]
+    track: String,
+    #[serde(alias = "artistName")]
+    artist: String,
+    #[serde(alias = "albumName")]
+    album: Option<String>,
+    album_artist: Option<String>,
+    #[serde(alias = "timeMs", alias = "timestamp")]
+    time: i64,
+    #[serde(alias = "durationMs")]
+    duration: Option<i64>,
+    #[serde(alias = "mbid")]
+    track_mbid: Option<String>,
+    artist_mbid: Option<String>,
+    album_mbid: Option<String>,
+    /// Entry type, e.g. "scrobble", "love", or "skip", absent for plain listens
+    event: Option<String>,
+    #[serde(default)]
+    skipped: bool,
+    #[serde(default)]
+    loved: bool,
+    /// Not part of Pano's own exports, for tools that collapse back to back
+    /// plays of a track into one entry
+    #[serde(alias = "play_count")]
+    play_count: Option<u32>,
+}
+
+impl PanoScrobble {
+    fn is_listen(&self) -> bool {
+        !self.skipped && self.event.as_deref().is_none_or(|e| e == "scrobble")
+    }
+
+    fn is_skip(&self) -> bool {
+        self.skipped || self.event.as_deref() == Some("skip")
+    }
+
+    fn is_love(&self) -> bool {
+        self.event.as_deref() == Some("love")
+    }
+}
+
+fn non_empty(s: Option<String>) -> Option<String> {
+    s.filter(|s| !s.is_empty())
+}
+
+impl PanoParser {
+    pub fn parse<R>(reader: R) -> Result<Vec<Play>, ParserError>
+    where
+        R: Read,
+    {
+        Ok(Self::parse_with_skips(reader)?.0)
+    }
+
+    /// Parse an export into listens, along with the tracks it marks as
+    /// skipped, which are marked as such
+    pub fn parse_with_skips<R>(reader: R) -> Result<(Vec<Play>, Vec<Play>), ParserError>
+    where
+        R: Read,
+    {
+        let export: PanoExport =
+            serde_json::from_reader(reader).map_err(|e| ParserError::Syntax(e.to_string()))?;
+
+        // loves are separate entries, so mark every listen of a loved track
+        let loved: HashSet<(String, String)> = export
+            .scrobbles
+            .iter()
+            .filter(|s| s.is_love())
+            .map(|s| (s.track.clone(), s.artist.clone()))
+            .collect();
+
+        let mut plays = Vec::new();
+        let mut skips = Vec::new();
+
+        for scrobble in export.scrobbles {
+            let is_skip = scrobble.is_skip();
+            if !is_skip && !scrobble.is_listen() {
+                continue;
+            }
+
+            let played_time: DateTime<FixedOffset> = Utc
+                .timestamp_millis_opt(scrobble.time)
+                .single()
+                .ok_or_else(|| {
+                    ParserError::Syntax(format!("invalid scrobble time '{}'", scrobble.time))
+                })?
+                .into();
+
+            let is_loved = scrobble.loved
+                || loved.contains(&(scrobble.track.clone(), scrobble.artist.clone()));
+
+            let artists = vec![Artist {
+                artist_name: scrobble.artist,
+                artist_mb_id: non_empty(scrobble.artist_mbid),
+            }];
+
+            let play_count = scrobble.play_count;
+            let play = Play {
+                track_name: scrobble.track,
+                artists: Some(artists),
+                release_name: non_empty(scrobble.album),
+                album_artist: non_empty(scrobble.album_artist),
+                release_mb_id: non_empty(scrobble.album_mbid),
+                track_mb_id: non_empty(scrobble.track_mbid),
+                duration: scrobble.duration.filter(|d| *d > 0).map(|d| d / 1000),
+                played_time: Some(played_time),
+                loved: is_loved.then_some(true),
+                skipped: is_skip.then_some(true),
+                ..Default::default()
+            };
+
+            let copies = match play_count {
+                None | Some(1) => vec![play],
+                Some(n) => play.repeat(n)?,
+            };
+
+            if is_skip {
+                skips.extend(copies);
+            } else {
+                plays.extend(copies);
+            }
+        }
+
+        Ok((plays, skips))
+    }
+}
+
+impl LogParser for PanoParser {
+    fn parse_reader<R: BufRead>(reader: R) -> Result<Vec<Play>, ParserError> {
+        Self::parse(reader)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_export() {
+        let export = r#"{
+            "scrobbles": [
+                {
+                    "track": "Track 1",
+                    "artist": "Artist 1",
+                    "album": "Album 1",
+                    "albumArtist": "Artist 1",
+                    "time": 1700000000000,
+                    "duration": 240000,
+                    "trackMbid": "",
+                    "artistMbid": "artist-id"
+                },
+                {"track": "Track 2", "artist": "Artist 2", "time": 1700000300000, "event": "love"},
+                {"track": "Track 2", "artist": "Artist 2", "time": 1700000400000, "duration": 60000, "playCount": 2},
+                {"track": "Track 3", "artist": "Artist 3", "time": 1700000600000, "skipped": true},
+                {"track": "Track 4", "artist": "Artist 4", "time": 1700000700000, "event": "skip"}
+            ]
+        }"#;
+
+        let (plays, skips) = PanoParser::parse_with_skips(export.as_bytes()).unwrap();
+
+        assert_eq!(plays.len(), 3);
+        assert_eq!(skips.len(), 2);
+        assert_eq!(skips[0].track_name, "Track 3");
+        assert_eq!(skips[0].skipped, Some(true));
+        assert_eq!(skips[1].track_name, "Track 4");
+        assert_eq!(plays[0].track_name, "Track 1");
+        assert_eq!(plays[0].loved, None);
+        assert_eq!(plays[1].track_name, "Track 2");
+        assert_eq!(plays[1].loved, Some(true));
+        assert_eq!(plays[1].played_time.unwrap().timestamp(), 1700000340);
+        assert_eq!(plays[2].played_time.unwrap().timestamp(), 1700000400);
+        assert_eq!(plays[0].release_name, Some("Album 1".to_string()));
+        assert_eq!(plays[0].album_artist, Some("Artist 1".to_string()));
+        assert_eq!(plays[0].track_mb_id, None);
+        assert_eq!(
+            plays[0].artists.as_ref().unwrap()[0].artist_mb_id,
+            Some("artist-id".to_string())
+        );
+        assert_eq!(plays[0].duration, Some(240));
+        assert_eq!(plays[0].played_time.unwrap().timestamp(), 1700000000);
+    }
+}
@@ -1,24 +1,45 @@
-use chrono::{DateTime, FixedOffset};
-use jacquard::{CowStr, smol_str::ToSmolStr, types::string::Datetime};
+use chrono::{DateTime, FixedOffset, Utc};
+use jacquard::{
+    CowStr,
+    smol_str::{SmolStr, ToSmolStr},
+    types::{
+        string::{AtprotoStr, Datetime},
+        value::{Data, Object},
+    },
+};
+use owo_colors::{OwoColorize, Stream::Stdout, Style};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+use crate::{error::OnyxError, parser::ParserError};
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct Artist {
     pub artist_name: String,
     pub artist_mb_id: Option<String>,
 }
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct Play {
     pub track_name: String,
     pub track_mb_id: Option<String>,
     pub recording_mb_id: Option<String>,
     pub duration: Option<i64>,
+    /// Older form of `artists` as parallel arrays, still accepted as input
+    /// but folded into `artists` by `normalize_artists` before submitting
     pub artist_names: Option<Vec<String>>,
     pub artist_mb_ids: Option<Vec<String>>,
+    /// The canonical artist list
     pub artists: Option<Vec<Artist>>,
     pub release_name: Option<String>,
     pub release_mb_id: Option<String>,
+    pub album_artist: Option<String>,
+    pub disc_number: Option<i64>,
+    pub loved: Option<bool>,
+    pub source: Option<String>,
+    /// The track was skipped rather than listened to, such plays go to the
+    /// skip collection rather than the feed, see `Scrobbler::submit`
+    pub skipped: Option<bool>,
     pub isrc: Option<String>,
     pub origin_url: Option<String>,
     pub music_service_base_domain: Option<String>,
@@ -28,7 +49,7 @@ pub struct Play {
     pub release_discriminant: Option<String>,
 }
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct PlayView {
     pub track_name: String,
     pub track_mb_id: Option<String>,
@@ -42,15 +63,288 @@ pub struct PlayView {
     pub music_service_base_domain: Option<String>,
     pub submission_client_agent: Option<String>,
     pub played_time: Option<DateTime<FixedOffset>>,
+    pub track_discriminant: Option<String>,
+    pub release_discriminant: Option<String>,
 }
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct Status {
     pub time: DateTime<FixedOffset>,
     pub expiry: Option<DateTime<FixedOffset>>,
     pub item: PlayView,
 }
 
+/// Local time format used by `Status::display` unless overridden
+pub const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// `DEFAULT_DATE_FORMAT` with a 12-hour clock
+pub const TWELVE_HOUR_DATE_FORMAT: &str = "%Y-%m-%d %I:%M:%S %p";
+
+/// How closely two plays match, from most to least specific
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlayMatch {
+    Track,
+    Release,
+    Artist,
+    None,
+}
+
+/// A status as fetched at a point in time
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatusSnapshot {
+    pub captured: DateTime<Utc>,
+    pub status: Status,
+}
+
+fn is_mb_id(s: &str) -> bool {
+    let groups: Vec<&str> = s.split('-').collect();
+    let lengths = [8, 4, 4, 4, 12];
+
+    groups.len() == lengths.len()
+        && groups
+            .iter()
+            .zip(lengths)
+            .all(|(g, len)| g.len() == len && g.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+fn is_isrc(s: &str) -> bool {
+    let chars: Vec<char> = s.chars().collect();
+
+    chars.len() == 12
+        && chars[0..2].iter().all(|c| c.is_ascii_alphabetic())
+        && chars[2..5].iter().all(|c| c.is_ascii_alphanumeric())
+        && chars[5..12].iter().all(|c| c.is_ascii_digit())
+}
+
+fn check_mb_id(field: &str, value: Option<&String>) -> Result<(), OnyxError> {
+    match value {
+        Some(id) if !is_mb_id(id) => Err(OnyxError::Parse(format!(
+            "`{}` must be a MusicBrainz ID (UUID), got '{}'",
+            field, id
+        ))),
+        _ => Ok(()),
+    }
+}
+
+fn check_isrc(value: Option<&String>) -> Result<(), OnyxError> {
+    match value {
+        Some(isrc) if !is_isrc(isrc) => Err(OnyxError::Parse(format!(
+            "`isrc` must be in the form CCXXXYYNNNNN, got '{}'",
+            isrc
+        ))),
+        _ => Ok(()),
+    }
+}
+
+fn check_artist_ids(artists: &[Artist]) -> Result<(), OnyxError> {
+    for artist in artists {
+        check_mb_id("artist_mb_ids", artist.artist_mb_id.as_ref())?;
+    }
+
+    Ok(())
+}
+
+/// Length assumed for a track without a duration when spacing out repeated
+/// plays, about the average for popular music
+const ESTIMATED_DURATION: i64 = 210;
+
+/// Most plays `Play::repeat` makes of one track, a log entry counting more
+/// is far more likely a corrupt row than a track on repeat
+pub const MAX_PLAY_COUNT: u32 = 1000;
+
+impl Play {
+    /// Copies of the play for a track on repeat, `count` plays back to back
+    /// spaced by its duration, or `ESTIMATED_DURATION` without one, the last
+    /// starting at its played time (or now)
+    pub fn repeat(&self, count: u32) -> Result<Vec<Play>, ParserError> {
+        if count > MAX_PLAY_COUNT {
+            return Err(ParserError::Other(format!(
+                "play count {} is over the limit of {}",
+                count, MAX_PLAY_COUNT
+            )));
+        }
+
+        let end = self
+            .played_time
+            .unwrap_or_else(|| chrono::Local::now().into());
+        let duration = self
+            .duration
+            .filter(|d| *d > 0)
+            .unwrap_or(ESTIMATED_DURATION);
+
+        (0..count)
+            .rev()
+            .map(|i| {
+                let played_time = duration
+                    .checked_mul(i64::from(i))
+                    .and_then(chrono::Duration::try_seconds)
+                    .and_then(|offset| end.checked_sub_signed(offset))
+                    .ok_or_else(|| {
+                        ParserError::Other(format!(
+                            "{} plays of '{}' reach too far back",
+                            count, self.track_name
+                        ))
+                    })?;
+
+                Ok(Play {
+                    played_time: Some(played_time),
+                    ..self.clone()
+                })
+            })
+            .collect()
+    }
+
+    /// Check the fields a play record needs are present
+    pub fn validate(&self) -> Result<(), OnyxError> {
+        if self.track_name.trim().is_empty() {
+            return Err(OnyxError::Parse("`track_name` must not be empty".into()));
+        }
+
+        let has_artist = self
+            .artists
+            .iter()
+            .flatten()
+            .any(|a| !a.artist_name.trim().is_empty())
+            || self
+                .artist_names
+                .iter()
+                .flatten()
+                .any(|a| !a.trim().is_empty());
+
+        if !has_artist {
+            return Err(OnyxError::Parse(format!(
+                "'{}' needs at least one artist",
+                self.track_name
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Strip leading and trailing whitespace from the track, release and
+    /// artist names, which some exporters pad
+    pub fn trim_names(&mut self) {
+        let trim = |s: &mut String| *s = s.trim().to_string();
+
+        trim(&mut self.track_name);
+        self.release_name.iter_mut().for_each(trim);
+        self.album_artist.iter_mut().for_each(trim);
+        self.artist_names.iter_mut().flatten().for_each(trim);
+
+        for artist in self.artists.iter_mut().flatten() {
+            trim(&mut artist.artist_name);
+        }
+    }
+
+    /// The legacy `artist_names`/`artist_mb_ids` arrays as artists, pairing
+    /// IDs with names by position
+    fn legacy_artists(&self) -> Option<Vec<Artist>> {
+        let names = self.artist_names.as_ref()?;
+
+        Some(
+            names
+                .iter()
+                .enumerate()
+                .map(|(i, name)| Artist {
+                    artist_name: name.clone(),
+                    artist_mb_id: self
+                        .artist_mb_ids
+                        .as_ref()
+                        .and_then(|ids| ids.get(i).cloned()),
+                })
+                .collect(),
+        )
+    }
+
+    /// Leave `artists` as the only artist list, so a record can't carry two
+    /// that disagree. The legacy arrays become `artists` if that's unset, and
+    /// are cleared either way.
+    pub fn normalize_artists(&mut self) {
+        if self.artists.is_none() {
+            self.artists = self.legacy_artists();
+        }
+
+        self.artist_names = None;
+        self.artist_mb_ids = None;
+    }
+
+    /// Check that MusicBrainz IDs and the ISRC are well-formed
+    pub fn validate_ids(&self) -> Result<(), OnyxError> {
+        check_mb_id("track_mb_id", self.track_mb_id.as_ref())?;
+        check_mb_id("recording_mb_id", self.recording_mb_id.as_ref())?;
+        check_mb_id("release_mb_id", self.release_mb_id.as_ref())?;
+        check_isrc(self.isrc.as_ref())?;
+
+        if let Some(artists) = &self.artists {
+            check_artist_ids(artists)?;
+        }
+
+        for id in self.artist_mb_ids.iter().flatten() {
+            check_mb_id("artist_mb_ids", Some(id))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl PlayView {
+    /// Take any field that wasn't given from `current`, for `status set
+    /// --merge`. An empty string counts as given, so it still clears the
+    /// field once `clear_empty` runs.
+    pub fn merge_unset(&mut self, current: PlayView) {
+        if self.track_name.is_empty() {
+            self.track_name = current.track_name;
+        }
+
+        let fields = [
+            (&mut self.track_mb_id, current.track_mb_id),
+            (&mut self.recording_mb_id, current.recording_mb_id),
+            (&mut self.release_name, current.release_name),
+            (&mut self.release_mb_id, current.release_mb_id),
+            (&mut self.isrc, current.isrc),
+            (&mut self.origin_url, current.origin_url),
+            (&mut self.track_discriminant, current.track_discriminant),
+            (&mut self.release_discriminant, current.release_discriminant),
+        ];
+
+        for (field, current) in fields {
+            if field.is_none() {
+                *field = current;
+            }
+        }
+
+        self.duration = self.duration.or(current.duration);
+        self.played_time = self.played_time.or(current.played_time);
+    }
+
+    /// Treat fields given as empty strings as unset
+    pub fn clear_empty(&mut self) {
+        for field in [
+            &mut self.track_mb_id,
+            &mut self.recording_mb_id,
+            &mut self.release_name,
+            &mut self.release_mb_id,
+            &mut self.isrc,
+            &mut self.origin_url,
+            &mut self.track_discriminant,
+            &mut self.release_discriminant,
+        ] {
+            if field.as_ref().is_some_and(|f| f.trim().is_empty()) {
+                *field = None;
+            }
+        }
+    }
+
+    /// Check that MusicBrainz IDs and the ISRC are well-formed
+    pub fn validate_ids(&self) -> Result<(), OnyxError> {
+        check_mb_id("track_mb_id", self.track_mb_id.as_ref())?;
+        check_mb_id("recording_mb_id", self.recording_mb_id.as_ref())?;
+        check_mb_id("release_mb_id", self.release_mb_id.as_ref())?;
+        check_isrc(self.isrc.as_ref())?;
+        check_artist_ids(&self.artists)
+    }
+}
+
 impl From<jacquard_api::fm_teal::alpha::feed::Artist<'_>> for Artist {
     fn from(value: jacquard_api::fm_teal::alpha::feed::Artist) -> Self {
         Self {
@@ -70,9 +364,27 @@ impl From<Artist> for jacquard_api::fm_teal::alpha::feed::Artist<'static> {
     }
 }
 
+// The play lexicon has no album artist, disc number, loved flag or source, so
+// these are carried as extra fields on the record
+const EXTRA_ALBUM_ARTIST: &str = "albumArtist";
+const EXTRA_DISC_NUMBER: &str = "discNumber";
+const EXTRA_LOVED: &str = "loved";
+const EXTRA_SOURCE: &str = "source";
+
 impl From<jacquard_api::fm_teal::alpha::feed::play::Play<'_>> for Play {
     fn from(value: jacquard_api::fm_teal::alpha::feed::play::Play<'_>) -> Self {
+        let extra = |key: &str| value.extra_data.as_ref().and_then(|d| d.get(key));
+
         Self {
+            album_artist: extra(EXTRA_ALBUM_ARTIST)
+                .and_then(|d| d.as_str())
+                .map(|s| s.to_string()),
+            disc_number: extra(EXTRA_DISC_NUMBER).and_then(|d| d.as_integer()),
+            loved: extra(EXTRA_LOVED).and_then(|d| d.as_boolean()),
+            source: extra(EXTRA_SOURCE)
+                .and_then(|d| d.as_str())
+                .map(|s| s.to_string()),
+            skipped: None,
             track_name: value.track_name.to_string(),
             track_mb_id: value.track_mb_id.map(|s| s.to_string()),
             recording_mb_id: value.recording_mb_id.map(|s| s.to_string()),
@@ -101,6 +413,33 @@ impl From<jacquard_api::fm_teal::alpha::feed::play::Play<'_>> for Play {
 
 impl From<Play> for jacquard_api::fm_teal::alpha::feed::play::Play<'static> {
     fn from(val: Play) -> Self {
+        let mut extra_data = BTreeMap::new();
+
+        if let Some(album_artist) = val.album_artist {
+            extra_data.insert(
+                SmolStr::new_static(EXTRA_ALBUM_ARTIST),
+                Data::String(AtprotoStr::String(CowStr::Owned(album_artist.to_smolstr()))),
+            );
+        }
+
+        if let Some(disc_number) = val.disc_number {
+            extra_data.insert(
+                SmolStr::new_static(EXTRA_DISC_NUMBER),
+                Data::Integer(disc_number),
+            );
+        }
+
+        if let Some(loved) = val.loved {
+            extra_data.insert(SmolStr::new_static(EXTRA_LOVED), Data::Boolean(loved));
+        }
+
+        if let Some(source) = val.source {
+            extra_data.insert(
+                SmolStr::new_static(EXTRA_SOURCE),
+                Data::String(AtprotoStr::String(CowStr::Owned(source.to_smolstr()))),
+            );
+        }
+
         jacquard_api::fm_teal::alpha::feed::play::Play {
             track_name: CowStr::Owned(val.track_name.to_smolstr()),
             track_mb_id: val.track_mb_id.map(|s| CowStr::Owned(s.to_smolstr())),
@@ -132,13 +471,48 @@ impl From<Play> for jacquard_api::fm_teal::alpha::feed::play::Play<'static> {
             release_discriminant: val
                 .release_discriminant
                 .map(|s| CowStr::Owned(s.to_smolstr())),
-            extra_data: None,
+            extra_data: (!extra_data.is_empty()).then_some(extra_data),
+        }
+    }
+}
+
+// Unlike plays, the play view lexicon has no discriminants, so they are
+// carried as extra fields under the same names the play lexicon uses
+const EXTRA_TRACK_DISCRIMINANT: &str = "trackDiscriminant";
+const EXTRA_RELEASE_DISCRIMINANT: &str = "releaseDiscriminant";
+
+impl From<&Play> for PlayView {
+    fn from(play: &Play) -> Self {
+        // artists can be given either way on a play, a status only has one
+        let artists = play
+            .artists
+            .clone()
+            .or_else(|| play.legacy_artists())
+            .unwrap_or_default();
+
+        Self {
+            track_name: play.track_name.clone(),
+            track_mb_id: play.track_mb_id.clone(),
+            recording_mb_id: play.recording_mb_id.clone(),
+            duration: play.duration,
+            artists,
+            release_name: play.release_name.clone(),
+            release_mb_id: play.release_mb_id.clone(),
+            isrc: play.isrc.clone(),
+            origin_url: play.origin_url.clone(),
+            music_service_base_domain: play.music_service_base_domain.clone(),
+            submission_client_agent: play.submission_client_agent.clone(),
+            played_time: play.played_time,
+            track_discriminant: play.track_discriminant.clone(),
+            release_discriminant: play.release_discriminant.clone(),
         }
     }
 }
 
 impl From<jacquard_api::fm_teal::alpha::feed::PlayView<'_>> for PlayView {
     fn from(value: jacquard_api::fm_teal::alpha::feed::PlayView<'_>) -> Self {
+        let extra = |key: &str| value.extra_data.as_ref().and_then(|d| d.get(key));
+
         Self {
             track_name: value.track_name.to_string(),
             track_mb_id: value.track_mb_id.map(|s| s.to_string()),
@@ -152,12 +526,32 @@ impl From<jacquard_api::fm_teal::alpha::feed::PlayView<'_>> for PlayView {
             music_service_base_domain: value.music_service_base_domain.map(|s| s.to_string()),
             submission_client_agent: value.submission_client_agent.map(|s| s.to_string()),
             played_time: value.played_time.map(|dt| *dt.as_ref()),
+            track_discriminant: extra(EXTRA_TRACK_DISCRIMINANT)
+                .and_then(|d| d.as_str())
+                .map(|s| s.to_string()),
+            release_discriminant: extra(EXTRA_RELEASE_DISCRIMINANT)
+                .and_then(|d| d.as_str())
+                .map(|s| s.to_string()),
         }
     }
 }
 
 impl From<PlayView> for jacquard_api::fm_teal::alpha::feed::PlayView<'static> {
     fn from(val: PlayView) -> Self {
+        let mut extra_data = BTreeMap::new();
+
+        for (key, value) in [
+            (EXTRA_TRACK_DISCRIMINANT, val.track_discriminant),
+            (EXTRA_RELEASE_DISCRIMINANT, val.release_discriminant),
+        ] {
+            if let Some(value) = value {
+                extra_data.insert(
+                    SmolStr::new_static(key),
+                    Data::String(AtprotoStr::String(CowStr::Owned(value.to_smolstr()))),
+                );
+            }
+        }
+
         jacquard_api::fm_teal::alpha::feed::PlayView {
             track_name: CowStr::Owned(val.track_name.to_smolstr()),
             track_mb_id: val.track_mb_id.map(|s| CowStr::Owned(s.to_smolstr())),
@@ -175,7 +569,7 @@ impl From<PlayView> for jacquard_api::fm_teal::alpha::feed::PlayView<'static> {
                 .submission_client_agent
                 .map(|s| CowStr::Owned(s.to_smolstr())),
             played_time: val.played_time.map(Datetime::new),
-            extra_data: None,
+            extra_data: (!extra_data.is_empty()).then_some(extra_data),
         }
     }
 }
@@ -190,6 +584,81 @@ impl From<jacquard_api::fm_teal::alpha::actor::status::Status<'_>> for Status {
     }
 }
 
+fn data_string(data: &Object<'_>, key: &str) -> Option<String> {
+    data.get(key)
+        .and_then(|d| d.as_str())
+        .map(|s| s.to_string())
+}
+
+fn data_time(data: &Object<'_>, key: &str) -> Option<DateTime<FixedOffset>> {
+    data.get(key)
+        .and_then(|d| d.as_str())
+        .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+}
+
+impl From<&Object<'_>> for PlayView {
+    fn from(value: &Object<'_>) -> Self {
+        let artists = value
+            .get("artists")
+            .and_then(|d| d.as_array())
+            .map(|a| {
+                a.iter()
+                    .filter_map(|d| d.as_object())
+                    .filter_map(|o| {
+                        Some(Artist {
+                            artist_name: data_string(o, "artistName")?,
+                            artist_mb_id: data_string(o, "artistMbId"),
+                        })
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Self {
+            track_name: data_string(value, "trackName").unwrap_or_default(),
+            track_mb_id: data_string(value, "trackMbId"),
+            recording_mb_id: data_string(value, "recordingMbId"),
+            duration: value.get("duration").and_then(|d| d.as_integer()),
+            artists,
+            release_name: data_string(value, "releaseName"),
+            release_mb_id: data_string(value, "releaseMbId"),
+            isrc: data_string(value, "isrc"),
+            origin_url: data_string(value, "originUrl"),
+            music_service_base_domain: data_string(value, "musicServiceBaseDomain"),
+            submission_client_agent: data_string(value, "submissionClientAgent"),
+            played_time: data_time(value, "playedTime"),
+            track_discriminant: data_string(value, EXTRA_TRACK_DISCRIMINANT),
+            release_discriminant: data_string(value, EXTRA_RELEASE_DISCRIMINANT),
+        }
+    }
+}
+
+/// Read a status record field by field rather than through the lexicon type,
+/// so a record with a partial or missing `item` still loads. Only `time` is
+/// required.
+impl TryFrom<&Data<'_>> for Status {
+    type Error = OnyxError;
+
+    fn try_from(value: &Data<'_>) -> Result<Self, Self::Error> {
+        let record = value
+            .as_object()
+            .ok_or_else(|| OnyxError::Parse("status record is not an object".to_string()))?;
+
+        let time = data_time(record, "time")
+            .ok_or_else(|| OnyxError::Parse("status record has no valid time".to_string()))?;
+
+        Ok(Self {
+            time,
+            expiry: data_time(record, "expiry"),
+            item: record
+                .get("item")
+                .and_then(|d| d.as_object())
+                .map(PlayView::from)
+                .unwrap_or_default(),
+        })
+    }
+}
+
 impl From<Status> for jacquard_api::fm_teal::alpha::actor::status::Status<'static> {
     fn from(val: Status) -> Self {
         jacquard_api::fm_teal::alpha::actor::status::Status {
@@ -201,126 +670,632 @@ impl From<Status> for jacquard_api::fm_teal::alpha::actor::status::Status<'stati
     }
 }
 
+fn same_id(a: &Option<String>, b: &Option<String>) -> bool {
+    matches!((a, b), (Some(a), Some(b)) if a.eq_ignore_ascii_case(b))
+}
+
+fn same_name(a: &str, b: &str) -> bool {
+    !a.is_empty() && a.trim().to_lowercase() == b.trim().to_lowercase()
+}
+
+impl PlayView {
+    fn same_artist(&self, other: &PlayView) -> bool {
+        self.artists.iter().any(|a| {
+            other.artists.iter().any(|b| {
+                same_id(&a.artist_mb_id, &b.artist_mb_id)
+                    || same_name(&a.artist_name, &b.artist_name)
+            })
+        })
+    }
+
+    /// Compare two plays, preferring MusicBrainz IDs and falling back to names
+    pub fn compare(&self, other: &PlayView) -> PlayMatch {
+        let same_artist = self.same_artist(other);
+
+        let same_track = same_id(&self.recording_mb_id, &other.recording_mb_id)
+            || same_id(&self.track_mb_id, &other.track_mb_id)
+            || (same_artist && same_name(&self.track_name, &other.track_name));
+
+        let same_release = same_id(&self.release_mb_id, &other.release_mb_id)
+            || match (&self.release_name, &other.release_name) {
+                (Some(a), Some(b)) => same_artist && same_name(a, b),
+                _ => false,
+            };
+
+        if same_track {
+            PlayMatch::Track
+        } else if same_release {
+            PlayMatch::Release
+        } else if same_artist {
+            PlayMatch::Artist
+        } else {
+            PlayMatch::None
+        }
+    }
+}
+
 impl Status {
-    pub fn display(&self, raw: bool, full: bool) {
-        // if both track name and artists are blank, probably nothing's playing
-        if self.item.track_name.is_empty() && self.item.artists.is_empty() && !raw {
-            println!("nothing playing right now");
-            return;
+    fn format_time(time: &DateTime<FixedOffset>, raw: bool, date_format: Option<&str>) -> String {
+        if raw {
+            time.format("%Y-%m-%d %H:%M:%S %:z").to_string()
+        } else {
+            let local_dt = time.with_timezone(&chrono::Local);
+            local_dt
+                .format(date_format.unwrap_or(DEFAULT_DATE_FORMAT))
+                .to_string()
+        }
+    }
+
+    /// Format a duration in seconds as `m:ss`, or `h:mm:ss` from an hour
+    /// up, `None` for a negative duration, which can only be bad data
+    fn format_duration(duration: i64) -> Option<String> {
+        if duration < 0 {
+            return None;
         }
 
-        println!("track: {}", self.item.track_name);
+        let hours = duration / 3600;
+        let minutes = (duration % 3600) / 60;
+        let seconds = duration % 60;
+
+        if hours > 0 {
+            Some(format!("{}:{:02}:{:02}", hours, minutes, seconds))
+        } else {
+            Some(format!("{}:{:02}", minutes, seconds))
+        }
+    }
+
+    /// Whether the status is blank, which usually means nothing is playing
+    pub fn is_empty(&self) -> bool {
+        self.item.track_name.is_empty() && self.item.artists.is_empty()
+    }
+
+    /// Labelled fields shown by `display`, in display order
+    pub fn fields(
+        &self,
+        raw: bool,
+        full: bool,
+        date_format: Option<&str>,
+    ) -> Vec<(&'static str, String)> {
+        let mut fields = vec![("track", self.item.track_name.clone())];
 
         if let Some(track_id) = &self.item.track_mb_id
             && full
         {
-            println!("track id: {}", track_id);
+            fields.push(("track id", track_id.clone()));
         }
 
         if let Some(recording_id) = &self.item.recording_mb_id
             && full
         {
-            println!("recording id: {}", recording_id);
+            fields.push(("recording id", recording_id.clone()));
         }
 
         if !self.item.artists.is_empty() || raw {
-            print!("artists: ");
-
-            for i in 0..self.item.artists.len() {
-                print!("{}", self.item.artists[i].artist_name);
-
-                if let Some(artist_id) = &self.item.artists[i].artist_mb_id
-                    && full
-                {
-                    print!(" [{}]", artist_id);
-                }
-
-                if i != self.item.artists.len() - 1 {
-                    print!(", ");
-                }
-            }
+            let artists: Vec<String> = self
+                .item
+                .artists
+                .iter()
+                .map(|artist| match &artist.artist_mb_id {
+                    Some(artist_id) if full => format!("{} [{}]", artist.artist_name, artist_id),
+                    _ => artist.artist_name.clone(),
+                })
+                .collect();
 
-            println!();
+            fields.push(("artists", artists.join(", ")));
         }
 
         if let Some(release) = &self.item.release_name {
-            println!("release: {}", release);
+            fields.push(("release", release.clone()));
         }
 
         if let Some(release_id) = &self.item.release_mb_id
             && full
         {
-            println!("release id: {}", release_id);
+            fields.push(("release id", release_id.clone()));
         }
 
         if let Some(isrc) = &self.item.isrc
             && full
         {
-            println!("isrc: {}", isrc);
+            fields.push(("isrc", isrc.clone()));
+        }
+
+        if let Some(discriminant) = &self.item.track_discriminant
+            && full
+        {
+            fields.push(("track variant", discriminant.clone()));
+        }
+
+        if let Some(discriminant) = &self.item.release_discriminant
+            && full
+        {
+            fields.push(("release variant", discriminant.clone()));
         }
 
         if let Some(played_time) = &self.item.played_time {
-            if raw {
-                println!("played: {}", played_time.format("%Y-%m-%d %H:%M:%S %:z"));
-            } else {
-                let local_dt = played_time.with_timezone(&chrono::Local);
-                println!("played: {}", local_dt.format("%Y-%m-%d %H:%M:%S"));
-            }
+            fields.push(("played", Self::format_time(played_time, raw, date_format)));
         }
 
         if let Some(duration) = self.item.duration {
             if raw {
-                println!("duration: {}", duration);
-            } else {
-                let hours = duration / 3600;
-                let minutes = (duration - (hours * 3600)) / 60;
-                let seconds = duration - (minutes * 60);
-
-                let mut duration_str = "".to_string();
-                if hours > 0 {
-                    duration_str = format!("{:02}:", hours);
-                }
-                if minutes > 0 || hours > 0 {
-                    duration_str = format!("{}{:02}:", duration_str, minutes);
-                }
-                if seconds > 0 || minutes > 0 || hours > 0 {
-                    duration_str = format!("{}{:02}", duration_str, seconds);
-                }
-
-                println!("duration: {}", duration_str);
+                fields.push(("duration", duration.to_string()));
+            } else if let Some(duration) = Self::format_duration(duration) {
+                fields.push(("duration", duration));
             }
         }
 
         if let Some(service) = &self.item.music_service_base_domain
             && full
         {
-            println!("service: {}", service);
+            fields.push(("service", service.clone()));
         }
 
         if let Some(client) = &self.item.submission_client_agent
             && full
         {
-            println!("client: {}", client);
+            fields.push(("client", client.clone()));
         }
 
         if full {
-            if raw {
-                println!("time: {}", self.time.format("%Y-%m-%d %H:%M:%S %:z"));
-            } else {
-                let local_dt = self.time.with_timezone(&chrono::Local);
-                println!("time: {}", local_dt.format("%Y-%m-%d %H:%M:%S"));
-            }
+            fields.push(("time", Self::format_time(&self.time, raw, date_format)));
         }
 
         if let Some(expiry) = &self.expiry
             && full
         {
-            if raw {
-                println!("expiry: {}", expiry.format("%Y-%m-%d %H:%M:%S %:z"));
+            fields.push(("expiry", Self::format_time(expiry, raw, date_format)));
+        }
+
+        fields
+    }
+
+    /// Fields as in `fields`, but with an `artist` field per artist,
+    /// followed by its `artist id` when `full`, so artist names with commas
+    /// in them can't be mistaken for several artists
+    pub fn split_fields(
+        &self,
+        raw: bool,
+        full: bool,
+        date_format: Option<&str>,
+    ) -> Vec<(&'static str, String)> {
+        let mut fields = Vec::new();
+
+        for (label, value) in self.fields(raw, full, date_format) {
+            if label != "artists" {
+                fields.push((label, value));
+                continue;
+            }
+
+            for artist in &self.item.artists {
+                fields.push(("artist", artist.artist_name.clone()));
+
+                if let Some(artist_id) = &artist.artist_mb_id
+                    && full
+                {
+                    fields.push(("artist id", artist_id.clone()));
+                }
+            }
+        }
+
+        fields
+    }
+
+    pub fn display(&self, raw: bool, full: bool, long: bool, date_format: Option<&str>) {
+        // if both track name and artists are blank, probably nothing's playing
+        if self.is_empty() && !raw {
+            println!("nothing playing right now");
+            return;
+        }
+
+        for (label, value) in self.fields(raw, full, date_format) {
+            self.print_field(label, &value, full, long, false);
+        }
+    }
+
+    /// Display the status, highlighting fields that differ from `previous`
+    pub fn display_changes(
+        &self,
+        previous: &Status,
+        raw: bool,
+        full: bool,
+        long: bool,
+        date_format: Option<&str>,
+    ) {
+        if self.is_empty() && !raw {
+            println!("nothing playing right now");
+            return;
+        }
+
+        let previous_fields = previous.fields(raw, full, date_format);
+
+        for (label, value) in self.fields(raw, full, date_format) {
+            let changed = !previous_fields
+                .iter()
+                .any(|(l, v)| *l == label && *v == value);
+
+            self.print_field(label, &value, full, long, changed);
+        }
+    }
+
+    /// Print one field, under `long` the artists go one per line with their
+    /// IDs indented beneath them instead of inline
+    fn print_field(&self, label: &str, value: &str, full: bool, long: bool, highlight: bool) {
+        let style = |line: &str| {
+            if highlight {
+                line.if_supports_color(Stdout, |t| t.style(Style::new().bright_green().bold()))
+                    .to_string()
             } else {
-                let local_dt = expiry.with_timezone(&chrono::Local);
-                println!("expiry: {}", local_dt.format("%Y-%m-%d %H:%M:%S"));
+                line.to_string()
             }
+        };
+
+        if !(long && label == "artists") {
+            println!("{}: {}", label, style(value));
+            return;
         }
+
+        println!("{}:", label);
+        for artist in &self.item.artists {
+            println!("  {}", style(&artist.artist_name));
+
+            if let Some(artist_id) = &artist.artist_mb_id
+                && full
+            {
+                println!("    {}", style(artist_id));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_ids() {
+        assert!(is_mb_id("5b11f4ce-a62d-471e-81fc-a69a8278c7da"));
+        assert!(!is_mb_id("5b11f4ce-a62d-471e-81fc-a69a8278c7d"));
+        assert!(!is_mb_id("5b11f4cea62d471e81fca69a8278c7da"));
+        assert!(!is_mb_id("zb11f4ce-a62d-471e-81fc-a69a8278c7da"));
+
+        assert!(is_isrc("USRC17607839"));
+        assert!(!is_isrc("US-RC1-76-07839"));
+        assert!(!is_isrc("USRC1760783X"));
+
+        let play = Play {
+            track_name: "Track".to_string(),
+            isrc: Some("bad".to_string()),
+            ..Default::default()
+        };
+
+        match play.validate_ids() {
+            Err(OnyxError::Parse(msg)) => assert!(msg.contains("isrc")),
+            other => panic!("expected parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_validate_play() {
+        let mut play = Play {
+            track_name: " ".to_string(),
+            artist_names: Some(vec!["Artist 1".to_string()]),
+            ..Default::default()
+        };
+        assert!(matches!(play.validate(), Err(OnyxError::Parse(_))));
+
+        play.track_name = "Track 1".to_string();
+        assert!(play.validate().is_ok());
+
+        play.artist_names = None;
+        play.artists = Some(vec![Artist::default()]);
+        assert!(matches!(play.validate(), Err(OnyxError::Parse(_))));
+    }
+
+    #[test]
+    fn test_compare_plays() {
+        let artist = |name: &str| Artist {
+            artist_name: name.to_string(),
+            artist_mb_id: None,
+        };
+
+        let play = PlayView {
+            track_name: "Track 1".to_string(),
+            artists: vec![artist("Artist 1"), artist("Artist 2")],
+            release_name: Some("Album 1".to_string()),
+            ..Default::default()
+        };
+
+        let same_track = PlayView {
+            track_name: "track 1".to_string(),
+            artists: vec![artist("Artist 2")],
+            ..Default::default()
+        };
+
+        let same_release = PlayView {
+            track_name: "Track 2".to_string(),
+            release_name: Some("Album 1".to_string()),
+            ..same_track.clone()
+        };
+
+        let same_artist = PlayView {
+            release_name: None,
+            ..same_release.clone()
+        };
+
+        let different = PlayView {
+            track_name: "Track 1".to_string(),
+            artists: vec![artist("Artist 3")],
+            ..Default::default()
+        };
+
+        assert_eq!(play.compare(&same_track), PlayMatch::Track);
+        assert_eq!(play.compare(&same_release), PlayMatch::Release);
+        assert_eq!(play.compare(&same_artist), PlayMatch::Artist);
+        assert_eq!(play.compare(&different), PlayMatch::None);
+    }
+
+    #[test]
+    fn test_repeat_play() {
+        let mut track = Play {
+            track_name: "Track".to_string(),
+            played_time: DateTime::parse_from_rfc3339("2024-01-01T12:00:00Z").ok(),
+            duration: Some(180),
+            ..Default::default()
+        };
+
+        let plays = track.repeat(3).unwrap();
+        let times: Vec<String> = plays
+            .iter()
+            .map(|p| p.played_time.unwrap().to_rfc3339())
+            .collect();
+        assert_eq!(
+            times,
+            [
+                "2024-01-01T11:54:00+00:00",
+                "2024-01-01T11:57:00+00:00",
+                "2024-01-01T12:00:00+00:00"
+            ]
+        );
+
+        // without a duration, plays are spaced by an estimate so they don't
+        // collapse into duplicates
+        track.duration = None;
+        let plays = track.repeat(2).unwrap();
+        assert_eq!(
+            plays[1].played_time.unwrap() - plays[0].played_time.unwrap(),
+            chrono::Duration::seconds(ESTIMATED_DURATION)
+        );
+
+        assert!(track.repeat(MAX_PLAY_COUNT + 1).is_err());
+
+        track.duration = Some(i64::MAX);
+        assert!(track.repeat(2).is_err());
+    }
+
+    #[test]
+    fn test_status_fields() {
+        assert_eq!(Status::format_duration(0), Some("0:00".to_string()));
+        assert_eq!(Status::format_duration(5), Some("0:05".to_string()));
+        assert_eq!(Status::format_duration(59), Some("0:59".to_string()));
+        assert_eq!(Status::format_duration(245), Some("4:05".to_string()));
+        assert_eq!(Status::format_duration(3725), Some("1:02:05".to_string()));
+        assert_eq!(Status::format_duration(-5), None);
+
+        let status = Status {
+            item: PlayView {
+                track_name: "Track 1".to_string(),
+                artists: vec![Artist {
+                    artist_name: "Artist 1".to_string(),
+                    artist_mb_id: None,
+                }],
+                duration: Some(3725),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        assert_eq!(
+            status.fields(false, false, None),
+            vec![
+                ("track", "Track 1".to_string()),
+                ("artists", "Artist 1".to_string()),
+                ("duration", "1:02:05".to_string()),
+            ]
+        );
+
+        // a negative duration is left out, unless showing raw values
+        let mut status = status;
+        status.item.duration = Some(-1);
+        assert!(
+            !status
+                .fields(false, false, None)
+                .iter()
+                .any(|(l, _)| *l == "duration")
+        );
+        assert!(
+            status
+                .fields(true, false, None)
+                .contains(&("duration", "-1".to_string()))
+        );
+
+        // split fields give each artist its own field, commas and all
+        status.item.artists.push(Artist {
+            artist_name: "Crosby, Stills & Nash".to_string(),
+            artist_mb_id: Some("e4cfa2ae-8ac1-4d1b-a2e8-4b1e7d2f4a1c".to_string()),
+        });
+        assert_eq!(
+            status.split_fields(false, true, None)[..4],
+            [
+                ("track", "Track 1".to_string()),
+                ("artist", "Artist 1".to_string()),
+                ("artist", "Crosby, Stills & Nash".to_string()),
+                (
+                    "artist id",
+                    "e4cfa2ae-8ac1-4d1b-a2e8-4b1e7d2f4a1c".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_extra_data_round_trip() {
+        let play = Play {
+            track_name: "Track".to_string(),
+            album_artist: Some("Album Artist".to_string()),
+            disc_number: Some(2),
+            loved: Some(true),
+            source: Some("spotify".to_string()),
+            ..Default::default()
+        };
+
+        let record: jacquard_api::fm_teal::alpha::feed::play::Play = play.into();
+        assert_eq!(record.extra_data.as_ref().map(|d| d.len()), Some(4));
+
+        let play: Play = record.into();
+        assert_eq!(play.album_artist, Some("Album Artist".to_string()));
+        assert_eq!(play.disc_number, Some(2));
+        assert_eq!(play.loved, Some(true));
+        assert_eq!(play.source, Some("spotify".to_string()));
+
+        let record: jacquard_api::fm_teal::alpha::feed::play::Play = Play::default().into();
+        assert!(record.extra_data.is_none());
+
+        let view = PlayView {
+            track_name: "Track".to_string(),
+            track_discriminant: Some("Live".to_string()),
+            release_discriminant: Some("Deluxe".to_string()),
+            ..Default::default()
+        };
+
+        let record: jacquard_api::fm_teal::alpha::feed::PlayView = view.into();
+        assert_eq!(record.extra_data.as_ref().map(|d| d.len()), Some(2));
+
+        let view: PlayView = record.into();
+        assert_eq!(view.track_discriminant, Some("Live".to_string()));
+        assert_eq!(view.release_discriminant, Some("Deluxe".to_string()));
+    }
+
+    #[test]
+    fn test_minimal_status_record() {
+        let data = |json: &str| Data::from_json_owned(serde_json::from_str(json).unwrap()).unwrap();
+
+        let status = Status::try_from(&data(r#"{"time": "2024-01-01T12:00:00Z"}"#)).unwrap();
+        assert_eq!(status.time.to_rfc3339(), "2024-01-01T12:00:00+00:00");
+        assert!(status.expiry.is_none());
+        assert!(status.item.track_name.is_empty());
+        assert!(status.item.artists.is_empty());
+
+        let status = Status::try_from(&data(
+            r#"{
+                "time": "2024-01-01T12:00:00Z",
+                "item": {"trackName": "Track", "artists": [{"artistName": "Artist"}, {}]}
+            }"#,
+        ))
+        .unwrap();
+        assert_eq!(status.item.track_name, "Track");
+        assert_eq!(status.item.artists.len(), 1);
+        assert!(status.item.duration.is_none());
+
+        assert!(matches!(
+            Status::try_from(&data(r#"{"item": {"trackName": "Track"}}"#)),
+            Err(OnyxError::Parse(_))
+        ));
+    }
+
+    #[test]
+    fn test_trim_names() {
+        let mut play = Play {
+            track_name: "  Track ".to_string(),
+            release_name: Some("\tAlbum  ".to_string()),
+            artists: Some(vec![Artist {
+                artist_name: " Artist ".to_string(),
+                artist_mb_id: None,
+            }]),
+            artist_names: Some(vec!["Other Artist  ".to_string()]),
+            ..Default::default()
+        };
+
+        play.trim_names();
+        assert_eq!(play.track_name, "Track");
+        assert_eq!(play.release_name, Some("Album".to_string()));
+        assert_eq!(play.artists.unwrap()[0].artist_name, "Artist");
+        assert_eq!(play.artist_names, Some(vec!["Other Artist".to_string()]));
+    }
+
+    #[test]
+    fn test_normalize_artists() {
+        let mut play = Play {
+            track_name: "Track".to_string(),
+            artist_names: Some(vec!["Artist 1".to_string(), "Artist 2".to_string()]),
+            artist_mb_ids: Some(vec!["id_1".to_string(), "id_2".to_string()]),
+            ..Default::default()
+        };
+        play.normalize_artists();
+
+        let artists = play.artists.as_ref().unwrap();
+        assert_eq!(artists.len(), 2);
+        assert_eq!(artists[1].artist_name, "Artist 2");
+        assert_eq!(artists[1].artist_mb_id, Some("id_2".to_string()));
+        assert_eq!(play.artist_names, None);
+        assert_eq!(play.artist_mb_ids, None);
+
+        // `artists` wins when both are set
+        let mut play = Play {
+            track_name: "Track".to_string(),
+            artists: Some(vec![Artist {
+                artist_name: "Artist 1".to_string(),
+                artist_mb_id: None,
+            }]),
+            artist_names: Some(vec!["Someone Else".to_string()]),
+            ..Default::default()
+        };
+        play.normalize_artists();
+
+        assert_eq!(play.artists.as_ref().unwrap()[0].artist_name, "Artist 1");
+        assert_eq!(play.artist_names, None);
+    }
+
+    #[test]
+    fn test_play_view_from_play() {
+        let play = Play {
+            track_name: "Track".to_string(),
+            artist_names: Some(vec!["Artist 1".to_string(), "Artist 2".to_string()]),
+            artist_mb_ids: Some(vec!["id_1".to_string()]),
+            duration: Some(200),
+            ..Default::default()
+        };
+
+        let view = PlayView::from(&play);
+        assert_eq!(view.track_name, "Track");
+        assert_eq!(view.duration, Some(200));
+        assert_eq!(view.artists.len(), 2);
+        assert_eq!(view.artists[0].artist_mb_id, Some("id_1".to_string()));
+        assert_eq!(view.artists[1].artist_name, "Artist 2");
+        assert_eq!(view.artists[1].artist_mb_id, None);
+    }
+
+    #[test]
+    fn test_merge_unset() {
+        let current = PlayView {
+            track_name: "Track".to_string(),
+            release_name: Some("Album".to_string()),
+            isrc: Some("USRC17607839".to_string()),
+            duration: Some(180),
+            ..Default::default()
+        };
+
+        let mut play = PlayView {
+            release_name: Some("".to_string()),
+            duration: Some(200),
+            ..Default::default()
+        };
+
+        play.merge_unset(current);
+        play.clear_empty();
+
+        assert_eq!(play.track_name, "Track");
+        assert_eq!(play.release_name, None);
+        assert_eq!(play.isrc, Some("USRC17607839".to_string()));
+        assert_eq!(play.duration, Some(200));
     }
 }
@@ -1,22 +1,493 @@
-use std::{io::BufRead, path::PathBuf};
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::{BufRead, BufReader, IsTerminal, Write},
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, FixedOffset, Utc};
+use indicatif::{ProgressBar, ProgressStyle};
 
-use jacquard::client::{Agent, AgentSessionExt};
-use jacquard_api::fm_teal::alpha::feed as fm_teal_feed;
-use owo_colors::OwoColorize;
+use jacquard::{
+    client::{Agent, AgentSessionExt},
+    prelude::XrpcClient,
+    smol_str::SmolStr,
+    types::{
+        aturi::AtUri,
+        did::Did,
+        ident::AtIdentifier,
+        nsid::Nsid,
+        string::AtprotoStr,
+        value::{Data, to_data},
+    },
+};
+use jacquard_api::{
+    com_atproto::repo::{
+        apply_writes::{ApplyWrites, ApplyWritesWritesItem, Delete},
+        create_record::CreateRecord,
+    },
+    fm_teal::alpha::feed as fm_teal_feed,
+};
+use notify_debouncer_mini::{DebouncedEventKind, new_debouncer, notify::RecursiveMode};
+use owo_colors::{OwoColorize, Stream::Stdout, Style};
+use serde::Serialize;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt};
 
 use crate::{
     LogFormat,
     auth::GenericSession,
     error::OnyxError,
-    parser::{LogParser, audio_scrobbler::AudioScrobblerParser, json::JsonParser},
-    record::Play,
+    feed::PlayRecord,
+    history::{History, HistoryEntry},
+    http::HttpOptions,
+    label::{Label, label, stderr_label},
+    metrics,
+    musicbrainz::MusicBrainzClient,
+    parser::{
+        LineError, LogParser, LogPlay, ParserError, audio_scrobbler::AudioScrobblerParser,
+        delimited::DelimitedParser, json::JsonParser, maloja::MalojaParser, pano::PanoParser,
+    },
+    record::{Artist, DEFAULT_DATE_FORMAT, Play},
+    systemd,
 };
 
+/// Plays read from a log file
+#[derive(Debug, Default)]
+pub struct ParsedLog {
+    pub plays: Vec<LogPlay>,
+    /// Entries the log marks as skipped, only scrobbled when recording skips
+    pub skips: Vec<LogPlay>,
+    /// Entries left out because they failed to parse, when skipping errors
+    pub errors: Vec<LineError>,
+}
+
+impl ParsedLog {
+    /// The plays, without where they were in the log
+    pub fn into_plays(self) -> Vec<Play> {
+        self.plays.into_iter().map(|p| p.play).collect()
+    }
+}
+
+/// Path that stands for standard input in place of a log file
+pub const STDIN_PATH: &str = "-";
+
+/// Parse a log file in the given format, or standard input if `path` is `-`
+///
+/// With `skip_errors`, entries that fail to parse are left out and kept in
+/// `ParsedLog::errors` rather than failing the whole log.
+pub fn parse_log(
+    path: &Path,
+    format: &LogFormat,
+    delimited: Option<&DelimitedParser>,
+    skip_errors: bool,
+) -> Result<ParsedLog, OnyxError> {
+    let reader: Box<dyn BufRead> = if path == Path::new(STDIN_PATH) {
+        Box::new(std::io::stdin().lock())
+    } else {
+        Box::new(BufReader::new(File::open(path).map_err(|e| {
+            OnyxError::Io(format!("failed to read {}: {}", path.display(), e))
+        })?))
+    };
+
+    // these are read as a single document, there are no entries to skip
+    if skip_errors && matches!(format, LogFormat::Maloja | LogFormat::Pano) {
+        return Err(OnyxError::Parse(
+            "`--skip-errors` isn't supported for Maloja and Pano exports".to_string(),
+        ));
+    }
+
+    let mut errors = Vec::new();
+
+    let plays = match format {
+        LogFormat::AudioScrobbler => {
+            let (plays, skips) =
+                AudioScrobblerParser::parse_with_skips(reader, skip_errors.then_some(&mut errors))?;
+            return Ok(ParsedLog {
+                plays,
+                skips,
+                errors,
+            });
+        }
+        LogFormat::Json => JsonParser::parse_lines(reader, skip_errors.then_some(&mut errors)),
+        LogFormat::Maloja => parse_with::<MalojaParser>(reader, &mut errors, skip_errors),
+        LogFormat::Pano => {
+            let (plays, skips) = PanoParser::parse_with_skips(reader)?;
+            let unnumbered = |plays: Vec<Play>| {
+                plays
+                    .into_iter()
+                    .map(|play| LogPlay { line: None, play })
+                    .collect()
+            };
+
+            return Ok(ParsedLog {
+                plays: unnumbered(plays),
+                skips: unnumbered(skips),
+                errors,
+            });
+        }
+        LogFormat::GenericDelimited => match delimited {
+            Some(parser) => parser.parse(reader, skip_errors.then_some(&mut errors)),
+            None => {
+                return Err(OnyxError::Parse(
+                    "`--columns` is required for the generic-delimited format".to_string(),
+                ));
+            }
+        },
+    }?;
+
+    Ok(ParsedLog {
+        plays,
+        skips: Vec::new(),
+        errors,
+    })
+}
+
+/// Parse a format read as a single document with `P`, skipping entries
+/// that fail to parse if `skip_errors`
+fn parse_with<P: LogParser>(
+    reader: Box<dyn BufRead>,
+    errors: &mut Vec<LineError>,
+    skip_errors: bool,
+) -> Result<Vec<LogPlay>, ParserError> {
+    let plays = if skip_errors {
+        P::parse_reader_skipping(reader, errors)
+    } else {
+        P::parse_reader(reader)
+    }?;
+
+    Ok(plays
+        .into_iter()
+        .map(|play| LogPlay { line: None, play })
+        .collect())
+}
+
+/// How far past now a played time can be before it counts as in the
+/// future, leaving room for clocks that are slightly off
+const FUTURE_TOLERANCE: chrono::Duration = chrono::Duration::minutes(5);
+
+/// Whether the play's time is in the future beyond `FUTURE_TOLERANCE`,
+/// usually from a device with a wrong clock
+fn is_future(play: &Play) -> bool {
+    play.played_time
+        .is_some_and(|t| t > chrono::Local::now() + FUTURE_TOLERANCE)
+}
+
+/// Artists to keep or leave out of an import, matched by name ignoring case
+#[derive(Debug, Clone, Default)]
+pub struct ArtistFilter {
+    /// If not empty, only plays by at least one of these are kept
+    pub include: Vec<String>,
+    /// Plays by any of these are left out, even if included
+    pub exclude: Vec<String>,
+}
+
+impl ArtistFilter {
+    fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    /// Whether the play passes the filter
+    fn allows(&self, play: &Play) -> bool {
+        let normalize = |name: &str| name.trim().to_lowercase();
+        let artists: Vec<String> = match (&play.artists, &play.artist_names) {
+            (Some(artists), _) => artists.iter().map(|a| normalize(&a.artist_name)).collect(),
+            (None, Some(names)) => names.iter().map(|n| normalize(n)).collect(),
+            (None, None) => Vec::new(),
+        };
+        let matches = |names: &[String]| names.iter().any(|n| artists.contains(&normalize(n)));
+
+        (self.include.is_empty() || matches(&self.include)) && !matches(&self.exclude)
+    }
+}
+
+/// What makes two plays duplicates, see `merge_plays`
+type PlayKey = (String, Option<DateTime<FixedOffset>>, Vec<String>);
+
+fn play_key(play: &Play) -> PlayKey {
+    let artists = play
+        .artists
+        .as_ref()
+        .map(|v| v.iter().map(|a| a.artist_name.clone()).collect())
+        .or_else(|| play.artist_names.clone())
+        .unwrap_or_default();
+
+    (play.track_name.clone(), play.played_time, artists)
+}
+
+/// Merge plays from several logs into chronological order, dropping duplicates
+///
+/// Plays are considered duplicates if they share a track name, artists, and
+/// played time. Plays without a played time are kept in their original order,
+/// ahead of timestamped plays.
+pub fn merge_plays(logs: Vec<Vec<Play>>) -> Vec<Play> {
+    merge_by(logs.into_iter().flatten().collect(), |p| p)
+}
+
+/// `merge_plays` over anything holding a play
+fn merge_by<T>(mut items: Vec<T>, play: impl Fn(&T) -> &Play) -> Vec<T> {
+    items.sort_by_key(|i| play(i).played_time);
+
+    let mut seen = HashSet::new();
+    items.retain(|i| {
+        let p = play(i);
+        p.played_time.is_none() || seen.insert(play_key(p))
+    });
+
+    items
+}
+
+/// A play to submit in a batch, with where it came from so submission
+/// errors can point back at the offending entry
+struct LogEntry {
+    /// Where the play came from, such as its entry in a log file
+    origin: String,
+    play: Play,
+}
+
+/// Ask before submitting a large import, showing how many plays it has and
+/// the range they cover
+fn confirm_import(entries: &[LogEntry]) -> Result<bool, OnyxError> {
+    let times = entries.iter().filter_map(|e| e.play.played_time);
+    let range = match (times.clone().min(), times.max()) {
+        (Some(first), Some(last)) => format!(
+            ", played {} to {}",
+            first.format(DEFAULT_DATE_FORMAT),
+            last.format(DEFAULT_DATE_FORMAT)
+        ),
+        _ => String::new(),
+    };
+
+    println!(
+        "{}: about to submit {} plays{}",
+        label(Label::Warning),
+        entries.len(),
+        range
+    );
+
+    if !std::io::stdin().is_terminal() {
+        return Err(OnyxError::Other(
+            "large import needs confirmation, use `--yes` to skip it".into(),
+        ));
+    }
+
+    let answer = crate::prompt("continue? [y/N]: ")?;
+    Ok(matches!(answer.to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// Artists listed in an import summary under `--summary`
+const SUMMARY_TOP_ARTISTS: usize = 5;
+
+/// Most played artists and the time span covered by a set of plays
+#[derive(Debug, PartialEq)]
+struct ImportSummary {
+    /// Artist names and play counts, most played first, ties by name
+    top_artists: Vec<(String, usize)>,
+    span: Option<(DateTime<FixedOffset>, DateTime<FixedOffset>)>,
+}
+
+impl ImportSummary {
+    fn from_plays(plays: &[Play]) -> Self {
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for play in plays {
+            let mut play = play.clone();
+            play.normalize_artists();
+
+            for artist in play.artists.unwrap_or_default() {
+                *counts.entry(artist.artist_name).or_default() += 1;
+            }
+        }
+
+        let mut top_artists: Vec<(String, usize)> = counts.into_iter().collect();
+        top_artists.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        top_artists.truncate(SUMMARY_TOP_ARTISTS);
+
+        let times = plays.iter().filter_map(|p| p.played_time);
+        let span = times.clone().min().zip(times.max());
+
+        Self { top_artists, span }
+    }
+
+    fn print(&self) {
+        if let Some((first, last)) = self.span {
+            println!(
+                "  played {} to {}",
+                first.format(DEFAULT_DATE_FORMAT),
+                last.format(DEFAULT_DATE_FORMAT)
+            );
+        }
+
+        if !self.top_artists.is_empty() {
+            println!("  top artists:");
+        }
+
+        for (name, count) in &self.top_artists {
+            println!(
+                "    {} {}",
+                name.if_supports_color(Stdout, |t| t.magenta()),
+                format!("({} plays)", count).if_supports_color(Stdout, |t| t.dimmed())
+            );
+        }
+    }
+}
+
+/// Print the `[✓]`/`[✗]` line for one submitted track
+fn print_result(name: &str, ok: bool) {
+    if ok {
+        println!(
+            "{} {}",
+            "[✓]".if_supports_color(Stdout, |t| t.style(Style::new().green().bold())),
+            name
+        );
+    } else {
+        println!(
+            "{} {}",
+            "[✗]".if_supports_color(Stdout, |t| t.style(Style::new().red().bold())),
+            name
+        );
+    }
+}
+
+fn skipped_summary(skipped: usize) -> String {
+    if skipped == 0 {
+        String::new()
+    } else {
+        format!(", {} skipped", skipped)
+    }
+}
+
+fn future_summary(future: usize) -> String {
+    if future == 0 {
+        String::new()
+    } else {
+        format!(", {} dated in the future", future)
+    }
+}
+
+fn generate_client_agent(service: &str, version: &str, id: Option<String>) -> String {
+    if let Some(id) = id {
+        format!("{}/{} ({})", service, version, id)
+    } else {
+        format!("{}/{}", service, version)
+    }
+}
+
+/// Write plays as newline-delimited teal.fm play records, as they would be
+/// submitted by `Scrobbler::scrobble_track`
+pub fn export_plays<W>(
+    plays: Vec<Play>,
+    service: &str,
+    version: &str,
+    mut writer: W,
+) -> Result<usize, OnyxError>
+where
+    W: Write,
+{
+    let count = plays.len();
+
+    for mut play in plays {
+        play.normalize_artists();
+        play.submission_client_agent = Some(generate_client_agent(
+            service,
+            version,
+            play.submission_client_agent,
+        ));
+
+        let record: fm_teal_feed::play::Play = play.into();
+        serde_json::to_writer(&mut writer, &record)?;
+        writeln!(writer)?;
+    }
+
+    writer.flush()?;
+    Ok(count)
+}
+
+/// Payload posted to the webhook after each successful scrobble
+#[derive(Debug, Serialize)]
+struct WebhookEvent<'a> {
+    event: &'static str,
+    track: &'a str,
+    artists: Vec<&'a str>,
+    played_time: Option<DateTime<FixedOffset>>,
+    uri: String,
+}
+
+/// A URL notified of successful scrobbles
+#[derive(Debug, Clone)]
+pub struct Webhook {
+    pub url: url::Url,
+    pub header: Option<(String, String)>,
+
+    client: reqwest::Client,
+}
+
+impl Webhook {
+    pub fn new(
+        url: url::Url,
+        header: Option<(String, String)>,
+        timeout: std::time::Duration,
+        http: &HttpOptions,
+    ) -> Result<Self, OnyxError> {
+        let http = HttpOptions {
+            timeout: Some(timeout),
+            ..http.clone()
+        };
+
+        Ok(Self {
+            url,
+            header,
+            client: http.build_client()?,
+        })
+    }
+
+    async fn send(&self, event: &WebhookEvent<'_>) -> Result<(), OnyxError> {
+        let mut request = self
+            .client
+            .post(self.url.clone())
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(serde_json::to_vec(event)?);
+
+        if let Some((name, value)) = &self.header {
+            request = request.header(name, value);
+        }
+
+        request
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| OnyxError::Network(e.to_string()))?;
+
+        Ok(())
+    }
+}
+
+/// Most operations `applyWrites` accepts in one call
+const MAX_BATCH_WRITES: usize = 200;
+
+/// Collection skipped tracks are written to. teal has no skip lexicon, so a
+/// skip is a play record kept apart from the feed, where anything counting
+/// plays would take it for a listen. The NSID is under onyx's own domain,
+/// since teal's namespace is teal's to define.
+pub const SKIP_COLLECTION: &str = "io.github.olduser101.onyx.skip";
+
 pub struct Scrobbler {
     pub service: String,
     pub version: String,
 
     agent: Agent<GenericSession>,
+    webhook: Option<Webhook>,
+    history: Option<History>,
+    lookup: Option<MusicBrainzClient>,
+    trim: bool,
+    source: Option<String>,
+    record_skips: bool,
+    summary: bool,
+    since: Option<DateTime<FixedOffset>>,
+    strict: bool,
+    skip_errors: bool,
+    time_offset: Option<chrono::Duration>,
+    artist_filter: ArtistFilter,
+    quiet: bool,
+    manual: bool,
 }
 
 impl Scrobbler {
@@ -25,97 +496,861 @@ impl Scrobbler {
             service: service.to_owned(),
             version: version.to_owned(),
             agent: Agent::from(session),
+            webhook: None,
+            history: None,
+            lookup: None,
+            trim: true,
+            source: None,
+            record_skips: false,
+            summary: false,
+            since: None,
+            strict: false,
+            skip_errors: false,
+            time_offset: None,
+            artist_filter: ArtistFilter::default(),
+            quiet: false,
+            manual: false,
         }
     }
 
-    fn generate_client_agent(&self, id: Option<String>) -> String {
-        if let Some(id) = id {
-            format!("{}/{} ({})", self.service, self.version, id)
+    /// The agent plays are created through, for writing other records with
+    /// the same session
+    pub fn agent(&self) -> &Agent<GenericSession> {
+        &self.agent
+    }
+
+    pub fn with_webhook(mut self, webhook: Option<Webhook>) -> Self {
+        self.webhook = webhook;
+        self
+    }
+
+    pub fn with_history(mut self, history: Option<History>) -> Self {
+        self.history = history;
+        self
+    }
+
+    pub fn with_lookup(mut self, lookup: Option<MusicBrainzClient>) -> Self {
+        self.lookup = lookup;
+        self
+    }
+
+    /// Whether to trim whitespace from names before submitting, on by default
+    pub fn with_trim(mut self, trim: bool) -> Self {
+        self.trim = trim;
+        self
+    }
+
+    /// Where plays came from, recorded on plays that don't already have one
+    pub fn with_source(mut self, source: Option<String>) -> Self {
+        self.source = source;
+        self
+    }
+
+    /// Whether `scrobble_logfile` submits entries the log marks as skipped,
+    /// off by default
+    pub fn with_skips(mut self, record_skips: bool) -> Self {
+        self.record_skips = record_skips;
+        self
+    }
+
+    /// Whether `scrobble_logfile` follows its summary with the top artists
+    /// and time span of the submitted plays, off by default
+    pub fn with_summary(mut self, summary: bool) -> Self {
+        self.summary = summary;
+        self
+    }
+
+    /// Whether `scrobble_logfile` leaves out log entries that fail to parse,
+    /// listing them at the end, rather than failing the whole log, off by
+    /// default
+    pub fn with_skip_errors(mut self, skip_errors: bool) -> Self {
+        self.skip_errors = skip_errors;
+        self
+    }
+
+    /// Whether plays dated in the future are refused, rather than submitted
+    /// with a warning
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Have `scrobble_logfile` shift every play's time by this much, for
+    /// logs recorded with the device's clock off
+    pub fn with_time_offset(mut self, offset: Option<chrono::Duration>) -> Self {
+        self.time_offset = offset;
+        self
+    }
+
+    /// Have `scrobble_logfile` only submit plays the filter allows
+    pub fn with_artist_filter(mut self, filter: ArtistFilter) -> Self {
+        self.artist_filter = filter;
+        self
+    }
+
+    /// Have `scrobble_logfile` only submit plays after this time, leaving
+    /// out untimed ones since they can't be placed
+    pub fn with_since(mut self, since: Option<DateTime<FixedOffset>>) -> Self {
+        self.since = since;
+        self
+    }
+
+    /// Keep progress and results off standard output, for callers that
+    /// report the outcome themselves, warnings and errors go to standard
+    /// error instead
+    pub fn with_quiet(mut self, quiet: bool) -> Self {
+        self.quiet = quiet;
+        self
+    }
+
+    /// Mark plays in the history file as submitted by hand rather than
+    /// imported from a log, so `last_scrobble_time` passes over them
+    pub fn with_manual(mut self, manual: bool) -> Self {
+        self.manual = manual;
+        self
+    }
+
+    /// Report something that doesn't stop the submission
+    fn warn(&self, message: impl std::fmt::Display) {
+        if self.quiet {
+            eprintln!("{}: {}", stderr_label(Label::Warning), message);
         } else {
-            format!("{}/{}", self.service, self.version)
+            println!("{}: {}", label(Label::Warning), message);
         }
     }
 
-    pub async fn scrobble_track(&self, mut track: Play) -> Result<(), OnyxError> {
-        let name = track.track_name.clone();
+    /// Print a progress note, unless quiet
+    fn note(&self, message: impl std::fmt::Display) {
+        if !self.quiet {
+            println!("{}", message.if_supports_color(Stdout, |t| t.dimmed()));
+        }
+    }
+
+    /// Played time of the latest play imported to this account, going by
+    /// the history file
+    pub async fn last_scrobble_time(&self) -> Result<Option<DateTime<FixedOffset>>, OnyxError> {
+        let Some(history) = &self.history else {
+            return Err(OnyxError::Other(
+                "the last scrobble time comes from the history file, which is turned off".into(),
+            ));
+        };
 
-        let res = async {
-            track.submission_client_agent =
-                Some(self.generate_client_agent(track.submission_client_agent));
-            let play: fm_teal_feed::play::Play = track.into();
-            self.agent.create_record(play, None).await
+        let (did, _) = self
+            .agent
+            .info()
+            .await
+            .ok_or_else(|| OnyxError::Auth("session has no DID".to_string()))?;
+
+        history.last_played(did.as_str())
+    }
+
+    /// Fill in missing MusicBrainz IDs, if lookups are enabled, failures are
+    /// only reported since the play is still worth submitting without them
+    async fn lookup_ids(&self, track: &mut Play) {
+        let Some(lookup) = &self.lookup else {
+            return;
+        };
+
+        if let Err(e) = lookup.enrich(track).await {
+            self.warn(format!(
+                "MusicBrainz lookup failed for '{}': {}",
+                track.track_name, e
+            ));
         }
-        .await;
+    }
 
-        if let Err(e) = res {
-            println!("{} {}", "[✗]".red().bold(), name);
-            return Err(OnyxError::Other(format!("{}, for '{}'", e, name).into()));
+    /// Record a submitted play in the history file, if any, failures are only
+    /// reported since the play is already on the PDS
+    fn record_history(&self, play: Play, uri: &AtUri<'_>) {
+        let Some(history) = &self.history else {
+            return;
+        };
+
+        let entry = HistoryEntry {
+            submitted: Utc::now(),
+            uri: uri.to_string(),
+            rkey: uri.rkey().map(|r| r.as_ref().to_string()),
+            manual: self.manual,
+            play,
+        };
+
+        if let Err(e) = history.append(&entry) {
+            self.warn(format!(
+                "failed to record history in {}: {}",
+                history.path.display(),
+                e
+            ));
+        }
+    }
+
+    /// Drop deleted records from the history file, if any, failures are only
+    /// reported since the records are already gone from the PDS
+    fn forget_history(&self, records: &[PlayRecord]) {
+        let Some(history) = &self.history else {
+            return;
+        };
+
+        let uris: Vec<&str> = records.iter().map(|r| r.uri.as_str()).collect();
+        if let Err(e) = history.remove(&uris) {
+            self.warn(format!(
+                "failed to update history in {}: {}",
+                history.path.display(),
+                e
+            ));
+        }
+    }
+
+    /// Tell the webhook, if any, about a scrobble or skip, failures are only
+    /// reported
+    async fn notify(
+        &self,
+        event: &'static str,
+        track: &str,
+        artists: &[Artist],
+        played_time: Option<DateTime<FixedOffset>>,
+        uri: String,
+    ) {
+        let Some(webhook) = &self.webhook else {
+            return;
+        };
+
+        let event = WebhookEvent {
+            event,
+            track,
+            artists: artists.iter().map(|a| a.artist_name.as_str()).collect(),
+            played_time,
+            uri,
+        };
+
+        if let Err(e) = webhook.send(&event).await {
+            self.warn(format!("webhook failed for '{}': {}", track, e));
+        }
+    }
+
+    fn generate_client_agent(&self, id: Option<String>) -> String {
+        generate_client_agent(&self.service, &self.version, id)
+    }
+
+    /// Create the play record, without reporting the result, returning the
+    /// play as submitted
+    async fn submit(&self, mut track: Play) -> Result<Play, OnyxError> {
+        if self.trim {
+            track.trim_names();
+        }
+
+        track.normalize_artists();
+        track.validate()?;
+
+        if is_future(&track) {
+            let message = format!(
+                "'{}' is dated in the future, at {}",
+                track.track_name,
+                track.played_time.unwrap_or_default().to_rfc3339()
+            );
+
+            if self.strict {
+                return Err(OnyxError::Parse(message));
+            }
+
+            self.warn(message);
+        }
+
+        self.lookup_ids(&mut track).await;
+        if track.source.is_none() {
+            track.source = self.source.clone();
+        }
+
+        let name = track.track_name.clone();
+        let artists = track.artists.clone().unwrap_or_default();
+        let played_time = track.played_time;
+        let skipped = track.skipped == Some(true);
+
+        track.submission_client_agent =
+            Some(self.generate_client_agent(track.submission_client_agent));
+        let submitted = track.clone();
+        let play: fm_teal_feed::play::Play = track.into();
+
+        let created = if skipped {
+            self.create_skip(&play).await
         } else {
-            println!("{} {}", "[✓]".green().bold(), name);
+            self.agent
+                .create_record(play, None)
+                .await
+                .map(|output| output.uri)
+                .map_err(|e| OnyxError::Other(e.to_string().into()))
+        };
+
+        let uri = match created {
+            Ok(uri) => uri,
+            Err(e) => {
+                metrics::scrobble_failed();
+                return Err(OnyxError::Other(format!("{}, for '{}'", e, name).into()));
+            }
+        };
+
+        metrics::scrobble_submitted(&name, &artists);
+
+        self.record_history(submitted.clone(), &uri);
+
+        let event = if skipped { "skip" } else { "scrobble" };
+        self.notify(event, &name, &artists, played_time, uri.to_string())
+            .await;
+
+        Ok(submitted)
+    }
+
+    /// Create a skip record in `SKIP_COLLECTION`, through a plain
+    /// `createRecord` since it isn't the play lexicon's collection
+    async fn create_skip(
+        &self,
+        play: &fm_teal_feed::play::Play<'_>,
+    ) -> Result<AtUri<'static>, OnyxError> {
+        let (did, _) = self
+            .agent
+            .info()
+            .await
+            .ok_or_else(|| OnyxError::Auth("session has no DID".to_string()))?;
+        let collection = Nsid::new_static(SKIP_COLLECTION)?;
+
+        let mut record =
+            to_data(play).map_err(|e| OnyxError::Parse(format!("invalid skip: {}", e)))?;
+        if let Data::Object(object) = &mut record {
+            object.0.insert(
+                SmolStr::new_static("$type"),
+                Data::String(AtprotoStr::Nsid(collection.clone())),
+            );
         }
 
-        Ok(())
+        let request = CreateRecord::new()
+            .repo(AtIdentifier::Did(did))
+            .collection(collection)
+            .record(record)
+            .build();
+
+        let output = self
+            .agent
+            .send(request)
+            .await?
+            .into_output()
+            .map_err(|e| OnyxError::Other(e.to_string().into()))?;
+
+        Ok(output.uri)
+    }
+
+    /// Delete play records from `did`'s repo, batched into `applyWrites`
+    /// calls, returning how many were deleted, and dropping them from the
+    /// history file
+    pub async fn delete_plays(
+        &self,
+        did: &str,
+        records: &[PlayRecord],
+    ) -> Result<usize, OnyxError> {
+        let did = Did::new(did)?;
+        let mut deleted = 0;
+
+        for batch in records.chunks(MAX_BATCH_WRITES) {
+            let uris = batch
+                .iter()
+                .map(|r| AtUri::new(&r.uri))
+                .collect::<Result<Vec<_>, _>>()?;
+            let mut writes = Vec::new();
+
+            for (record, uri) in batch.iter().zip(&uris) {
+                let rkey = uri.rkey().ok_or_else(|| {
+                    OnyxError::Parse(format!("record URI {} has no record key", record.uri))
+                })?;
+                // skips from the history file live in their own collection
+                let collection = uri.collection().ok_or_else(|| {
+                    OnyxError::Parse(format!("record URI {} has no collection", record.uri))
+                })?;
+
+                writes.push(ApplyWritesWritesItem::Delete(Box::new(
+                    Delete::new()
+                        .collection(collection.clone())
+                        .rkey(rkey.clone())
+                        .build(),
+                )));
+            }
+
+            let request = ApplyWrites::new()
+                .repo(AtIdentifier::Did(did.clone()))
+                .writes(writes)
+                .build();
+
+            self.agent
+                .send(request)
+                .await?
+                .into_output()
+                .map_err(|e| OnyxError::Other(e.to_string().into()))?;
+
+            // per batch, so a later failure leaves the history matching the PDS
+            self.forget_history(batch);
+            deleted += batch.len();
+        }
+
+        Ok(deleted)
+    }
+
+    /// Submit a single track, returning it as submitted
+    pub async fn scrobble_track(&self, track: Play) -> Result<Play, OnyxError> {
+        let name = track.track_name.clone();
+        let res = self.submit(track).await;
+        if !self.quiet {
+            print_result(&name, res.is_ok());
+        }
+        res
+    }
+
+    /// Submit a track on repeat, `count` plays back to back as in
+    /// `Play::repeat`, returning the plays as submitted
+    pub async fn scrobble_repeated(&self, track: Play, count: u32) -> Result<Vec<Play>, OnyxError> {
+        let entries = track
+            .repeat(count)?
+            .into_iter()
+            .enumerate()
+            .map(|(i, play)| LogEntry {
+                origin: format!("repeat {} of {}", i + 1, count),
+                play,
+            })
+            .collect();
+
+        let (submitted, failed) = self.submit_entries(entries, true, 0, &[]).await;
+        if failed > 0 {
+            return Err(OnyxError::Other(
+                format!(
+                    "failed to scrobble {} of {} repeats, see errors above",
+                    failed, count
+                )
+                .into(),
+            ));
+        }
+
+        Ok(submitted)
     }
 
+    /// Submit the plays in log files, merged in chronological order,
+    /// returning them as submitted
     pub async fn scrobble_logfile(
         &self,
-        path: PathBuf,
+        paths: &[PathBuf],
         format: LogFormat,
-    ) -> Result<(), OnyxError> {
-        println!(
-            "{} {}",
-            "scrobbling log:".dimmed(),
-            path.to_str().unwrap().dimmed()
-        );
+        delimited: Option<DelimitedParser>,
+        verbose: bool,
+        limit: Option<usize>,
+        confirm_over: Option<usize>,
+    ) -> Result<Vec<Play>, OnyxError> {
+        let mut entries = Vec::new();
+        let mut skipped = 0;
+        let mut parse_errors = Vec::new();
 
-        let tracks = match format {
-            LogFormat::AudioScrobbler => <AudioScrobblerParser as LogParser>::parse(path.clone()),
-            LogFormat::Json => <JsonParser as LogParser>::parse(path.clone()),
-        }?;
+        for path in paths {
+            self.note(format!("scrobbling log: {}", path.to_str().unwrap()));
 
-        let count = tracks.len();
-        let mut errors = Vec::new();
+            let mut log = parse_log(path, &format, delimited.as_ref(), self.skip_errors)?;
+            parse_errors.extend(log.errors.drain(..).map(|e| (path, e)));
+            if self.record_skips {
+                log.plays.append(&mut log.skips);
+            }
+            if let Some(offset) = self.time_offset {
+                for p in &mut log.plays {
+                    let Some(time) = p.play.played_time.as_mut() else {
+                        continue;
+                    };
 
-        for track in tracks {
-            if let Err(e) = self.scrobble_track(track).await {
-                errors.push(e);
+                    *time = time.checked_add_signed(offset).ok_or_else(|| {
+                        OnyxError::Parse(format!(
+                            "the time offset moves '{}' in {} out of range",
+                            p.play.track_name,
+                            path.display()
+                        ))
+                    })?;
+                }
             }
+
+            skipped += log.skips.len();
+            entries.extend(log.plays.into_iter().enumerate().map(|(i, p)| LogEntry {
+                origin: match p.line {
+                    Some(line) => format!("line {} in {}", line, path.display()),
+                    None => format!("entry {} in {}", i + 1, path.display()),
+                },
+                play: p.play,
+            }));
         }
 
-        if !errors.is_empty() {
-            println!("\n{}:", "errors".red().bold());
+        let mut entries = merge_by(entries, |e| &e.play);
 
-            for error in &errors {
-                println!("  - {}", error);
-            }
+        if let Some(since) = self.since {
+            let before = entries.len();
+            let untimed = entries
+                .iter()
+                .filter(|e| e.play.played_time.is_none())
+                .count();
+            entries.retain(|e| e.play.played_time.is_some_and(|t| t > since));
 
-            println!(
-                "\n{}: {} tracks submitted, {} failed",
-                "summary".yellow().bold(),
-                count - errors.len(),
-                errors.len()
-            );
+            let untimed_note = if untimed > 0 {
+                format!(", and {} with no played time", untimed)
+            } else {
+                String::new()
+            };
+            self.note(format!(
+                "left out {} plays played at or before {}{}",
+                before - entries.len() - untimed,
+                since.format(DEFAULT_DATE_FORMAT),
+                untimed_note
+            ));
+        }
+
+        if !self.artist_filter.is_empty() {
+            let before = entries.len();
+            entries.retain(|e| self.artist_filter.allows(&e.play));
+
+            self.note(format!(
+                "left out {} plays by filtered artists",
+                before - entries.len()
+            ));
+        }
+
+        if let Some(limit) = limit {
+            entries.truncate(limit);
+        }
+
+        if let Some(threshold) = confirm_over
+            && entries.len() > threshold
+            && !confirm_import(&entries)?
+        {
+            return Err(OnyxError::Other("import cancelled".into()));
+        }
+
+        let (submitted, failed) = self
+            .submit_entries(entries, verbose, skipped, &parse_errors)
+            .await;
+
+        if failed > 0 {
+            let paths: Vec<&str> = paths.iter().map(|p| p.to_str().unwrap()).collect();
 
             return Err(OnyxError::Other(
                 format!(
                     "failed to scrobble log file {}, see errors above",
-                    path.to_str().unwrap()
+                    paths.join(", ")
                 )
                 .into(),
             ));
+        }
+
+        Ok(submitted)
+    }
+
+    /// Submit plays one at a time, reporting each failure and a summary at
+    /// the end, returning the plays as submitted and how many failed
+    ///
+    /// `skipped` and `parse_errors` are what was left out of the batch, for
+    /// the summary.
+    async fn submit_entries(
+        &self,
+        entries: Vec<LogEntry>,
+        verbose: bool,
+        skipped: usize,
+        parse_errors: &[(&PathBuf, LineError)],
+    ) -> (Vec<Play>, usize) {
+        let count = entries.len();
+        let future = entries.iter().filter(|e| is_future(&e.play)).count();
+
+        // per-track lines only under --verbose, otherwise a progress bar
+        let progress = if verbose || self.quiet {
+            ProgressBar::hidden()
         } else {
-            println!("\n{}: {} tracks submitted", "success".green().bold(), count);
+            ProgressBar::new(count as u64).with_style(
+                ProgressStyle::with_template("{bar:40} {pos}/{len} tracks, eta {eta} {msg}")
+                    .unwrap(),
+            )
+        };
+
+        // submissions run one at a time, so per-track lines come out in the
+        // same order as the entries, results are kept in that order too so
+        // the error list and summary don't depend on how they were submitted
+        let mut results = Vec::with_capacity(count);
+        let mut submitted = Vec::new();
+
+        for entry in entries {
+            progress.set_message(entry.play.track_name.clone());
+
+            let name = entry.play.track_name.clone();
+            let played = entry
+                .play
+                .played_time
+                .map_or("unknown".to_string(), |t| t.to_rfc3339());
+            let res = self.submit(entry.play).await;
+
+            if verbose && !self.quiet {
+                print_result(&name, res.is_ok());
+            }
+
+            match res {
+                Ok(play) => {
+                    submitted.push(play);
+                    results.push(Ok(()));
+                }
+                Err(e) => results.push(Err(format!("{} ({}, played {})", e, entry.origin, played))),
+            }
+
+            progress.inc(1);
+        }
+
+        progress.finish_and_clear();
+
+        let errors: Vec<String> = results.into_iter().filter_map(Result::err).collect();
+
+        // the caller reports the outcome, only what went wrong is left to say
+        if self.quiet {
+            for (path, e) in parse_errors {
+                self.warn(format!(
+                    "left out {}:{}: {}",
+                    path.display(),
+                    e.line,
+                    e.error
+                ));
+            }
+
+            for error in &errors {
+                eprintln!("{}: {}", stderr_label(Label::Error), error);
+            }
+
+            return (submitted, errors.len());
+        }
+
+        if !parse_errors.is_empty() {
+            println!(
+                "\n{}: left out {} entries that failed to parse:",
+                label(Label::Warning),
+                parse_errors.len()
+            );
+
+            for (path, e) in parse_errors {
+                println!("  - {}:{}: {}", path.display(), e.line, e.error);
+            }
         }
 
+        if !errors.is_empty() {
+            println!(
+                "\n{}:",
+                "errors".if_supports_color(Stdout, |t| t.style(Style::new().red().bold()))
+            );
+
+            for error in &errors {
+                println!("  - {}", error);
+            }
+
+            println!(
+                "\n{}: {} tracks submitted, {} failed{}{}",
+                label(Label::Summary),
+                count - errors.len(),
+                errors.len(),
+                skipped_summary(skipped),
+                future_summary(future)
+            );
+        } else {
+            println!(
+                "\n{}: {} tracks submitted{}{}",
+                label(Label::Success),
+                count,
+                skipped_summary(skipped),
+                future_summary(future)
+            );
+        }
+
+        if self.summary {
+            ImportSummary::from_plays(&submitted).print();
+        }
+
+        (submitted, errors.len())
+    }
+
+    /// Watch `dir` for new or changed log files and submit plays that
+    /// haven't been submitted before, until interrupted with Ctrl-C
+    ///
+    /// A file is only read once it has gone `settle` without changing, so a
+    /// player still writing it isn't read half way. Plays already in the
+    /// history file, or submitted earlier in this run, are skipped.
+    pub async fn watch_logs(
+        &self,
+        dir: &Path,
+        format: LogFormat,
+        delimited: Option<DelimitedParser>,
+        delete: bool,
+        settle: std::time::Duration,
+    ) -> Result<(), OnyxError> {
+        let mut seen: HashSet<_> = match &self.history {
+            Some(history) => {
+                let (did, _) = self
+                    .agent
+                    .info()
+                    .await
+                    .ok_or_else(|| OnyxError::Auth("session has no DID".to_string()))?;
+
+                history
+                    .entries_for(did.as_str())?
+                    .iter()
+                    .map(|e| play_key(&e.play))
+                    .collect()
+            }
+            None => HashSet::new(),
+        };
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut debouncer = new_debouncer(settle, move |res| {
+            let _ = tx.send(res);
+        })
+        .map_err(|e| OnyxError::Other(Box::new(e)))?;
+        debouncer
+            .watcher()
+            .watch(dir, RecursiveMode::NonRecursive)
+            .map_err(|e| OnyxError::Other(Box::new(e)))?;
+
+        self.note(format!(
+            "watching {} for logs, press Ctrl-C to stop",
+            dir.to_str().unwrap()
+        ));
+        systemd::ready();
+
+        loop {
+            let events = tokio::select! {
+                events = rx.recv() => match events {
+                    Some(events) => events.map_err(|e| OnyxError::Other(Box::new(e)))?,
+                    None => break,
+                },
+                _ = tokio::signal::ctrl_c() => break,
+                () = systemd::watchdog_due() => {
+                    systemd::watchdog();
+                    continue;
+                }
+            };
+
+            let paths: HashSet<PathBuf> = events
+                .into_iter()
+                .filter(|e| e.kind == DebouncedEventKind::Any && e.path.is_file())
+                .map(|e| e.path)
+                .collect();
+
+            for path in paths {
+                self.scrobble_new_plays(&path, &format, delimited.as_ref(), delete, &mut seen)
+                    .await;
+            }
+        }
+
+        systemd::stopping();
         Ok(())
     }
 
+    /// Submit the plays in one watched log that aren't in `seen`, failures
+    /// are only reported so the watch keeps going
+    ///
+    /// When quiet, the outcome is a single JSON line per log.
+    async fn scrobble_new_plays(
+        &self,
+        path: &Path,
+        format: &LogFormat,
+        delimited: Option<&DelimitedParser>,
+        delete: bool,
+        seen: &mut HashSet<PlayKey>,
+    ) {
+        let plays = match parse_log(path, format, delimited, false) {
+            Ok(log) => log.into_plays(),
+            Err(e) => {
+                self.warn(format!("skipping {}: {}", path.display(), e));
+                return;
+            }
+        };
+
+        let plays: Vec<Play> = merge_plays(vec![plays])
+            .into_iter()
+            .filter(|p| !seen.contains(&play_key(p)))
+            .collect();
+
+        let count = plays.len();
+        let mut failed = 0;
+
+        if count > 0 {
+            self.note(format!("scrobbling log: {}", path.to_str().unwrap()));
+        }
+
+        for play in plays {
+            let key = play_key(&play);
+
+            // failed plays stay unseen, so the next change to the log retries them
+            match self.scrobble_track(play).await {
+                Ok(_) => {
+                    seen.insert(key);
+                }
+                Err(e) if self.quiet => {
+                    failed += 1;
+                    eprintln!("{}: {}", stderr_label(Label::Error), e);
+                }
+                Err(e) => {
+                    failed += 1;
+                    println!("  - {}", e);
+                }
+            }
+        }
+
+        if !self.quiet && failed > 0 {
+            println!(
+                "{}: {} tracks submitted, {} failed",
+                label(Label::Summary),
+                count - failed,
+                failed
+            );
+        } else if !self.quiet && count > 0 {
+            println!("{}: {} tracks submitted", label(Label::Success), count);
+        }
+
+        // a log with nothing new was already submitted, maybe before a
+        // restart, so it can go as well
+        let deleted = delete && failed == 0 && self.delete_log(path);
+
+        if self.quiet && (count > 0 || deleted) {
+            let outcome = serde_json::json!({
+                "log": path,
+                "submitted": count - failed,
+                "failed": failed,
+                "deleted": deleted,
+            });
+            println!("{}", outcome);
+        }
+    }
+
+    /// Remove a watched log once it has been submitted, returning whether
+    /// it was removed
+    fn delete_log(&self, path: &Path) -> bool {
+        match std::fs::remove_file(path) {
+            Ok(()) => {
+                self.note(format!("deleted log: {}", path.to_str().unwrap()));
+                true
+            }
+            Err(e) => {
+                self.warn(format!("failed to delete {}: {}", path.display(), e));
+                false
+            }
+        }
+    }
+
     pub async fn scrobble_lines<R>(&self, reader: R) -> Result<(), OnyxError>
     where
-        R: BufRead,
+        R: AsyncBufRead + Unpin,
     {
-        for msg in reader.lines() {
-            let msg = msg?;
+        let mut lines = reader.lines();
+
+        loop {
+            // a client can go quiet for hours, keep the watchdog fed meanwhile
+            let msg = tokio::select! {
+                msg = lines.next_line() => msg?,
+                () = systemd::watchdog_due() => {
+                    systemd::watchdog();
+                    continue;
+                }
+            };
+            let Some(msg) = msg else {
+                break;
+            };
 
             if msg.trim().is_empty() {
                 // skip empty messages
@@ -129,3 +1364,224 @@ impl Scrobbler {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        mock_pds::{DID, MockPds},
+        record::Artist,
+    };
+
+    fn play(name: &str, time: &str) -> Play {
+        Play {
+            track_name: name.to_string(),
+            artists: Some(vec![Artist {
+                artist_name: "Artist".to_string(),
+                artist_mb_id: None,
+            }]),
+            played_time: Some(DateTime::parse_from_rfc3339(time).unwrap()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_merge_plays() {
+        let first = vec![
+            play("Track 3", "2024-02-01T00:00:00Z"),
+            play("Track 1", "2024-01-01T00:00:00Z"),
+        ];
+        let second = vec![
+            play("Track 2", "2024-01-15T00:00:00Z"),
+            play("Track 1", "2024-01-01T00:00:00Z"),
+        ];
+
+        let merged = merge_plays(vec![first, second]);
+
+        assert_eq!(merged.len(), 3);
+        assert_eq!(merged[0].track_name, "Track 1");
+        assert_eq!(merged[1].track_name, "Track 2");
+        assert_eq!(merged[2].track_name, "Track 3");
+    }
+
+    #[test]
+    fn test_artist_filter() {
+        let by = |artist: &str| Play {
+            artists: Some(vec![Artist {
+                artist_name: artist.to_string(),
+                artist_mb_id: None,
+            }]),
+            ..play("Track", "2024-01-01T00:00:00Z")
+        };
+        let legacy = Play {
+            artists: None,
+            artist_names: Some(vec!["Sigur Rós".to_string()]),
+            ..by("")
+        };
+
+        assert!(ArtistFilter::default().allows(&by("Anyone")));
+
+        let exclude = ArtistFilter {
+            include: Vec::new(),
+            exclude: vec!["sigur rÓs".to_string()],
+        };
+        assert!(exclude.allows(&by("Artist")));
+        assert!(!exclude.allows(&by("Sigur Rós")));
+        assert!(!exclude.allows(&legacy));
+
+        let include = ArtistFilter {
+            include: vec!["artist".to_string(), "Other".to_string()],
+            exclude: vec!["Other".to_string()],
+        };
+        assert!(include.allows(&by("Artist")));
+        assert!(!include.allows(&by("Other")));
+        assert!(!include.allows(&by("Someone Else")));
+    }
+
+    #[test]
+    fn test_parse_log_skip_errors() {
+        let path = std::env::temp_dir().join(format!("onyx-maloja-{}.json", std::process::id()));
+        std::fs::write(&path, "{}").unwrap();
+
+        let maloja = parse_log(&path, &LogFormat::Maloja, None, true);
+        let pano = parse_log(&path, &LogFormat::Pano, None, true);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(matches!(maloja, Err(OnyxError::Parse(_))));
+        assert!(matches!(pano, Err(OnyxError::Parse(_))));
+    }
+
+    #[test]
+    fn test_is_future() {
+        let now: DateTime<FixedOffset> = chrono::Local::now().into();
+        let at = |offset| Play {
+            played_time: Some(now + offset),
+            ..Default::default()
+        };
+
+        assert!(!is_future(&at(chrono::Duration::hours(-1))));
+        assert!(!is_future(&at(chrono::Duration::minutes(1))));
+        assert!(is_future(&at(chrono::Duration::hours(1))));
+        assert!(!is_future(&Play::default()));
+    }
+
+    #[test]
+    fn test_import_summary() {
+        let mut legacy = play("Track 3", "2024-01-15T00:00:00Z");
+        legacy.artists = None;
+        legacy.artist_names = Some(vec!["Other".to_string(), "Artist".to_string()]);
+
+        let plays = vec![
+            play("Track 1", "2024-02-01T00:00:00Z"),
+            play("Track 2", "2024-01-01T00:00:00Z"),
+            legacy,
+            Play {
+                played_time: None,
+                ..play("Track 4", "2024-01-01T00:00:00Z")
+            },
+        ];
+
+        let summary = ImportSummary::from_plays(&plays);
+        assert_eq!(
+            summary.top_artists,
+            [("Artist".to_string(), 4), ("Other".to_string(), 1)]
+        );
+
+        let (first, last) = summary.span.unwrap();
+        assert_eq!(first.to_rfc3339(), "2024-01-01T00:00:00+00:00");
+        assert_eq!(last.to_rfc3339(), "2024-02-01T00:00:00+00:00");
+
+        assert_eq!(
+            ImportSummary::from_plays(&[]),
+            ImportSummary {
+                top_artists: Vec::new(),
+                span: None
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_scrobble_repeated() {
+        let pds = MockPds::start().await;
+        let scrobbler = Scrobbler::new("onyx", "test", pds.session().await);
+
+        let track = Play {
+            duration: Some(100),
+            ..play("Track 1", "2024-01-01T00:10:00Z")
+        };
+        let plays = scrobbler.scrobble_repeated(track, 3).await.unwrap();
+
+        let times: Vec<String> = plays
+            .iter()
+            .map(|p| p.played_time.unwrap().to_rfc3339())
+            .collect();
+        assert_eq!(
+            times,
+            [
+                "2024-01-01T00:06:40+00:00",
+                "2024-01-01T00:08:20+00:00",
+                "2024-01-01T00:10:00+00:00"
+            ]
+        );
+        assert_eq!(pds.requests("com.atproto.repo.createRecord").len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_submit_and_delete() {
+        let pds = MockPds::start().await;
+        let scrobbler = Scrobbler::new("onyx", "test", pds.session().await);
+
+        scrobbler
+            .submit(play("Track 1", "2024-01-01T00:00:00Z"))
+            .await
+            .unwrap();
+
+        let created = pds.requests("com.atproto.repo.createRecord");
+        assert_eq!(created.len(), 1);
+        assert_eq!(created[0].body["repo"], DID);
+        assert_eq!(created[0].body["collection"], "fm.teal.alpha.feed.play");
+        assert_eq!(created[0].body["record"]["trackName"], "Track 1");
+        assert_eq!(
+            created[0].body["record"]["submissionClientAgent"],
+            "onyx/test"
+        );
+
+        let records = [
+            PlayRecord {
+                uri: format!("at://{}/fm.teal.alpha.feed.play/1", DID),
+                play: Play::default(),
+            },
+            PlayRecord {
+                uri: format!("at://{}/{}/2", DID, SKIP_COLLECTION),
+                play: Play::default(),
+            },
+        ];
+        assert_eq!(scrobbler.delete_plays(DID, &records).await.unwrap(), 2);
+
+        let writes = pds.requests("com.atproto.repo.applyWrites");
+        assert_eq!(writes.len(), 1);
+        assert_eq!(
+            writes[0].body["writes"][0]["collection"],
+            "fm.teal.alpha.feed.play"
+        );
+        assert_eq!(writes[0].body["writes"][0]["rkey"], "1");
+        assert_eq!(writes[0].body["writes"][1]["collection"], SKIP_COLLECTION);
+    }
+
+    #[tokio::test]
+    async fn test_submit_skip() {
+        let pds = MockPds::start().await;
+        let scrobbler = Scrobbler::new("onyx", "test", pds.session().await);
+
+        let mut skip = play("Track 1", "2024-01-01T00:00:00Z");
+        skip.skipped = Some(true);
+        scrobbler.submit(skip).await.unwrap();
+
+        let created = pds.requests("com.atproto.repo.createRecord");
+        assert_eq!(created.len(), 1);
+        assert_eq!(created[0].body["collection"], SKIP_COLLECTION);
+        assert_eq!(created[0].body["record"]["$type"], SKIP_COLLECTION);
+        assert_eq!(created[0].body["record"]["trackName"], "Track 1");
+        assert!(created[0].body["record"].get("skipped").is_none());
+    }
+}
@@ -1,94 +1,218 @@
 use chrono::{DateTime, Duration, FixedOffset};
 use jacquard::{
-    client::{Agent, AgentSessionExt, BasicClient},
-    prelude::IdentityResolver,
-    types::{aturi::AtUri, did::Did, string::Handle},
+    client::Agent,
+    prelude::{IdentityResolver, XrpcClient, XrpcExt},
+    smol_str::SmolStr,
+    types::{
+        cid::Cid,
+        collection::Collection,
+        did::Did,
+        ident::AtIdentifier,
+        nsid::Nsid,
+        recordkey::RecordKey,
+        string::AtprotoStr,
+        value::{Data, to_data},
+    },
+    xrpc::XrpcError,
 };
-use jacquard_api::fm_teal::alpha::actor::status as fm_teal_status;
-use jacquard_identity::{JacquardResolver, PublicResolver};
+use jacquard_api::{
+    com_atproto::repo::{
+        get_record::GetRecord,
+        put_record::{PutRecord, PutRecordError},
+    },
+    fm_teal::alpha::actor::status as fm_teal_status,
+};
+use jacquard_identity::JacquardResolver;
 
 use crate::{
     auth::GenericSession,
     error::OnyxError,
+    http::HttpOptions,
+    identity::{self, HandleCache},
+    metrics,
     record::{PlayView, Status},
+    systemd,
 };
 
-fn get_status_endpoint(did: String) -> String {
-    format!("at://{}/fm.teal.alpha.actor.status/self", did)
+/// Longest wait between keep-alive refreshes
+const KEEP_ALIVE_INTERVAL: Duration = Duration::minutes(5);
+
+/// Expiry given to each keep-alive refresh, longer than the interval so the
+/// status never lapses between refreshes
+const KEEP_ALIVE_EXPIRY: Duration = Duration::minutes(10);
+
+/// Record key of the status record, there is one per account
+const STATUS_RKEY: &str = "self";
+
+/// Shortest wait between keep-alive refreshes, so a status that is about to
+/// lapse doesn't refresh in a busy loop
+const KEEP_ALIVE_MIN_WAIT: Duration = Duration::seconds(1);
+
+/// How long to wait before refreshing a status that expires at `expiry`,
+/// half the time it has left, so it's refreshed before it lapses however
+/// short its expiry
+fn keep_alive_wait(expiry: Option<DateTime<FixedOffset>>, now: DateTime<FixedOffset>) -> Duration {
+    match expiry {
+        Some(expiry) => ((expiry - now) / 2).clamp(KEEP_ALIVE_MIN_WAIT, KEEP_ALIVE_INTERVAL),
+        None => KEEP_ALIVE_INTERVAL,
+    }
 }
 
 pub struct StatusManager {
     pub ident: String,
 
+    http: HttpOptions,
     resolver: JacquardResolver,
+    handle_cache: HandleCache,
+    collection: Nsid<'static>,
+    rkey: String,
+    session: Option<GenericSession>,
 }
 
 impl StatusManager {
-    pub fn new(ident: &str) -> Self {
-        Self {
+    pub fn try_new(
+        ident: &str,
+        http: HttpOptions,
+        handle_cache: HandleCache,
+    ) -> Result<Self, OnyxError> {
+        Ok(Self {
             ident: ident.to_owned(),
-            resolver: PublicResolver::default(),
-        }
+            resolver: http.build_resolver()?,
+            http,
+            handle_cache,
+            collection: fm_teal_status::Status::nsid(),
+            rkey: STATUS_RKEY.to_string(),
+            session: None,
+        })
+    }
+
+    /// Read through the logged in session, for the session's own status
+    pub fn with_session(mut self, session: Option<GenericSession>) -> Self {
+        self.session = session;
+        self
+    }
+
+    /// Give back the session set with `with_session`, to write with once
+    /// the reads are done
+    pub fn into_session(self) -> Option<GenericSession> {
+        self.session
     }
 
-    async fn resolve_did(&self, ident: &str) -> Result<Did<'_>, OnyxError> {
-        if let Ok(did) = ident.parse() {
-            return Ok(did);
+    /// Read and write the status at a different collection or record key,
+    /// for trying other versions of the status lexicon
+    pub fn with_record(mut self, collection: Option<Nsid<'static>>, rkey: Option<String>) -> Self {
+        if let Some(collection) = collection {
+            self.collection = collection;
+        }
+
+        if let Some(rkey) = rkey {
+            self.rkey = rkey;
         }
 
-        let handle = Handle::new(ident)?;
-        let did = self.resolver.resolve_handle(&handle).await?;
-        Ok(did)
+        self
+    }
+
+    async fn resolve_did(&self, ident: &str) -> Result<Did<'static>, OnyxError> {
+        identity::resolve_did(
+            &self.resolver,
+            &self.handle_cache,
+            ident,
+            self.http.resolve_retries,
+        )
+        .await
     }
 
     pub async fn get_status(&self) -> Result<Status, OnyxError> {
-        let did = self.resolve_did(&self.ident).await?;
+        Ok(self.get_status_record().await?.0)
+    }
 
-        let endpoint = get_status_endpoint(did.to_string());
+    /// Read the status along with the CID of its record, to pass back to
+    /// `set_status` when writing a change based on it
+    pub async fn get_status_record(&self) -> Result<(Status, Option<Cid<'static>>), OnyxError> {
+        let did = self.resolve_did(&self.ident).await?;
 
-        let uri = fm_teal_status::Status::uri(&endpoint)?;
-        let agent = BasicClient::unauthenticated();
+        let request = GetRecord::new()
+            .repo(AtIdentifier::Did(did.clone()))
+            .collection(self.collection.clone())
+            .rkey(RecordKey::any(&self.rkey)?)
+            .build();
 
-        let response = agent
-            .get_record::<fm_teal_status::StatusRecord>(&uri)
-            .await?;
+        // a session already knows its PDS, so only resolve it for public reads
+        let response = match &self.session {
+            Some(session) => session.send(request).await?,
+            None => {
+                let pds = self.resolver.pds_for_did(&did).await?;
+                self.http.build_client()?.xrpc(pds).send(&request).await?
+            }
+        };
 
-        let status_rec = response
+        let output = response
             .into_output()
-            .map_err(|e| OnyxError::Other(e.to_string().into()))?
-            .value;
+            .map_err(|e| OnyxError::Other(e.to_string().into()))?;
 
-        Ok(status_rec.into())
+        Ok((Status::try_from(&output.value)?, output.cid))
     }
 
+    /// Write the status, returning the new record's CID. With `swap`, the
+    /// write only goes through if the record is still at that CID, so an
+    /// update made elsewhere since it was read fails with
+    /// `OnyxError::Conflict` instead of being overwritten
     pub async fn set_status(
         &self,
-        session: GenericSession,
+        agent: &Agent<GenericSession>,
         status: Status,
-    ) -> Result<(), OnyxError> {
+        swap: Option<Cid<'static>>,
+    ) -> Result<Cid<'static>, OnyxError> {
         let did = self.resolve_did(&self.ident).await?;
-        let endpoint = get_status_endpoint(did.to_string());
-        let uri = AtUri::new(&endpoint)?;
-
-        let agent = Agent::from(session);
-        agent
-            .update_record::<fm_teal_status::Status>(&uri, |stat| {
-                let status: fm_teal_status::Status = status.into();
-                stat.time = status.time;
-                stat.expiry = status.expiry;
-                stat.item = status.item;
-            })
-            .await?;
+        let (track_name, artists) = (status.item.track_name.clone(), status.item.artists.clone());
 
-        Ok(())
+        let status: fm_teal_status::Status = status.into();
+        let mut record =
+            to_data(&status).map_err(|e| OnyxError::Parse(format!("invalid status: {}", e)))?;
+
+        // written through a plain putRecord so the collection can differ
+        // from the lexicon's, which means adding the type by hand
+        if let Data::Object(object) = &mut record {
+            object.0.insert(
+                SmolStr::new_static("$type"),
+                Data::String(AtprotoStr::Nsid(self.collection.clone())),
+            );
+        }
+
+        let request = PutRecord::new()
+            .repo(AtIdentifier::Did(did))
+            .collection(self.collection.clone())
+            .rkey(RecordKey::any(&self.rkey)?)
+            .record(record)
+            .maybe_swap_record(swap)
+            .build();
+
+        let output = agent
+            .send(request)
+            .await?
+            .into_output()
+            .map_err(|e| match e {
+                XrpcError::Xrpc(PutRecordError::InvalidSwap(_)) => OnyxError::Conflict(
+                    "status was changed elsewhere since it was read".to_string(),
+                ),
+                e => OnyxError::Other(e.to_string().into()),
+            })?;
+
+        metrics::status_updated(&track_name, &artists);
+
+        Ok(output.cid)
     }
 
-    pub async fn clear_status(&self, session: GenericSession) -> Result<(), OnyxError> {
+    pub async fn clear_status(
+        &self,
+        agent: &Agent<GenericSession>,
+        swap: Option<Cid<'static>>,
+    ) -> Result<(), OnyxError> {
         let now: DateTime<FixedOffset> = chrono::Local::now().into();
         let expiry = now - Duration::minutes(1);
 
         self.set_status(
-            session,
+            agent,
             Status {
                 time: now,
                 expiry: Some(expiry),
@@ -98,7 +222,147 @@ impl StatusManager {
                     ..Default::default()
                 },
             },
+            swap,
         )
-        .await
+        .await?;
+
+        Ok(())
+    }
+
+    /// Periodically re-push `status` with a fresh expiry until `end`, or until
+    /// interrupted with Ctrl-C, in which case the status is cleared. `cid` is
+    /// that of the record as last written, so a status set elsewhere in the
+    /// meantime stops the loop with `OnyxError::Conflict` rather than being
+    /// overwritten
+    pub async fn keep_alive(
+        &self,
+        agent: &Agent<GenericSession>,
+        mut status: Status,
+        end: Option<DateTime<FixedOffset>>,
+        mut cid: Cid<'static>,
+    ) -> Result<(), OnyxError> {
+        systemd::ready();
+
+        loop {
+            let now: DateTime<FixedOffset> = chrono::Local::now().into();
+            let mut wait = keep_alive_wait(status.expiry, now);
+
+            if let Some(end) = end {
+                if now >= end {
+                    return Ok(());
+                }
+
+                wait = wait.min(end - now);
+            }
+
+            tokio::select! {
+                _ = systemd::sleep(wait.to_std().unwrap_or_default()) => {}
+                _ = tokio::signal::ctrl_c() => {
+                    systemd::stopping();
+                    return self.clear_status(agent, Some(cid)).await;
+                }
+            }
+
+            let now: DateTime<FixedOffset> = chrono::Local::now().into();
+            if end.is_some_and(|end| now >= end) {
+                return Ok(());
+            }
+
+            let expiry = now + KEEP_ALIVE_EXPIRY;
+            status.expiry = Some(end.map_or(expiry, |end| expiry.min(end)));
+            cid = self.set_status(agent, status.clone(), Some(cid)).await?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        mock_pds::{DID, MockPds},
+        record::Artist,
+    };
+
+    #[test]
+    fn test_keep_alive_wait() {
+        let now: DateTime<FixedOffset> = chrono::Local::now().into();
+
+        assert_eq!(keep_alive_wait(None, now), KEEP_ALIVE_INTERVAL);
+        assert_eq!(
+            keep_alive_wait(Some(now + Duration::minutes(2)), now),
+            Duration::minutes(1)
+        );
+        assert_eq!(
+            keep_alive_wait(Some(now + Duration::hours(1)), now),
+            KEEP_ALIVE_INTERVAL
+        );
+        assert_eq!(
+            keep_alive_wait(Some(now - Duration::minutes(1)), now),
+            KEEP_ALIVE_MIN_WAIT
+        );
+    }
+
+    #[tokio::test]
+    async fn test_set_and_get_status() {
+        let pds = MockPds::start().await;
+        let manager = StatusManager::try_new(DID, HttpOptions::default(), HandleCache::disabled())
+            .unwrap()
+            .with_session(Some(pds.session().await));
+
+        // nothing set yet
+        assert!(manager.get_status().await.is_err());
+
+        let now: DateTime<FixedOffset> = chrono::Local::now().into();
+        let status = Status {
+            time: now,
+            expiry: Some(now + Duration::minutes(10)),
+            item: PlayView {
+                track_name: "Track 1".to_string(),
+                artists: vec![Artist {
+                    artist_name: "Artist 1".to_string(),
+                    artist_mb_id: None,
+                }],
+                ..Default::default()
+            },
+        };
+        let agent = Agent::from(pds.session().await);
+        let cid = manager.set_status(&agent, status, None).await.unwrap();
+
+        let puts = pds.requests("com.atproto.repo.putRecord");
+        assert_eq!(puts.len(), 1);
+        assert_eq!(puts[0].body["collection"], "fm.teal.alpha.actor.status");
+        assert_eq!(puts[0].body["rkey"], STATUS_RKEY);
+        assert_eq!(
+            puts[0].body["record"]["$type"],
+            "fm.teal.alpha.actor.status"
+        );
+
+        let (status, read_cid) = manager.get_status_record().await.unwrap();
+        assert_eq!(status.item.track_name, "Track 1");
+        assert_eq!(status.item.artists[0].artist_name, "Artist 1");
+        assert_eq!(read_cid, Some(cid.clone()));
+
+        // a write based on the status as read goes through
+        let mut next = status.clone();
+        next.item.track_name = "Track 2".to_string();
+        let swapped = manager
+            .set_status(&agent, next.clone(), Some(cid.clone()))
+            .await
+            .unwrap();
+        assert_eq!(
+            pds.requests("com.atproto.repo.putRecord")[1].body["swapRecord"],
+            cid.as_str()
+        );
+
+        // one based on the now stale CID conflicts and changes nothing
+        next.item.track_name = "Track 3".to_string();
+        assert!(matches!(
+            manager.set_status(&agent, next, Some(cid)).await,
+            Err(OnyxError::Conflict(_))
+        ));
+
+        let (status, read_cid) = manager.get_status_record().await.unwrap();
+        assert_eq!(status.item.track_name, "Track 2");
+        assert_eq!(read_cid, Some(swapped));
     }
 }
@@ -0,0 +1,68 @@
+//! systemd service notifications, so long-running commands can run as
+//! `Type=notify` units with `WatchdogSec=` set. Outside systemd these do
+//! nothing.
+
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Tell systemd the service has finished starting up
+pub fn ready() {
+    // notifications are best effort, a unit without them still runs
+    #[cfg(unix)]
+    let _ = sd_notify::notify(false, &[sd_notify::NotifyState::Ready]);
+}
+
+/// Tell systemd the service is shutting down
+pub fn stopping() {
+    #[cfg(unix)]
+    let _ = sd_notify::notify(false, &[sd_notify::NotifyState::Stopping]);
+}
+
+/// Reset the watchdog timer, if the unit has one
+pub fn watchdog() {
+    #[cfg(unix)]
+    if watchdog_interval().is_some() {
+        let _ = sd_notify::notify(false, &[sd_notify::NotifyState::Watchdog]);
+    }
+}
+
+/// How often the watchdog needs resetting, half the unit's `WatchdogSec=` as
+/// systemd recommends, or `None` without a watchdog
+pub fn watchdog_interval() -> Option<Duration> {
+    #[cfg(unix)]
+    {
+        let mut usec = 0;
+        sd_notify::watchdog_enabled(false, &mut usec).then(|| Duration::from_micros(usec / 2))
+    }
+
+    #[cfg(not(unix))]
+    None
+}
+
+/// Sleep for `duration`, waking as often as needed to reset the watchdog.
+/// Anything that hangs between sleeps still starves it.
+pub async fn sleep(duration: Duration) {
+    let deadline = Instant::now() + duration;
+    let step = watchdog_interval();
+
+    loop {
+        watchdog();
+
+        let now = Instant::now();
+        if now >= deadline {
+            return;
+        }
+
+        let next = step.map_or(deadline, |step| (now + step).min(deadline));
+        tokio::time::sleep_until(next).await;
+    }
+}
+
+/// Resolve once the watchdog is next due a reset, or never without one. For
+/// racing against waits with no deadline, like accepting a connection.
+pub async fn watchdog_due() {
+    match watchdog_interval() {
+        Some(interval) => tokio::time::sleep(interval).await,
+        None => std::future::pending().await,
+    }
+}